@@ -0,0 +1,48 @@
+pub use sea_orm_migration::prelude::*;
+
+mod m20240101_000001_create_users;
+mod m20240101_000002_create_messages;
+mod m20240101_000003_create_attachments;
+mod m20240101_000004_create_message_search_index;
+mod m20240201_000001_create_user_blocks;
+mod m20240215_000001_create_moderation;
+mod m20240301_000001_add_user_roles;
+mod m20240315_000001_create_audit_events;
+mod m20240401_000001_account_deletion_and_exports;
+mod m20240415_000001_disappearing_messages;
+mod m20240501_000001_create_scheduled_messages;
+mod m20240515_000001_create_user_events;
+mod m20240601_000001_create_bots_and_api_keys;
+mod m20240615_000001_create_webhooks;
+mod m20240701_000001_add_user_events_message_id;
+mod m20240705_000001_add_attachment_claimed_at;
+mod m20240710_000001_add_data_export_claimed_at;
+mod m20240715_000001_backfill_bot_phone_numbers;
+
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20240101_000001_create_users::Migration),
+            Box::new(m20240101_000002_create_messages::Migration),
+            Box::new(m20240101_000003_create_attachments::Migration),
+            Box::new(m20240101_000004_create_message_search_index::Migration),
+            Box::new(m20240201_000001_create_user_blocks::Migration),
+            Box::new(m20240215_000001_create_moderation::Migration),
+            Box::new(m20240301_000001_add_user_roles::Migration),
+            Box::new(m20240315_000001_create_audit_events::Migration),
+            Box::new(m20240401_000001_account_deletion_and_exports::Migration),
+            Box::new(m20240415_000001_disappearing_messages::Migration),
+            Box::new(m20240501_000001_create_scheduled_messages::Migration),
+            Box::new(m20240515_000001_create_user_events::Migration),
+            Box::new(m20240601_000001_create_bots_and_api_keys::Migration),
+            Box::new(m20240615_000001_create_webhooks::Migration),
+            Box::new(m20240701_000001_add_user_events_message_id::Migration),
+            Box::new(m20240705_000001_add_attachment_claimed_at::Migration),
+            Box::new(m20240710_000001_add_data_export_claimed_at::Migration),
+            Box::new(m20240715_000001_backfill_bot_phone_numbers::Migration),
+        ]
+    }
+}
@@ -0,0 +1,103 @@
+use sea_orm_migration::prelude::*;
+
+/// Baseline `messages` table, see `m20240101_000001_create_users`.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Messages::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Messages::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Messages::SenderId).integer().not_null())
+                    .col(ColumnDef::new(Messages::ReceiverId).integer().not_null())
+                    .col(ColumnDef::new(Messages::Message).text().not_null())
+                    .col(
+                        ColumnDef::new(Messages::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(Messages::Status)
+                            .string()
+                            .not_null()
+                            .default("unread"),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_messages_sender")
+                            .from(Messages::Table, Messages::SenderId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_messages_receiver")
+                            .from(Messages::Table, Messages::ReceiverId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Conversation history, newest first, in either direction
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_messages_sender_receiver_created")
+                    .table(Messages::Table)
+                    .col(Messages::SenderId)
+                    .col(Messages::ReceiverId)
+                    .col(Messages::CreatedAt)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_messages_receiver_status")
+                    .table(Messages::Table)
+                    .col(Messages::ReceiverId)
+                    .col(Messages::Status)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Messages::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Messages {
+    Table,
+    Id,
+    SenderId,
+    ReceiverId,
+    Message,
+    CreatedAt,
+    Status,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
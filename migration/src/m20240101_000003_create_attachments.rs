@@ -0,0 +1,145 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Attachments::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Attachments::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    // Null until the upload is sent as part of a message
+                    .col(ColumnDef::new(Attachments::MessageId).integer())
+                    .col(ColumnDef::new(Attachments::UploaderId).integer().not_null())
+                    .col(ColumnDef::new(Attachments::FileName).string().not_null())
+                    .col(ColumnDef::new(Attachments::ContentType).string().not_null())
+                    .col(
+                        ColumnDef::new(Attachments::SizeBytes)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Attachments::StorageKey)
+                            .string()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Attachments::Width).integer())
+                    .col(ColumnDef::new(Attachments::Height).integer())
+                    .col(ColumnDef::new(Attachments::Blurhash).string())
+                    .col(ColumnDef::new(Attachments::ThumbnailKey).string())
+                    .col(ColumnDef::new(Attachments::DurationMs).integer())
+                    .col(ColumnDef::new(Attachments::Waveform).json_binary())
+                    .col(
+                        ColumnDef::new(Attachments::ProcessingStatus)
+                            .string()
+                            .not_null()
+                            .default("pending"),
+                    )
+                    .col(
+                        ColumnDef::new(Attachments::ProcessingAttempts)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(Attachments::ProcessingError).text())
+                    .col(ColumnDef::new(Attachments::NextAttemptAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(Attachments::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_attachments_message")
+                            .from(Attachments::Table, Attachments::MessageId)
+                            .to(Messages::Table, Messages::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_attachments_uploader")
+                            .from(Attachments::Table, Attachments::UploaderId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_attachments_message")
+                    .table(Attachments::Table)
+                    .col(Attachments::MessageId)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+        // Media worker queue scan
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_attachments_processing_queue")
+                    .table(Attachments::Table)
+                    .col(Attachments::ProcessingStatus)
+                    .col(Attachments::NextAttemptAt)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Attachments::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Attachments {
+    Table,
+    Id,
+    MessageId,
+    UploaderId,
+    FileName,
+    ContentType,
+    SizeBytes,
+    StorageKey,
+    Width,
+    Height,
+    Blurhash,
+    ThumbnailKey,
+    DurationMs,
+    Waveform,
+    ProcessingStatus,
+    ProcessingAttempts,
+    ProcessingError,
+    NextAttemptAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Messages {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
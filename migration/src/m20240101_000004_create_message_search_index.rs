@@ -0,0 +1,28 @@
+use sea_orm_migration::prelude::*;
+
+/// GIN index backing `/search`. The expression, including the `simple` text
+/// search configuration, must match `search::SEARCH_CONFIG` in the server.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "CREATE INDEX IF NOT EXISTS idx_messages_message_fts ON messages \
+                 USING GIN (to_tsvector('simple', message))",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared("DROP INDEX IF EXISTS idx_messages_message_fts")
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+use sea_orm_migration::prelude::*;
+
+/// Reports, the moderation action log and account suspensions.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(Users::SuspendedUntil).timestamp_with_time_zone(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Reports::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Reports::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Reports::ReporterId).integer().not_null())
+                    .col(ColumnDef::new(Reports::ReportedUserId).integer().not_null())
+                    .col(ColumnDef::new(Reports::MessageId).integer())
+                    .col(ColumnDef::new(Reports::MessageSnapshot).text())
+                    .col(ColumnDef::new(Reports::Reason).string().not_null())
+                    .col(ColumnDef::new(Reports::Details).text())
+                    .col(
+                        ColumnDef::new(Reports::Status)
+                            .string()
+                            .not_null()
+                            .default("open"),
+                    )
+                    .col(ColumnDef::new(Reports::ResolutionNote).text())
+                    .col(ColumnDef::new(Reports::ResolvedBy).integer())
+                    .col(ColumnDef::new(Reports::ResolvedAt).timestamp_with_time_zone())
+                    .col(
+                        ColumnDef::new(Reports::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_reports_reporter")
+                            .from(Reports::Table, Reports::ReporterId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_reports_reported_user")
+                            .from(Reports::Table, Reports::ReportedUserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    // The snapshot keeps the report meaningful once the message is gone
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_reports_message")
+                            .from(Reports::Table, Reports::MessageId)
+                            .to(Messages::Table, Messages::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_reports_resolved_by")
+                            .from(Reports::Table, Reports::ResolvedBy)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_reports_status_created")
+                    .table(Reports::Table)
+                    .col(Reports::Status)
+                    .col(Reports::CreatedAt)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_reports_reported_user")
+                    .table(Reports::Table)
+                    .col(Reports::ReportedUserId)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ModerationActions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ModerationActions::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ModerationActions::ModeratorId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ModerationActions::Action)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ModerationActions::ReportId).integer())
+                    .col(ColumnDef::new(ModerationActions::TargetUserId).integer())
+                    .col(ColumnDef::new(ModerationActions::TargetMessageId).integer())
+                    .col(ColumnDef::new(ModerationActions::Note).text())
+                    .col(
+                        ColumnDef::new(ModerationActions::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_moderation_actions_moderator")
+                            .from(ModerationActions::Table, ModerationActions::ModeratorId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_moderation_actions_report")
+                            .from(ModerationActions::Table, ModerationActions::ReportId)
+                            .to(Reports::Table, Reports::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_moderation_actions_target_user")
+                            .from(ModerationActions::Table, ModerationActions::TargetUserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_moderation_actions_target_message")
+                            .from(ModerationActions::Table, ModerationActions::TargetMessageId)
+                            .to(Messages::Table, Messages::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_moderation_actions_created")
+                    .table(ModerationActions::Table)
+                    .col(ModerationActions::CreatedAt)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ModerationActions::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Reports::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::SuspendedUntil)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Reports {
+    Table,
+    Id,
+    ReporterId,
+    ReportedUserId,
+    MessageId,
+    MessageSnapshot,
+    Reason,
+    Details,
+    Status,
+    ResolutionNote,
+    ResolvedBy,
+    ResolvedAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum ModerationActions {
+    Table,
+    Id,
+    ModeratorId,
+    Action,
+    ReportId,
+    TargetUserId,
+    TargetMessageId,
+    Note,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+    SuspendedUntil,
+}
+
+#[derive(DeriveIden)]
+enum Messages {
+    Table,
+    Id,
+}
@@ -0,0 +1,91 @@
+use sea_orm_migration::prelude::*;
+
+/// Hash-chained audit log. Deliberately has no foreign keys: entries must
+/// outlive the users they mention and are never updated.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditEvents::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuditEvents::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AuditEvents::EventType).string().not_null())
+                    .col(ColumnDef::new(AuditEvents::ActorId).integer())
+                    .col(ColumnDef::new(AuditEvents::TargetUserId).integer())
+                    .col(ColumnDef::new(AuditEvents::IpAddress).string())
+                    .col(
+                        ColumnDef::new(AuditEvents::Details)
+                            .json_binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AuditEvents::PrevHash)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(AuditEvents::Hash)
+                            .string_len(64)
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AuditEvents::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        for (name, column) in [
+            ("idx_audit_events_event_type", AuditEvents::EventType),
+            ("idx_audit_events_actor", AuditEvents::ActorId),
+            ("idx_audit_events_target_user", AuditEvents::TargetUserId),
+            ("idx_audit_events_created", AuditEvents::CreatedAt),
+        ] {
+            manager
+                .create_index(
+                    Index::create()
+                        .name(name)
+                        .table(AuditEvents::Table)
+                        .col(column)
+                        .if_not_exists()
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuditEvents::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AuditEvents {
+    Table,
+    Id,
+    EventType,
+    ActorId,
+    TargetUserId,
+    IpAddress,
+    Details,
+    PrevHash,
+    Hash,
+    CreatedAt,
+}
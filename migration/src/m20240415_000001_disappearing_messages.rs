@@ -0,0 +1,147 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Messages::Table)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(Messages::ExpiresAt).timestamp_with_time_zone(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // Expiry sweeper scan
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_messages_expires_at")
+                    .table(Messages::Table)
+                    .col(Messages::ExpiresAt)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ConversationSettings::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ConversationSettings::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ConversationSettings::UserLowId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ConversationSettings::UserHighId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ConversationSettings::DisappearingSeconds).big_integer())
+                    .col(ColumnDef::new(ConversationSettings::UpdatedBy).integer())
+                    .col(
+                        ColumnDef::new(ConversationSettings::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_conversation_settings_user_low")
+                            .from(ConversationSettings::Table, ConversationSettings::UserLowId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_conversation_settings_user_high")
+                            .from(
+                                ConversationSettings::Table,
+                                ConversationSettings::UserHighId,
+                            )
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_conversation_settings_updated_by")
+                            .from(ConversationSettings::Table, ConversationSettings::UpdatedBy)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_conversation_settings_pair")
+                    .table(ConversationSettings::Table)
+                    .col(ConversationSettings::UserLowId)
+                    .col(ConversationSettings::UserHighId)
+                    .unique()
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ConversationSettings::Table).to_owned())
+            .await?;
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_messages_expires_at")
+                    .table(Messages::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Messages::Table)
+                    .drop_column(Messages::ExpiresAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ConversationSettings {
+    Table,
+    Id,
+    UserLowId,
+    UserHighId,
+    DisappearingSeconds,
+    UpdatedBy,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Messages {
+    Table,
+    ExpiresAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
@@ -0,0 +1,141 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ScheduledMessages::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ScheduledMessages::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ScheduledMessages::SenderId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ScheduledMessages::RecipientId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(ScheduledMessages::Message).text().not_null())
+                    .col(
+                        ColumnDef::new(ScheduledMessages::SendAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(ScheduledMessages::Status)
+                            .string()
+                            .not_null()
+                            .default("pending"),
+                    )
+                    .col(ColumnDef::new(ScheduledMessages::ClaimedAt).timestamp_with_time_zone())
+                    .col(ColumnDef::new(ScheduledMessages::MessageId).integer())
+                    .col(ColumnDef::new(ScheduledMessages::Error).text())
+                    .col(
+                        ColumnDef::new(ScheduledMessages::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(ScheduledMessages::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_scheduled_messages_sender")
+                            .from(ScheduledMessages::Table, ScheduledMessages::SenderId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_scheduled_messages_recipient")
+                            .from(ScheduledMessages::Table, ScheduledMessages::RecipientId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    // The delivered message may expire long before the schedule row
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_scheduled_messages_message")
+                            .from(ScheduledMessages::Table, ScheduledMessages::MessageId)
+                            .to(Messages::Table, Messages::Id)
+                            .on_delete(ForeignKeyAction::SetNull),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Scheduler due-row scan
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_scheduled_messages_status_send_at")
+                    .table(ScheduledMessages::Table)
+                    .col(ScheduledMessages::Status)
+                    .col(ScheduledMessages::SendAt)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_scheduled_messages_sender")
+                    .table(ScheduledMessages::Table)
+                    .col(ScheduledMessages::SenderId)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ScheduledMessages::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ScheduledMessages {
+    Table,
+    Id,
+    SenderId,
+    RecipientId,
+    Message,
+    SendAt,
+    Status,
+    ClaimedAt,
+    MessageId,
+    Error,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Messages {
+    Table,
+    Id,
+}
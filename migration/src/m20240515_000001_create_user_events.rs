@@ -0,0 +1,114 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Last sequence number handed out for the user's event log
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(Users::EventSeq)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserEvents::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserEvents::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(UserEvents::UserId).integer().not_null())
+                    .col(ColumnDef::new(UserEvents::Seq).big_integer().not_null())
+                    .col(ColumnDef::new(UserEvents::Kind).string().not_null())
+                    .col(ColumnDef::new(UserEvents::Frame).text().not_null())
+                    .col(
+                        ColumnDef::new(UserEvents::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_user_events_user")
+                            .from(UserEvents::Table, UserEvents::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Replay scan, and keeps sequence numbers unique per user
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_events_user_seq")
+                    .table(UserEvents::Table)
+                    .col(UserEvents::UserId)
+                    .col(UserEvents::Seq)
+                    .unique()
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+        // Pruning by age
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_events_created_at")
+                    .table(UserEvents::Table)
+                    .col(UserEvents::CreatedAt)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserEvents::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::EventSeq)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserEvents {
+    Table,
+    Id,
+    UserId,
+    Seq,
+    Kind,
+    Frame,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+    EventSeq,
+}
@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // The message a logged frame carries, so its copies can be redacted
+        // when the message is removed, expires or is deleted with its sender
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserEvents::Table)
+                    .add_column_if_not_exists(ColumnDef::new(UserEvents::MessageId).integer())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_events_message_id")
+                    .table(UserEvents::Table)
+                    .col(UserEvents::MessageId)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_user_events_message_id")
+                    .table(UserEvents::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserEvents::Table)
+                    .drop_column(UserEvents::MessageId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserEvents {
+    Table,
+    MessageId,
+}
@@ -0,0 +1,39 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // When an export worker took the job, so a claim left behind by a
+        // dead instance can be handed back
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(DataExports::Table)
+                    .add_column_if_not_exists(
+                        ColumnDef::new(DataExports::ClaimedAt).timestamp_with_time_zone(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(DataExports::Table)
+                    .drop_column(DataExports::ClaimedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum DataExports {
+    Table,
+    ClaimedAt,
+}
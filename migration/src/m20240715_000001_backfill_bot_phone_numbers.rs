@@ -0,0 +1,25 @@
+use sea_orm_migration::prelude::*;
+
+/// Bots used to be created with an empty `phone_number`. Give them the
+/// `bot-<id>` placeholder new bots get.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                "UPDATE users SET phone_number = 'bot-' || id \
+                 WHERE is_bot AND phone_number = ''",
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // The placeholders are harmless to keep
+        Ok(())
+    }
+}
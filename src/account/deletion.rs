@@ -0,0 +1,196 @@
+//! Account deletion. The `users` row is kept but stripped of anything
+//! identifying, so foreign keys from the other side of conversations, reports
+//! and the audit log stay valid.
+
+use crate::account::types::{AccountDeletedFrame, DeletionPolicy};
+use crate::attachments::{delete_blobs, StorageBackend};
+use crate::auth::Role;
+use crate::entity::{
+    api_keys, attachments, data_exports, messages, scheduled_messages, user_blocks, user_events,
+    users, ApiKeys, Attachments, DataExports, Messages, ScheduledMessages, UserBlocks, UserEvents,
+    Users,
+};
+use crate::scheduled;
+use crate::sync::{redact_messages, SyncEvent};
+use crate::webhooks::types::MessagesDeletedData;
+use crate::webhooks::{WebhookEvent, MESSAGE_DELETED};
+use crate::ws::{disconnect_user, SharedState};
+use chrono::Utc;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, DbErr,
+    EntityTrait, QueryFilter, QuerySelect, Set, TransactionTrait,
+};
+use std::collections::HashSet;
+use tracing::{info, warn};
+
+/// What a deletion removed, for the audit log.
+pub struct DeletionSummary {
+    pub messages_deleted: u64,
+    pub attachments_deleted: usize,
+}
+
+/// Placeholder username and phone number for a deleted account.
+pub fn tombstone_name(user_id: i32) -> String {
+    format!("deleted-{}", user_id)
+}
+
+pub async fn delete_account(
+    db: &DatabaseConnection,
+    storage: &dyn StorageBackend,
+    online: &SharedState,
+    user: users::Model,
+    policy: DeletionPolicy,
+) -> Result<DeletionSummary, DbErr> {
+    let partner_ids: HashSet<i32> = Messages::find()
+        .select_only()
+        .column(messages::Column::SenderId)
+        .column(messages::Column::ReceiverId)
+        .distinct()
+        .filter(
+            Condition::any()
+                .add(messages::Column::SenderId.eq(user.id))
+                .add(messages::Column::ReceiverId.eq(user.id)),
+        )
+        .into_tuple::<(i32, i32)>()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|(sender_id, receiver_id)| {
+            if sender_id == user.id {
+                receiver_id
+            } else {
+                sender_id
+            }
+        })
+        .collect();
+
+    // Uploads never attached to a message are always dropped; with the
+    // delete policy so is everything attached to the user's sent messages
+    let mut doomed = Condition::any().add(
+        Condition::all()
+            .add(attachments::Column::UploaderId.eq(user.id))
+            .add(attachments::Column::MessageId.is_null()),
+    );
+    let sent_ids: Vec<i32> = if policy == DeletionPolicy::Delete {
+        Messages::find()
+            .select_only()
+            .column(messages::Column::Id)
+            .filter(messages::Column::SenderId.eq(user.id))
+            .into_tuple()
+            .all(db)
+            .await?
+    } else {
+        Vec::new()
+    };
+    for chunk in sent_ids.chunks(1000) {
+        doomed = doomed.add(attachments::Column::MessageId.is_in(chunk.to_vec()));
+    }
+    let doomed_attachments = Attachments::find().filter(doomed).all(db).await?;
+    let exports = DataExports::find()
+        .filter(data_exports::Column::UserId.eq(user.id))
+        .all(db)
+        .await?;
+
+    let txn = db.begin().await?;
+    Attachments::delete_many()
+        .filter(attachments::Column::Id.is_in(doomed_attachments.iter().map(|a| a.id)))
+        .exec(&txn)
+        .await?;
+    let messages_deleted = if policy == DeletionPolicy::Delete {
+        Messages::delete_many()
+            .filter(messages::Column::SenderId.eq(user.id))
+            .exec(&txn)
+            .await?
+            .rows_affected
+    } else {
+        0
+    };
+    UserBlocks::delete_many()
+        .filter(
+            Condition::any()
+                .add(user_blocks::Column::BlockerId.eq(user.id))
+                .add(user_blocks::Column::BlockedId.eq(user.id)),
+        )
+        .exec(&txn)
+        .await?;
+    DataExports::delete_many()
+        .filter(data_exports::Column::UserId.eq(user.id))
+        .exec(&txn)
+        .await?;
+    // A deleted bot must not keep authenticating
+    ApiKeys::delete_many()
+        .filter(api_keys::Column::UserId.eq(user.id))
+        .exec(&txn)
+        .await?;
+    // Their event log holds copies of messages, and so do their partners'
+    UserEvents::delete_many()
+        .filter(user_events::Column::UserId.eq(user.id))
+        .exec(&txn)
+        .await?;
+    redact_messages(&txn, &sent_ids).await?;
+    ScheduledMessages::update_many()
+        .col_expr(
+            scheduled_messages::Column::Status,
+            Expr::value(scheduled::STATUS_CANCELLED),
+        )
+        .filter(
+            Condition::any()
+                .add(scheduled_messages::Column::SenderId.eq(user.id))
+                .add(scheduled_messages::Column::RecipientId.eq(user.id)),
+        )
+        .filter(scheduled_messages::Column::Status.eq(scheduled::STATUS_PENDING))
+        .exec(&txn)
+        .await?;
+
+    let id = user.id;
+    let old_username = user.username.clone();
+    let now = Utc::now();
+    let mut update: users::ActiveModel = user.into();
+    update.username = Set(tombstone_name(id));
+    update.phone_number = Set(tombstone_name(id));
+    update.role = Set(Role::User.as_str().to_string());
+    update.suspended_until = Set(None);
+    update.deleted_at = Set(Some(now));
+    update.updated_at = Set(Some(now));
+    update.update(&txn).await?;
+    txn.commit().await?;
+
+    // Blobs go only once the rows are gone, so nothing points at a missing file
+    for attachment in &doomed_attachments {
+        delete_blobs(storage, attachment).await;
+    }
+    for key in exports.iter().filter_map(|e| e.storage_key.as_deref()) {
+        if let Err(e) = storage.delete(key).await {
+            warn!("Failed to delete export archive {}: {}", key, e);
+        }
+    }
+    if !sent_ids.is_empty() {
+        WebhookEvent::new(
+            MESSAGE_DELETED,
+            MessagesDeletedData {
+                ids: sent_ids,
+                reason: "account_deleted",
+            },
+        )
+        .emit(db)
+        .await;
+    }
+
+    disconnect_user(online, &old_username, "Your account has been deleted").await;
+    let frame = AccountDeletedFrame::new(old_username, policy == DeletionPolicy::Delete).to_text();
+    for partner in Users::find()
+        .filter(users::Column::Id.is_in(partner_ids))
+        .all(db)
+        .await?
+    {
+        SyncEvent::new(frame.clone())
+            .publish(db, online, partner.id, &partner.username)
+            .await;
+    }
+
+    info!("🗑️ Account {} deleted ({})", id, policy.as_str());
+    Ok(DeletionSummary {
+        messages_deleted,
+        attachments_deleted: doomed_attachments.len(),
+    })
+}
@@ -0,0 +1,661 @@
+//! Background builder for GDPR data exports. Requests are queued as
+//! `pending` rows in `data_exports`; a worker claims them, zips the user's
+//! profile, conversations and attachment files into a temporary file,
+//! uploads that to storage, and later removes archives whose download
+//! window has passed.
+
+use crate::account::types::{
+    ArchivedAttachment, ArchivedMessage, BlockArchive, ConversationArchive, ExportReadyFrame,
+    ProfileArchive, ReportArchive, EXPORT_EXPIRED, EXPORT_FAILED, EXPORT_PENDING,
+    EXPORT_PROCESSING, EXPORT_READY,
+};
+use crate::attachments::StorageBackend;
+use crate::blocks::BLOCKED_STATUS;
+use crate::entity::{
+    attachments, data_exports, messages, reports, user_blocks, users, Attachments, DataExports,
+    Messages, Reports, UserBlocks, Users,
+};
+use crate::moderation::REMOVED_STATUS;
+use crate::retention::not_expired;
+use crate::shutdown::Shutdown;
+use crate::ws::{send_to_user, SharedState};
+use chrono::{DateTime, Duration, SubsecRound, Utc};
+use hmac::{Hmac, Mac};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+    QueryFilter, QueryOrder, Set,
+};
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+use uuid::Uuid;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+/// A `processing` row claimed longer ago than this belongs to a worker that
+/// died.
+const STALE_AFTER: Duration = Duration::minutes(30);
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn mac(key: &str, export_id: i32, expires: i64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("export:{}:{}", export_id, expires).as_bytes());
+    mac
+}
+
+/// Signature binding a download link to one export and an expiry (unix seconds).
+pub fn sign(key: &str, export_id: i32, expires: i64) -> String {
+    hex::encode(mac(key, export_id, expires).finalize().into_bytes())
+}
+
+pub fn verify(key: &str, export_id: i32, expires: i64, signature: &str) -> bool {
+    match hex::decode(signature) {
+        Ok(bytes) => mac(key, export_id, expires).verify_slice(&bytes).is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[derive(Clone)]
+pub struct ExportWorker {
+    db: DatabaseConnection,
+    storage: Arc<dyn StorageBackend>,
+    online: SharedState,
+    retention: Duration,
+    wake: Arc<Notify>,
+}
+
+impl ExportWorker {
+    /// `retention` is how long a finished archive stays downloadable.
+    pub fn new(
+        db: DatabaseConnection,
+        storage: Arc<dyn StorageBackend>,
+        online: SharedState,
+        retention: Duration,
+    ) -> Self {
+        Self {
+            db,
+            storage,
+            online,
+            retention,
+            wake: Arc::new(Notify::new()),
+        }
+    }
+
+    pub fn notify(&self) {
+        self.wake.notify_one();
+    }
+
+    pub fn spawn(self, shutdown: &Shutdown) -> tokio::task::JoinHandle<()> {
+        let shutdown = shutdown.clone();
+        shutdown.clone().spawn(async move {
+            while !shutdown.is_draining() {
+                if let Err(e) = self.requeue_stale().await {
+                    warn!("Export worker failed to requeue stale jobs: {}", e);
+                }
+                if let Err(e) = self.run_pending().await {
+                    warn!("Export worker failed to poll queue: {}", e);
+                }
+                if let Err(e) = self.expire_archives().await {
+                    warn!("Export worker failed to expire archives: {}", e);
+                }
+                tokio::select! {
+                    _ = shutdown.cancelled() => {}
+                    _ = self.wake.notified() => {}
+                    _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                }
+            }
+        })
+    }
+
+    /// Hand jobs left in `processing` back to the queue. Staleness runs from
+    /// the claim, not the request, so a job that waited behind others isn't
+    /// taken from the worker still building it. Rows claimed before claims
+    /// were timestamped have no `claimed_at` and are stale by definition.
+    async fn requeue_stale(&self) -> Result<(), sea_orm::DbErr> {
+        let requeued = DataExports::update_many()
+            .col_expr(data_exports::Column::Status, Expr::value(EXPORT_PENDING))
+            .col_expr(
+                data_exports::Column::ClaimedAt,
+                Expr::value(Option::<chrono::DateTime<Utc>>::None),
+            )
+            .filter(data_exports::Column::Status.eq(EXPORT_PROCESSING))
+            .filter(
+                Condition::any()
+                    .add(data_exports::Column::ClaimedAt.is_null())
+                    .add(data_exports::Column::ClaimedAt.lt(Utc::now() - STALE_AFTER)),
+            )
+            .exec(&self.db)
+            .await?
+            .rows_affected;
+        if requeued > 0 {
+            warn!("Requeued {} interrupted data export(s)", requeued);
+        }
+        Ok(())
+    }
+
+    async fn run_pending(&self) -> Result<(), sea_orm::DbErr> {
+        let pending = DataExports::find()
+            .filter(data_exports::Column::Status.eq(EXPORT_PENDING))
+            .order_by_asc(data_exports::Column::Id)
+            .all(&self.db)
+            .await?;
+
+        for job in pending {
+            if let Some(claimed_at) = self.claim(job.id).await? {
+                self.process(job, claimed_at).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Atomically move a job from pending to processing so that concurrent
+    /// server instances never build the same archive. Returns the claim's
+    /// timestamp, which identifies it when the result is written.
+    async fn claim(&self, id: i32) -> Result<Option<DateTime<Utc>>, sea_orm::DbErr> {
+        // Postgres keeps microseconds; round so the value compares equal later
+        let claimed_at = Utc::now().trunc_subsecs(6);
+        let result = DataExports::update_many()
+            .col_expr(data_exports::Column::Status, Expr::value(EXPORT_PROCESSING))
+            .col_expr(data_exports::Column::ClaimedAt, Expr::value(claimed_at))
+            .filter(data_exports::Column::Id.eq(id))
+            .filter(data_exports::Column::Status.eq(EXPORT_PENDING))
+            .exec(&self.db)
+            .await?;
+        Ok((result.rows_affected == 1).then_some(claimed_at))
+    }
+
+    /// Build the archive and record the outcome, unless the claim was lost
+    /// meanwhile: a build that outlives `STALE_AFTER` is requeued and may be
+    /// claimed again, and only the newest claim may finish the job.
+    async fn process(
+        &self,
+        job: data_exports::Model,
+        claimed_at: DateTime<Utc>,
+    ) -> Result<(), sea_orm::DbErr> {
+        let outcome = self.build(&job).await;
+        let now = Utc::now();
+        let mut update = DataExports::update_many()
+            .col_expr(
+                data_exports::Column::ClaimedAt,
+                Expr::value(Option::<DateTime<Utc>>::None),
+            )
+            .col_expr(data_exports::Column::CompletedAt, Expr::value(now));
+        update = match &outcome {
+            Ok((key, size)) => update
+                .col_expr(data_exports::Column::Status, Expr::value(EXPORT_READY))
+                .col_expr(data_exports::Column::StorageKey, Expr::value(key.clone()))
+                .col_expr(data_exports::Column::SizeBytes, Expr::value(*size as i64))
+                .col_expr(
+                    data_exports::Column::ExpiresAt,
+                    Expr::value(now + self.retention),
+                ),
+            Err(e) => update
+                .col_expr(data_exports::Column::Status, Expr::value(EXPORT_FAILED))
+                .col_expr(data_exports::Column::Error, Expr::value(e.clone())),
+        };
+        let written = update
+            .filter(data_exports::Column::Id.eq(job.id))
+            .filter(data_exports::Column::Status.eq(EXPORT_PROCESSING))
+            .filter(data_exports::Column::ClaimedAt.eq(claimed_at))
+            .exec(&self.db)
+            .await?
+            .rows_affected
+            == 1;
+
+        if !written {
+            warn!("Data export {} was reclaimed while building", job.id);
+            if let Ok((key, _)) = &outcome {
+                if let Err(e) = self.storage.delete(key).await {
+                    warn!("Failed to delete export archive {}: {}", key, e);
+                }
+            }
+            return Ok(());
+        }
+        match &outcome {
+            Ok((_, size)) => info!("📦 Data export {} ready ({} bytes)", job.id, size),
+            Err(e) => warn!("Data export {} failed: {}", job.id, e),
+        }
+
+        let Some(updated) = DataExports::find_by_id(job.id).one(&self.db).await? else {
+            return Ok(());
+        };
+        if let Some(user) = Users::find_by_id(job.user_id).one(&self.db).await? {
+            let frame = ExportReadyFrame::new(&updated).to_text();
+            send_to_user(&self.online, &user.username, frame).await;
+        }
+        Ok(())
+    }
+
+    /// Remove archives past their download window.
+    async fn expire_archives(&self) -> Result<(), sea_orm::DbErr> {
+        let expired = DataExports::find()
+            .filter(data_exports::Column::Status.eq(EXPORT_READY))
+            .filter(data_exports::Column::ExpiresAt.lte(Utc::now()))
+            .all(&self.db)
+            .await?;
+
+        for export in expired {
+            if let Some(key) = &export.storage_key {
+                if let Err(e) = self.storage.delete(key).await {
+                    warn!("Failed to delete export archive {}: {}", key, e);
+                    continue;
+                }
+            }
+            let mut update: data_exports::ActiveModel = export.into();
+            update.status = Set(EXPORT_EXPIRED.to_string());
+            update.storage_key = Set(None);
+            update.update(&self.db).await?;
+        }
+        Ok(())
+    }
+
+    /// Write the archive to storage, returning its key and size.
+    async fn build(&self, job: &data_exports::Model) -> Result<(String, u64), String> {
+        let user = Users::find_by_id(job.user_id)
+            .one(&self.db)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or("user no longer exists")?;
+
+        let mut archive = ArchiveBuilder::new()?;
+        self.add_profile(&mut archive, &user)?;
+        self.add_conversations(&mut archive, &user).await?;
+        self.add_blocks(&mut archive, &user).await?;
+        self.add_reports(&mut archive, &user).await?;
+        let file = archive.finish()?;
+
+        let key = format!("exports/{}/{}.zip", user.id, Uuid::new_v4());
+        let size = self
+            .storage
+            .put_file(&key, "application/zip", tokio::fs::File::from_std(file))
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok((key, size))
+    }
+
+    fn add_profile(&self, archive: &mut ArchiveBuilder, user: &users::Model) -> Result<(), String> {
+        archive.add_json(
+            "profile.json",
+            &ProfileArchive {
+                id: user.id,
+                username: user.username.clone(),
+                phone_number: user.phone_number.clone(),
+                role: user.role.clone(),
+                created_at: user.created_at,
+                updated_at: user.updated_at,
+                suspended_until: user.suspended_until,
+                exported_at: Utc::now(),
+            },
+        )
+    }
+
+    /// One JSON file per conversation partner, plus every attachment file.
+    async fn add_conversations(
+        &self,
+        archive: &mut ArchiveBuilder,
+        user: &users::Model,
+    ) -> Result<(), String> {
+        let all = Messages::find()
+            .filter(
+                Condition::any()
+                    .add(messages::Column::SenderId.eq(user.id))
+                    .add(messages::Column::ReceiverId.eq(user.id)),
+            )
+            .filter(messages::Column::Status.ne(REMOVED_STATUS))
+            .filter(not_expired())
+            .order_by_asc(messages::Column::CreatedAt)
+            .all(&self.db)
+            .await
+            .map_err(|e| e.to_string())?;
+        // Messages hidden from the user by a block were never delivered to them
+        let all: Vec<messages::Model> = all
+            .into_iter()
+            .filter(|m| !(m.receiver_id == user.id && m.status == BLOCKED_STATUS))
+            .collect();
+
+        let partner_ids: HashSet<i32> = all
+            .iter()
+            .map(|m| {
+                if m.sender_id == user.id {
+                    m.receiver_id
+                } else {
+                    m.sender_id
+                }
+            })
+            .collect();
+        let names = usernames(&self.db, partner_ids.into_iter().collect()).await?;
+
+        let message_ids: Vec<i32> = all.iter().map(|m| m.id).collect();
+        let mut files: HashMap<i32, Vec<attachments::Model>> = HashMap::new();
+        for chunk in message_ids.chunks(1000) {
+            for attachment in Attachments::find()
+                .filter(attachments::Column::MessageId.is_in(chunk.to_vec()))
+                .all(&self.db)
+                .await
+                .map_err(|e| e.to_string())?
+            {
+                if let Some(message_id) = attachment.message_id {
+                    files.entry(message_id).or_default().push(attachment);
+                }
+            }
+        }
+
+        let mut conversations: BTreeMap<String, Vec<ArchivedMessage>> = BTreeMap::new();
+        for message in all {
+            let (direction, partner) = if message.sender_id == user.id {
+                ("sent", message.receiver_id)
+            } else {
+                ("received", message.sender_id)
+            };
+
+            let mut archived = Vec::new();
+            for attachment in files.remove(&message.id).unwrap_or_default() {
+                let path = format!(
+                    "attachments/{}-{}",
+                    attachment.id,
+                    sanitize_file_name(&attachment.file_name)
+                );
+                match self.storage.get(&attachment.storage_key).await {
+                    Ok(data) => archive.add_file(&path, &data)?,
+                    // Keep going; the metadata is still worth exporting
+                    Err(e) => warn!("Export skipped attachment {}: {}", attachment.id, e),
+                }
+                archived.push(ArchivedAttachment {
+                    id: attachment.id,
+                    file_name: attachment.file_name,
+                    content_type: attachment.content_type,
+                    size_bytes: attachment.size_bytes,
+                    path,
+                });
+            }
+
+            let with = names
+                .get(&partner)
+                .cloned()
+                .unwrap_or_else(|| format!("user-{}", partner));
+            conversations
+                .entry(with)
+                .or_default()
+                .push(ArchivedMessage {
+                    id: message.id,
+                    direction,
+                    message: message.message,
+                    status: message.status,
+                    created_at: message.created_at,
+                    attachments: archived,
+                });
+        }
+
+        for (with, messages) in conversations {
+            archive.add_json(
+                &format!("conversations/{}.json", sanitize_file_name(&with)),
+                &ConversationArchive { with, messages },
+            )?;
+        }
+        Ok(())
+    }
+
+    async fn add_blocks(
+        &self,
+        archive: &mut ArchiveBuilder,
+        user: &users::Model,
+    ) -> Result<(), String> {
+        let blocks = UserBlocks::find()
+            .filter(user_blocks::Column::BlockerId.eq(user.id))
+            .find_also_related(Users)
+            .all(&self.db)
+            .await
+            .map_err(|e| e.to_string())?;
+        let blocks: Vec<BlockArchive> = blocks
+            .into_iter()
+            .filter_map(|(block, blocked)| {
+                blocked.map(|u| BlockArchive {
+                    username: u.username,
+                    blocked_at: block.created_at,
+                })
+            })
+            .collect();
+        archive.add_json("blocks.json", &blocks)
+    }
+
+    /// Reports the user filed. Reports against them are moderation records
+    /// and are not part of the export.
+    async fn add_reports(
+        &self,
+        archive: &mut ArchiveBuilder,
+        user: &users::Model,
+    ) -> Result<(), String> {
+        let filed = Reports::find()
+            .filter(reports::Column::ReporterId.eq(user.id))
+            .order_by_asc(reports::Column::CreatedAt)
+            .all(&self.db)
+            .await
+            .map_err(|e| e.to_string())?;
+        let names = usernames(&self.db, filed.iter().map(|r| r.reported_user_id).collect()).await?;
+
+        let filed: Vec<ReportArchive> = filed
+            .into_iter()
+            .map(|r| ReportArchive {
+                id: r.id,
+                reported_user: names.get(&r.reported_user_id).cloned(),
+                message_id: r.message_id,
+                reason: r.reason,
+                details: r.details,
+                status: r.status,
+                created_at: r.created_at,
+            })
+            .collect();
+        archive.add_json("reports.json", &filed)
+    }
+}
+
+async fn usernames(db: &DatabaseConnection, ids: Vec<i32>) -> Result<HashMap<i32, String>, String> {
+    Ok(Users::find()
+        .filter(users::Column::Id.is_in(ids))
+        .all(db)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|u| (u.id, u.username))
+        .collect())
+}
+
+/// Keep archive paths flat and portable.
+fn sanitize_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let cleaned = cleaned.trim_start_matches('.');
+    if cleaned.is_empty() {
+        "file".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+/// Zips into an anonymous temporary file, so an account with years of
+/// attachments doesn't have to fit in memory. The file disappears once
+/// closed.
+struct ArchiveBuilder {
+    zip: ZipWriter<File>,
+}
+
+impl ArchiveBuilder {
+    fn new() -> Result<Self, String> {
+        let file = tempfile::tempfile().map_err(|e| e.to_string())?;
+        Ok(Self {
+            zip: ZipWriter::new(file),
+        })
+    }
+
+    fn add_json<T: Serialize>(&mut self, path: &str, value: &T) -> Result<(), String> {
+        let json = serde_json::to_vec_pretty(value).map_err(|e| e.to_string())?;
+        self.write(path, &json, CompressionMethod::Deflated)
+    }
+
+    /// Attachments are mostly already-compressed media, so they are stored as is.
+    fn add_file(&mut self, path: &str, data: &[u8]) -> Result<(), String> {
+        self.write(path, data, CompressionMethod::Stored)
+    }
+
+    fn write(&mut self, path: &str, data: &[u8], method: CompressionMethod) -> Result<(), String> {
+        let options = FileOptions::default()
+            .compression_method(method)
+            .large_file(data.len() as u64 >= u32::MAX as u64);
+        self.zip
+            .start_file(path, options)
+            .map_err(|e| e.to_string())?;
+        self.zip.write_all(data).map_err(|e| e.to_string())
+    }
+
+    /// The finished archive, positioned at its start.
+    fn finish(mut self) -> Result<File, String> {
+        let mut file = self.zip.finish().map_err(|e| e.to_string())?;
+        file.rewind().map_err(|e| e.to_string())?;
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::storage::FsStorage;
+    use crate::test_support::{create_user, test_db};
+
+    async fn claimed_job(
+        db: &DatabaseConnection,
+        user_id: i32,
+        claimed_at: Option<chrono::DateTime<Utc>>,
+    ) -> i32 {
+        data_exports::ActiveModel {
+            user_id: Set(user_id),
+            status: Set(EXPORT_PROCESSING.to_string()),
+            claimed_at: Set(claimed_at),
+            // Requested long ago; only the claim's age should matter
+            created_at: Set(Utc::now() - Duration::days(1)),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap()
+        .id
+    }
+
+    #[tokio::test]
+    async fn only_stale_claims_are_requeued() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let worker = ExportWorker::new(
+            db.clone(),
+            Arc::new(FsStorage::new(std::env::temp_dir())),
+            SharedState::default(),
+            Duration::days(7),
+        );
+        let fresh = create_user(&db, "fresh").await;
+        let abandoned = create_user(&db, "abandoned").await;
+        let legacy = create_user(&db, "legacy").await;
+        let fresh = claimed_job(&db, fresh.id, Some(Utc::now())).await;
+        let abandoned = claimed_job(
+            &db,
+            abandoned.id,
+            Some(Utc::now() - STALE_AFTER - Duration::minutes(1)),
+        )
+        .await;
+        let legacy = claimed_job(&db, legacy.id, None).await;
+
+        worker.requeue_stale().await.unwrap();
+
+        let status = |id| {
+            let db = db.clone();
+            async move {
+                DataExports::find_by_id(id)
+                    .one(&db)
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .status
+            }
+        };
+        assert_eq!(status(fresh).await, EXPORT_PROCESSING);
+        assert_eq!(status(abandoned).await, EXPORT_PENDING);
+        assert_eq!(status(legacy).await, EXPORT_PENDING);
+    }
+
+    #[tokio::test]
+    async fn only_the_current_claim_finishes_a_job() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let root = std::env::temp_dir().join(format!("whisper-{}", Uuid::new_v4()));
+        let worker = ExportWorker::new(
+            db.clone(),
+            Arc::new(FsStorage::new(&root)),
+            SharedState::default(),
+            Duration::days(7),
+        );
+        let user = create_user(&db, "slow_export").await;
+        let job = data_exports::ActiveModel {
+            user_id: Set(user.id),
+            status: Set(EXPORT_PENDING.to_string()),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        // The first build outlives STALE_AFTER and the job is claimed again
+        let first = worker.claim(job.id).await.unwrap().unwrap();
+        DataExports::update_many()
+            .col_expr(
+                data_exports::Column::ClaimedAt,
+                Expr::value(first - STALE_AFTER - Duration::minutes(1)),
+            )
+            .filter(data_exports::Column::Id.eq(job.id))
+            .exec(&db)
+            .await
+            .unwrap();
+        worker.requeue_stale().await.unwrap();
+        let second = worker.claim(job.id).await.unwrap().unwrap();
+
+        let archives = || {
+            std::fs::read_dir(root.join(format!("exports/{}", user.id)))
+                .map(|entries| entries.count())
+                .unwrap_or(0)
+        };
+        worker.process(job.clone(), first).await.unwrap();
+        let row = DataExports::find_by_id(job.id)
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.status, EXPORT_PROCESSING);
+        assert_eq!(archives(), 0, "the stale build's archive is removed");
+
+        worker.process(job.clone(), second).await.unwrap();
+        let row = DataExports::find_by_id(job.id)
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.status, EXPORT_READY);
+        assert_eq!(row.claimed_at, None);
+        assert!(row.storage_key.is_some());
+        assert_eq!(archives(), 1);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::account::deletion::delete_account;
+use crate::account::export::{self, ExportWorker};
+use crate::account::types::{
+    DeletionPolicy, ExportDownloadParams, ExportView, EXPORT_PENDING, EXPORT_PROCESSING,
+    EXPORT_READY,
+};
+use crate::attachments::storage::StorageError;
+use crate::attachments::StorageBackend;
+use crate::audit::{AuditEvent, ClientIp, ACCOUNT_DELETED, ACCOUNT_EXPORT_REQUESTED};
+use crate::auth::CurrentUser;
+use crate::entity::{data_exports, users, DataExports, Users};
+use crate::error::AppError;
+use crate::ws::SharedState;
+use axum::{
+    extract::{FromRef, Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{Duration, TimeZone, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub struct AccountState {
+    pub db: DatabaseConnection,
+    pub online: SharedState,
+    pub storage: Arc<dyn StorageBackend>,
+    pub exports: ExportWorker,
+    pub deletion_policy: DeletionPolicy,
+    pub signing_key: Arc<str>,
+    pub url_ttl: Duration,
+}
+
+impl FromRef<AccountState> for DatabaseConnection {
+    fn from_ref(state: &AccountState) -> Self {
+        state.db.clone()
+    }
+}
+
+fn view(state: &AccountState, export: data_exports::Model) -> ExportView {
+    let download_url = (export.status == EXPORT_READY).then(|| {
+        let mut expires_at = Utc::now() + state.url_ttl;
+        if let Some(archive_expiry) = export.expires_at {
+            expires_at = expires_at.min(archive_expiry);
+        }
+        let expires = expires_at.timestamp();
+        format!(
+            "/exports/{}/download?expires={}&sig={}",
+            export.id,
+            expires,
+            export::sign(&state.signing_key, export.id, expires)
+        )
+    });
+    ExportView::new(export, download_url)
+}
+
+/// `DELETE /users/me` — delete the caller's account. Irreversible.
+pub async fn delete_me(
+    State(state): State<AccountState>,
+    CurrentUser(user): CurrentUser,
+    ClientIp(ip): ClientIp,
+) -> Result<StatusCode, AppError> {
+    let user_id = user.id;
+    let summary = delete_account(
+        &state.db,
+        state.storage.as_ref(),
+        &state.online,
+        user,
+        state.deletion_policy,
+    )
+    .await?;
+
+    AuditEvent::new(ACCOUNT_DELETED)
+        .actor(user_id)
+        .ip(ip)
+        .detail("policy", state.deletion_policy.as_str())
+        .detail("messages_deleted", summary.messages_deleted)
+        .detail("attachments_deleted", summary.attachments_deleted)
+        .log(&state.db)
+        .await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `POST /users/me/export` — queue an archive of the caller's data. Returns the
+/// unfinished request instead if one is already queued.
+pub async fn request_export(
+    State(state): State<AccountState>,
+    CurrentUser(user): CurrentUser,
+    ClientIp(ip): ClientIp,
+) -> Result<(StatusCode, Json<ExportView>), AppError> {
+    let unfinished = DataExports::find()
+        .filter(data_exports::Column::UserId.eq(user.id))
+        .filter(data_exports::Column::Status.is_in([EXPORT_PENDING, EXPORT_PROCESSING]))
+        .one(&state.db)
+        .await?;
+    if let Some(export) = unfinished {
+        return Ok((StatusCode::ACCEPTED, Json(view(&state, export))));
+    }
+
+    let export = data_exports::ActiveModel {
+        user_id: Set(user.id),
+        status: Set(EXPORT_PENDING.to_string()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await?;
+    state.exports.notify();
+
+    AuditEvent::new(ACCOUNT_EXPORT_REQUESTED)
+        .actor(user.id)
+        .ip(ip)
+        .detail("export_id", export.id)
+        .log(&state.db)
+        .await;
+    Ok((StatusCode::ACCEPTED, Json(view(&state, export))))
+}
+
+/// `GET /users/me/exports` — the caller's export requests, newest first.
+pub async fn list_exports(
+    State(state): State<AccountState>,
+    CurrentUser(user): CurrentUser,
+) -> Result<Json<Vec<ExportView>>, AppError> {
+    let exports = DataExports::find()
+        .filter(data_exports::Column::UserId.eq(user.id))
+        .order_by_desc(data_exports::Column::Id)
+        .all(&state.db)
+        .await?;
+    Ok(Json(exports.into_iter().map(|e| view(&state, e)).collect()))
+}
+
+/// `GET /users/me/exports/:id` — poll one request; carries a fresh download
+/// link once ready.
+pub async fn get_export(
+    State(state): State<AccountState>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i32>,
+) -> Result<Json<ExportView>, AppError> {
+    let export = DataExports::find_by_id(id)
+        .filter(data_exports::Column::UserId.eq(user.id))
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AppError::not_found("Export"))?;
+    Ok(Json(view(&state, export)))
+}
+
+/// `GET /exports/:id/download` — serve a ready archive for a valid, unexpired signed link.
+pub async fn download_export(
+    State(state): State<AccountState>,
+    Path(id): Path<i32>,
+    Query(params): Query<ExportDownloadParams>,
+) -> Result<Response, AppError> {
+    let denied = || AppError::forbidden("Invalid or expired link");
+
+    if !export::verify(&state.signing_key, id, params.expires, &params.sig) {
+        return Err(denied());
+    }
+    let expires_at = Utc
+        .timestamp_opt(params.expires, 0)
+        .single()
+        .ok_or_else(denied)?;
+    if expires_at < Utc::now() {
+        return Err(denied());
+    }
+
+    let export = DataExports::find_by_id(id)
+        .one(&state.db)
+        .await?
+        .filter(|e| e.status == EXPORT_READY)
+        .ok_or_else(denied)?;
+    // The owner may have deleted their account since the link was issued
+    let owner = Users::find_by_id(export.user_id)
+        .filter(users::Column::DeletedAt.is_null())
+        .one(&state.db)
+        .await?
+        .ok_or_else(denied)?;
+    let key = export.storage_key.ok_or_else(denied)?;
+
+    let data = state.storage.get(&key).await.map_err(|e| match e {
+        StorageError::NotFound(_) => AppError::not_found("Export"),
+        e => AppError::Internal(e.to_string()),
+    })?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"whisper-export-{}-{}.zip\"",
+                    owner.id, export.id
+                ),
+            ),
+            (header::CACHE_CONTROL, "private, no-store".to_string()),
+        ],
+        data,
+    )
+        .into_response())
+}
@@ -0,0 +1,7 @@
+pub mod deletion;
+pub mod export;
+pub mod handlers;
+pub mod routes;
+pub mod types;
+
+pub use export::ExportWorker;
@@ -0,0 +1,18 @@
+use axum::{
+    routing::{delete, get, post},
+    Router,
+};
+
+use crate::account::handlers::{
+    delete_me, download_export, get_export, list_exports, request_export, AccountState,
+};
+
+pub fn configure_account_routes(state: AccountState) -> Router {
+    Router::new()
+        .route("/users/me", delete(delete_me))
+        .route("/users/me/export", post(request_export))
+        .route("/users/me/exports", get(list_exports))
+        .route("/users/me/exports/:id", get(get_export))
+        .route("/exports/:id/download", get(download_export))
+        .with_state(state)
+}
@@ -0,0 +1,186 @@
+use crate::entity::data_exports;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub const EXPORT_PENDING: &str = "pending";
+pub const EXPORT_PROCESSING: &str = "processing";
+pub const EXPORT_READY: &str = "ready";
+pub const EXPORT_FAILED: &str = "failed";
+pub const EXPORT_EXPIRED: &str = "expired";
+
+/// What happens to a deleted account's messages, from `ACCOUNT_DELETION_POLICY`
+/// (see `config`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeletionPolicy {
+    /// Keep messages; the sender shows up as `deleted-<id>` in the
+    /// counterpart's history. The default.
+    Anonymize,
+    /// Delete every message the user sent, with its attachments. Messages
+    /// they received stay in the sender's history.
+    Delete,
+}
+
+impl DeletionPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeletionPolicy::Anonymize => "anonymize",
+            DeletionPolicy::Delete => "delete",
+        }
+    }
+}
+
+impl std::str::FromStr for DeletionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "anonymize" => Ok(DeletionPolicy::Anonymize),
+            "delete" => Ok(DeletionPolicy::Delete),
+            other => Err(format!(
+                "unknown deletion policy '{}', expected 'anonymize' or 'delete'",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ExportView {
+    pub id: i32,
+    pub status: String,
+    pub size_bytes: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// When the archive is deleted from the server
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Signed link, only present once the archive is ready
+    pub download_url: Option<String>,
+}
+
+impl ExportView {
+    pub fn new(export: data_exports::Model, download_url: Option<String>) -> Self {
+        Self {
+            id: export.id,
+            status: export.status,
+            size_bytes: export.size_bytes,
+            error: export.error,
+            created_at: export.created_at,
+            completed_at: export.completed_at,
+            expires_at: export.expires_at,
+            download_url,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ExportDownloadParams {
+    pub expires: i64,
+    pub sig: String,
+}
+
+/// Sent to online conversation partners when an account is deleted.
+#[derive(Serialize)]
+pub struct AccountDeletedFrame {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// The username the partner knew, before anonymization
+    pub username: String,
+    /// Whether the user's messages were deleted and should be purged locally
+    pub messages_removed: bool,
+}
+
+impl AccountDeletedFrame {
+    pub fn new(username: String, messages_removed: bool) -> Self {
+        Self {
+            kind: "account_deleted",
+            username,
+            messages_removed,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).expect("account frame serializes")
+    }
+}
+
+/// Sent to the requester when their export finishes.
+#[derive(Serialize)]
+pub struct ExportReadyFrame {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub id: i32,
+    pub status: String,
+}
+
+impl ExportReadyFrame {
+    pub fn new(export: &data_exports::Model) -> Self {
+        Self {
+            kind: "export_ready",
+            id: export.id,
+            status: export.status.clone(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).expect("export frame serializes")
+    }
+}
+
+// Archive contents
+
+#[derive(Serialize)]
+pub struct ProfileArchive {
+    pub id: i32,
+    pub username: String,
+    pub phone_number: String,
+    pub role: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub suspended_until: Option<DateTime<Utc>>,
+    pub exported_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct ArchivedAttachment {
+    pub id: i32,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    /// Path of the file inside the archive
+    pub path: String,
+}
+
+#[derive(Serialize)]
+pub struct ArchivedMessage {
+    pub id: i32,
+    /// `sent` or `received`
+    pub direction: &'static str,
+    pub message: String,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub attachments: Vec<ArchivedAttachment>,
+}
+
+#[derive(Serialize)]
+pub struct ConversationArchive {
+    pub with: String,
+    pub messages: Vec<ArchivedMessage>,
+}
+
+#[derive(Serialize)]
+pub struct BlockArchive {
+    pub username: String,
+    pub blocked_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct ReportArchive {
+    pub id: i32,
+    pub reported_user: Option<String>,
+    pub message_id: Option<i32>,
+    pub reason: String,
+    pub details: Option<String>,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+}
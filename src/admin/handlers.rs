@@ -0,0 +1,254 @@
+use crate::admin::types::{
+    AccountDetails, ServerStats, SetRoleRequest, UserSearchParams, UserSummary,
+};
+use crate::auth::{check_sanction, Admin, Moderator, Role};
+use crate::entity::{messages, reports, users, Attachments, Messages, Reports, Users};
+use crate::error::AppError;
+use crate::moderation::handlers::record_action;
+use crate::moderation::types::{NoteRequest, STATUS_OPEN};
+use crate::ws::{disconnect_user, SharedState};
+use axum::{
+    extract::{FromRef, Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{Duration, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, Set,
+};
+use tracing::warn;
+
+#[derive(Clone)]
+pub struct AdminState {
+    pub db: DatabaseConnection,
+    pub online: SharedState,
+}
+
+impl FromRef<AdminState> for DatabaseConnection {
+    fn from_ref(state: &AdminState) -> Self {
+        state.db.clone()
+    }
+}
+
+async fn find_user(db: &DatabaseConnection, username: &str) -> Result<users::Model, AppError> {
+    Users::find()
+        .filter(users::Column::Username.eq(username))
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::not_found("User"))
+}
+
+/// `GET /admin/users` — find accounts by username/phone, role or status.
+pub async fn search_users(
+    State(state): State<AdminState>,
+    Moderator(_): Moderator,
+    Query(params): Query<UserSearchParams>,
+) -> Result<Json<Vec<UserSummary>>, AppError> {
+    let mut query = Users::find();
+
+    if let Some(q) = params.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        query = query.filter(
+            Condition::any()
+                .add(users::Column::Username.contains(q))
+                .add(users::Column::PhoneNumber.contains(q)),
+        );
+    }
+    if let Some(role) = params.role.as_deref() {
+        let role: Role = role.parse().map_err(AppError::BadRequest)?;
+        query = query.filter(users::Column::Role.eq(role.as_str()));
+    }
+    let now = Utc::now();
+    query = match params.status.as_deref() {
+        None => query,
+        Some("deleted") => query.filter(users::Column::DeletedAt.is_not_null()),
+        Some("banned") => query
+            .filter(users::Column::DeletedAt.is_null())
+            .filter(users::Column::BannedAt.is_not_null()),
+        Some("suspended") => query
+            .filter(users::Column::DeletedAt.is_null())
+            .filter(users::Column::BannedAt.is_null())
+            .filter(users::Column::SuspendedUntil.gt(now)),
+        Some("active") => query
+            .filter(users::Column::DeletedAt.is_null())
+            .filter(users::Column::BannedAt.is_null())
+            .filter(
+                Condition::any()
+                    .add(users::Column::SuspendedUntil.is_null())
+                    .add(users::Column::SuspendedUntil.lte(now)),
+            ),
+        Some(other) => return Err(AppError::bad_request(format!("Unknown status '{}'", other))),
+    };
+
+    let found = query
+        .order_by_asc(users::Column::Id)
+        .limit(params.limit.unwrap_or(50).min(200))
+        .offset(params.offset.unwrap_or(0))
+        .all(&state.db)
+        .await?;
+
+    Ok(Json(found.into_iter().map(UserSummary::from).collect()))
+}
+
+/// `GET /admin/users/:username` — account status and activity counters.
+pub async fn get_account(
+    State(state): State<AdminState>,
+    Moderator(_): Moderator,
+    Path(username): Path<String>,
+) -> Result<Json<AccountDetails>, AppError> {
+    let user = find_user(&state.db, &username).await?;
+
+    let messages_sent = Messages::find()
+        .filter(messages::Column::SenderId.eq(user.id))
+        .count(&state.db)
+        .await?;
+    let messages_received = Messages::find()
+        .filter(messages::Column::ReceiverId.eq(user.id))
+        .count(&state.db)
+        .await?;
+    let open_reports = Reports::find()
+        .filter(reports::Column::ReportedUserId.eq(user.id))
+        .filter(reports::Column::Status.eq(STATUS_OPEN))
+        .count(&state.db)
+        .await?;
+    let online = state.online.lock().await.contains_key(&user.username);
+
+    Ok(Json(AccountDetails {
+        suspended_until: user.suspended_until,
+        banned_at: user.banned_at,
+        user: UserSummary::from(user),
+        online,
+        messages_sent,
+        messages_received,
+        open_reports,
+    }))
+}
+
+/// `POST /admin/users/:username/ban` — ban the account and drop its sockets immediately.
+pub async fn ban_user(
+    State(state): State<AdminState>,
+    Admin(admin): Admin,
+    Path(username): Path<String>,
+    payload: Option<Json<NoteRequest>>,
+) -> Result<StatusCode, AppError> {
+    let user = find_user(&state.db, &username).await?;
+    check_sanction(&admin, &user, "ban")?;
+
+    let mut update: users::ActiveModel = user.into();
+    update.banned_at = Set(Some(Utc::now()));
+    update.updated_at = Set(Some(Utc::now()));
+    let user = update.update(&state.db).await?;
+
+    warn!("⛔ {} banned by {}", user.username, admin.username);
+    disconnect_user(
+        &state.online,
+        &user.username,
+        "Your account has been banned",
+    )
+    .await;
+
+    let Json(payload) = payload.unwrap_or_default();
+    record_action(
+        &state.db,
+        admin.id,
+        "ban_user",
+        None,
+        Some(user.id),
+        None,
+        payload.note,
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `POST /admin/users/:username/unban`
+pub async fn unban_user(
+    State(state): State<AdminState>,
+    Admin(admin): Admin,
+    Path(username): Path<String>,
+    payload: Option<Json<NoteRequest>>,
+) -> Result<StatusCode, AppError> {
+    let user = find_user(&state.db, &username).await?;
+
+    let mut update: users::ActiveModel = user.into();
+    update.banned_at = Set(None);
+    update.updated_at = Set(Some(Utc::now()));
+    let user = update.update(&state.db).await?;
+
+    let Json(payload) = payload.unwrap_or_default();
+    record_action(
+        &state.db,
+        admin.id,
+        "unban_user",
+        None,
+        Some(user.id),
+        None,
+        payload.note,
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `PUT /admin/users/:username/role`
+pub async fn set_role(
+    State(state): State<AdminState>,
+    Admin(admin): Admin,
+    Path(username): Path<String>,
+    Json(payload): Json<SetRoleRequest>,
+) -> Result<Json<UserSummary>, AppError> {
+    let role: Role = payload.role.parse().map_err(AppError::Unprocessable)?;
+    let user = find_user(&state.db, &username).await?;
+    // Keeps at least one admin around
+    if user.id == admin.id && role != Role::Admin {
+        return Err(AppError::bad_request("You cannot demote yourself"));
+    }
+
+    let mut update: users::ActiveModel = user.into();
+    update.role = Set(role.as_str().to_string());
+    update.updated_at = Set(Some(Utc::now()));
+    let user = update.update(&state.db).await?;
+
+    record_action(
+        &state.db,
+        admin.id,
+        "set_role",
+        None,
+        Some(user.id),
+        None,
+        Some(format!("role={}", role.as_str())),
+    )
+    .await?;
+    Ok(Json(UserSummary::from(user)))
+}
+
+/// `GET /admin/stats`
+pub async fn server_stats(
+    State(state): State<AdminState>,
+    Admin(_): Admin,
+) -> Result<Json<ServerStats>, AppError> {
+    let db = &state.db;
+    let now = Utc::now();
+
+    Ok(Json(ServerStats {
+        users_total: Users::find().count(db).await?,
+        users_online: state.online.lock().await.len(),
+        users_suspended: Users::find()
+            .filter(users::Column::SuspendedUntil.gt(now))
+            .count(db)
+            .await?,
+        users_banned: Users::find()
+            .filter(users::Column::BannedAt.is_not_null())
+            .count(db)
+            .await?,
+        messages_total: Messages::find().count(db).await?,
+        messages_last_24h: Messages::find()
+            .filter(messages::Column::CreatedAt.gte(now - Duration::hours(24)))
+            .count(db)
+            .await?,
+        attachments_total: Attachments::find().count(db).await?,
+        reports_open: Reports::find()
+            .filter(reports::Column::Status.eq(STATUS_OPEN))
+            .count(db)
+            .await?,
+    }))
+}
@@ -0,0 +1,3 @@
+pub mod handlers;
+pub mod routes;
+pub mod types;
@@ -0,0 +1,19 @@
+use axum::{
+    routing::{get, post, put},
+    Router,
+};
+
+use crate::admin::handlers::{
+    ban_user, get_account, search_users, server_stats, set_role, unban_user, AdminState,
+};
+
+pub fn configure_admin_routes(state: AdminState) -> Router {
+    Router::new()
+        .route("/admin/users", get(search_users))
+        .route("/admin/users/:username", get(get_account))
+        .route("/admin/users/:username/ban", post(ban_user))
+        .route("/admin/users/:username/unban", post(unban_user))
+        .route("/admin/users/:username/role", put(set_role))
+        .route("/admin/stats", get(server_stats))
+        .with_state(state)
+}
@@ -0,0 +1,80 @@
+use crate::entity::users;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct UserSearchParams {
+    /// Substring of the username or phone number
+    pub q: Option<String>,
+    pub role: Option<String>,
+    /// `active`, `suspended`, `banned` or `deleted`
+    pub status: Option<String>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct UserSummary {
+    pub id: i32,
+    pub username: String,
+    pub phone_number: String,
+    pub role: String,
+    pub status: &'static str,
+    pub is_bot: bool,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+pub fn account_status(user: &users::Model) -> &'static str {
+    if user.is_deleted() {
+        "deleted"
+    } else if user.is_banned() {
+        "banned"
+    } else if user.is_suspended() {
+        "suspended"
+    } else {
+        "active"
+    }
+}
+
+impl From<users::Model> for UserSummary {
+    fn from(user: users::Model) -> Self {
+        Self {
+            status: account_status(&user),
+            id: user.id,
+            username: user.username,
+            phone_number: user.phone_number,
+            role: user.role,
+            is_bot: user.is_bot,
+            created_at: user.created_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AccountDetails {
+    #[serde(flatten)]
+    pub user: UserSummary,
+    pub suspended_until: Option<DateTime<Utc>>,
+    pub banned_at: Option<DateTime<Utc>>,
+    pub online: bool,
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub open_reports: u64,
+}
+
+#[derive(Deserialize)]
+pub struct SetRoleRequest {
+    pub role: String,
+}
+
+#[derive(Serialize)]
+pub struct ServerStats {
+    pub users_total: u64,
+    pub users_online: usize,
+    pub users_suspended: u64,
+    pub users_banned: u64,
+    pub messages_total: u64,
+    pub messages_last_24h: u64,
+    pub attachments_total: u64,
+    pub reports_open: u64,
+}
@@ -0,0 +1,460 @@
+//! Container parsing for voice notes. Supports Opus in Ogg and AAC in
+//! MP4/M4A. Nothing is decoded: duration comes from container timing and
+//! the waveform is derived from per-packet sizes, which track loudness
+//! closely for the VBR encoders used by mobile recorders.
+
+use crate::attachments::validation::AUDIO_MP4_BRANDS;
+
+/// Number of bars in the rendered waveform.
+pub const WAVEFORM_BARS: usize = 64;
+/// Voice notes longer than this are rejected.
+pub const MAX_DURATION_MS: u64 = 15 * 60 * 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioInfo {
+    pub content_type: &'static str,
+    pub duration_ms: u64,
+    /// `WAVEFORM_BARS` amplitudes scaled to 0..=100
+    pub waveform: Vec<u8>,
+}
+
+/// A packet's timing and size, the raw input to waveform computation.
+struct Packet {
+    duration: f64,
+    size: usize,
+}
+
+pub fn analyze(data: &[u8]) -> Result<AudioInfo, String> {
+    let (content_type, packets) = if data.starts_with(b"OggS") {
+        ("audio/ogg", ogg_opus_packets(data)?)
+    } else if data.len() >= 8 && &data[4..8] == b"ftyp" {
+        ("audio/mp4", mp4_packets(data)?)
+    } else {
+        return Err("Voice notes must be Opus/OGG or AAC/M4A".to_string());
+    };
+
+    let total: f64 = packets.iter().map(|p| p.duration).sum();
+    if packets.is_empty() || total <= 0.0 {
+        return Err("Audio contains no samples".to_string());
+    }
+    let duration_ms = (total * 1000.0).round() as u64;
+    if duration_ms > MAX_DURATION_MS {
+        return Err(format!(
+            "Voice notes are limited to {} seconds",
+            MAX_DURATION_MS / 1000
+        ));
+    }
+
+    Ok(AudioInfo {
+        content_type,
+        duration_ms,
+        waveform: waveform(&packets, total),
+    })
+}
+
+/// Downsample packet bitrates into `WAVEFORM_BARS` time buckets.
+fn waveform(packets: &[Packet], total: f64) -> Vec<u8> {
+    let mut sums = vec![0.0f64; WAVEFORM_BARS];
+    let mut weights = vec![0.0f64; WAVEFORM_BARS];
+    let mut t = 0.0;
+    for packet in packets {
+        let mid = t + packet.duration / 2.0;
+        let bucket = ((mid / total) * WAVEFORM_BARS as f64) as usize;
+        let bucket = bucket.min(WAVEFORM_BARS - 1);
+        if packet.duration > 0.0 {
+            sums[bucket] += packet.size as f64;
+            weights[bucket] += packet.duration;
+        }
+        t += packet.duration;
+    }
+
+    let rates: Vec<f64> = sums
+        .iter()
+        .zip(&weights)
+        .map(|(s, w)| if *w > 0.0 { s / w } else { 0.0 })
+        .collect();
+    let peak = rates.iter().cloned().fold(0.0, f64::max);
+    if peak <= 0.0 {
+        return vec![0; WAVEFORM_BARS];
+    }
+    rates
+        .iter()
+        .map(|r| ((r / peak) * 100.0).round() as u8)
+        .collect()
+}
+
+fn read_u16_le(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32_be(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn read_u64_be(data: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// Reassemble packets from Ogg pages and time them from their Opus TOC byte.
+fn ogg_opus_packets(data: &[u8]) -> Result<Vec<Packet>, String> {
+    let invalid = || "Invalid Ogg container".to_string();
+    let mut packets = Vec::new();
+    let mut current = Vec::new();
+    let mut pos = 0;
+    let mut index = 0usize;
+    let mut pre_skip = 0u16;
+    let mut last_granule = 0i64;
+
+    while pos < data.len() {
+        if data.get(pos..pos + 4) != Some(b"OggS") {
+            return Err(invalid());
+        }
+        let header = data.get(pos..pos + 27).ok_or_else(invalid)?;
+        let granule = i64::from_le_bytes(header[6..14].try_into().map_err(|_| invalid())?);
+        let segments = header[26] as usize;
+        let lacing = data
+            .get(pos + 27..pos + 27 + segments)
+            .ok_or_else(invalid)?;
+        let mut body = pos + 27 + segments;
+        if granule >= 0 {
+            last_granule = granule;
+        }
+
+        for &len in lacing {
+            let segment = data.get(body..body + len as usize).ok_or_else(invalid)?;
+            current.extend_from_slice(segment);
+            body += len as usize;
+            if len < 255 {
+                let packet = std::mem::take(&mut current);
+                match index {
+                    0 => {
+                        if !packet.starts_with(b"OpusHead") {
+                            return Err("Ogg stream is not Opus".to_string());
+                        }
+                        pre_skip = read_u16_le(&packet, 10).ok_or_else(invalid)?;
+                    }
+                    1 => {} // OpusTags
+                    _ => packets.push(Packet {
+                        duration: opus_packet_duration(&packet).ok_or_else(invalid)?,
+                        size: packet.len(),
+                    }),
+                }
+                index += 1;
+            }
+        }
+        pos = body;
+    }
+
+    // The final granule position is authoritative for the playable length;
+    // scale packet durations so their sum matches it.
+    let granule_secs = (last_granule - pre_skip as i64).max(0) as f64 / 48_000.0;
+    let packet_secs: f64 = packets.iter().map(|p| p.duration).sum();
+    if granule_secs > 0.0 && packet_secs > 0.0 {
+        let scale = granule_secs / packet_secs;
+        for packet in &mut packets {
+            packet.duration *= scale;
+        }
+    }
+    Ok(packets)
+}
+
+/// Duration in seconds encoded by an Opus packet's TOC byte (RFC 6716 §3.1).
+fn opus_packet_duration(packet: &[u8]) -> Option<f64> {
+    let toc = *packet.first()?;
+    let config = toc >> 3;
+    let frame_ms = match config {
+        0..=11 => [10.0, 20.0, 40.0, 60.0][(config % 4) as usize],
+        12..=15 => [10.0, 20.0][(config % 2) as usize],
+        _ => [2.5, 5.0, 10.0, 20.0][(config % 4) as usize],
+    };
+    let frames = match toc & 0x03 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => (*packet.get(1)? & 0x3F) as u32,
+    };
+    Some(frame_ms * frames as f64 / 1000.0)
+}
+
+/// Find the first child box of `kind` inside `data` (a box payload). Sizes
+/// come from the upload, so a box claiming more than is there ends the search.
+fn find_box<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let (header, size) = match read_u32_be(data, pos)? {
+            1 => (16, usize::try_from(read_u64_be(data, pos + 8)?).ok()?),
+            0 => (8, data.len() - pos),
+            n => (8, n as usize),
+        };
+        let end = pos.checked_add(size).filter(|end| *end <= data.len())?;
+        if size < header {
+            return None;
+        }
+        if &data[pos + 4..pos + 8] == kind {
+            return Some(&data[pos + header..end]);
+        }
+        pos = end;
+    }
+    None
+}
+
+fn find_path<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    path.iter()
+        .try_fold(data, |inner, kind| find_box(inner, kind))
+}
+
+/// Walk the first audio track's sample tables (mdhd, stts, stsz).
+fn mp4_packets(data: &[u8]) -> Result<Vec<Packet>, String> {
+    let invalid = || "Invalid MP4 container".to_string();
+    let ftyp = find_box(data, b"ftyp").ok_or_else(invalid)?;
+    let brand = ftyp.get(0..4).ok_or_else(invalid)?;
+    if !AUDIO_MP4_BRANDS.iter().any(|b| b.as_slice() == brand) {
+        return Err("MP4 file is not an audio container".to_string());
+    }
+
+    let moov = find_box(data, b"moov").ok_or_else(invalid)?;
+    let mut audio = None;
+    let mut pos = 0;
+    while let Some(trak) = find_box(&moov[pos..], b"trak") {
+        let offset = trak.as_ptr() as usize - moov.as_ptr() as usize;
+        pos = offset + trak.len();
+
+        let Some(hdlr) = find_path(trak, &[b"mdia", b"hdlr"]) else {
+            continue;
+        };
+        match hdlr.get(8..12) {
+            Some(b"vide") => return Err("Voice notes can't contain video".to_string()),
+            Some(b"soun") if audio.is_none() => audio = Some(trak),
+            _ => {}
+        }
+    }
+
+    if let Some(trak) = audio {
+        let mdhd = find_path(trak, &[b"mdia", b"mdhd"]).ok_or_else(invalid)?;
+        let timescale = match mdhd.first() {
+            Some(1) => read_u32_be(mdhd, 20),
+            _ => read_u32_be(mdhd, 12),
+        }
+        .filter(|t| *t > 0)
+        .ok_or_else(invalid)? as f64;
+
+        let stbl = find_path(trak, &[b"mdia", b"minf", b"stbl"]).ok_or_else(invalid)?;
+        let stsd = find_box(stbl, b"stsd").ok_or_else(invalid)?;
+        if stsd.get(12..16) != Some(b"mp4a") {
+            return Err("MP4 audio track is not AAC".to_string());
+        }
+        let stts = find_box(stbl, b"stts").ok_or_else(invalid)?;
+        let stsz = find_box(stbl, b"stsz").ok_or_else(invalid)?;
+
+        let mut durations = Vec::new();
+        let entries = read_u32_be(stts, 4).ok_or_else(invalid)? as usize;
+        for i in 0..entries {
+            let count = read_u32_be(stts, 8 + i * 8).ok_or_else(invalid)?;
+            let delta = read_u32_be(stts, 12 + i * 8).ok_or_else(invalid)?;
+            if durations.len() + count as usize > 10_000_000 {
+                return Err(invalid());
+            }
+            durations.resize(durations.len() + count as usize, delta as f64 / timescale);
+        }
+
+        let uniform = read_u32_be(stsz, 4).ok_or_else(invalid)? as usize;
+        let count = read_u32_be(stsz, 8).ok_or_else(invalid)? as usize;
+        let mut packets = Vec::with_capacity(count.min(durations.len()));
+        for (i, duration) in durations.into_iter().enumerate().take(count) {
+            let size = if uniform != 0 {
+                uniform
+            } else {
+                read_u32_be(stsz, 12 + i * 4).ok_or_else(invalid)? as usize
+            };
+            packets.push(Packet { duration, size });
+        }
+        return Ok(packets);
+    }
+
+    Err("MP4 file has no audio track".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ogg_page(granule: i64, packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut page = b"OggS\0\0".to_vec();
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&[0; 12]); // serial, sequence, crc
+        page.push(packets.len() as u8);
+        page.extend(packets.iter().map(|p| p.len() as u8));
+        for packet in packets {
+            page.extend_from_slice(packet);
+        }
+        page
+    }
+
+    /// `count` 20ms Opus packets of growing size.
+    fn opus_file(count: usize) -> Vec<u8> {
+        let mut head = b"OpusHead\x01\x01".to_vec();
+        head.extend_from_slice(&312u16.to_le_bytes()); // pre-skip
+        head.extend_from_slice(&48_000u32.to_le_bytes());
+        head.extend_from_slice(&[0, 0, 0]);
+        let tags = b"OpusTags\0\0\0\0\0\0\0\0".to_vec();
+        // TOC config 1 (SILK, 20ms), one frame
+        let packets: Vec<Vec<u8>> = (0..count).map(|i| vec![0x08; 10 + i]).collect();
+
+        let mut file = ogg_page(0, &[head]);
+        file.extend(ogg_page(0, &[tags]));
+        file.extend(ogg_page(312 + 960 * count as i64, &packets));
+        file
+    }
+
+    fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut b = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        b.extend_from_slice(kind);
+        b.extend_from_slice(payload);
+        b
+    }
+
+    /// `count` AAC frames of 1024 samples at 44.1kHz.
+    fn m4a_file(count: u32) -> Vec<u8> {
+        let mut hdlr = vec![0; 8];
+        hdlr.extend_from_slice(b"soun");
+        hdlr.extend_from_slice(&[0; 13]);
+        let mut mdhd = vec![0; 12];
+        mdhd.extend_from_slice(&44_100u32.to_be_bytes());
+        mdhd.extend_from_slice(&[0; 8]);
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 16];
+        stsd.extend_from_slice(b"mp4a");
+        stsd.extend_from_slice(&[0; 8]);
+        let mut stts = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stts.extend_from_slice(&count.to_be_bytes());
+        stts.extend_from_slice(&1024u32.to_be_bytes());
+        let mut stsz = vec![0; 8];
+        stsz.extend_from_slice(&count.to_be_bytes());
+        for i in 0..count {
+            stsz.extend_from_slice(&(100 + i).to_be_bytes());
+        }
+
+        let stbl = [
+            mp4_box(b"stsd", &stsd),
+            mp4_box(b"stts", &stts),
+            mp4_box(b"stsz", &stsz),
+        ]
+        .concat();
+        let minf = mp4_box(b"stbl", &stbl);
+        let mdia = [
+            mp4_box(b"hdlr", &hdlr),
+            mp4_box(b"mdhd", &mdhd),
+            mp4_box(b"minf", &minf),
+        ]
+        .concat();
+        let trak = mp4_box(b"mdia", &mdia);
+        let moov = mp4_box(b"trak", &trak);
+        [
+            mp4_box(b"ftyp", b"M4A \0\0\0\0M4A isom"),
+            mp4_box(b"moov", &moov),
+        ]
+        .concat()
+    }
+
+    /// Deterministic xorshift, so failures reproduce.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    #[test]
+    fn times_ogg_opus() {
+        let info = analyze(&opus_file(50)).unwrap();
+        assert_eq!(info.content_type, "audio/ogg");
+        assert_eq!(info.duration_ms, 1000);
+        assert_eq!(info.waveform.len(), WAVEFORM_BARS);
+        assert_eq!(info.waveform.last(), Some(&100));
+    }
+
+    #[test]
+    fn times_m4a() {
+        let info = analyze(&m4a_file(100)).unwrap();
+        assert_eq!(info.content_type, "audio/mp4");
+        assert_eq!(info.duration_ms, 2322);
+        assert_eq!(info.waveform.len(), WAVEFORM_BARS);
+    }
+
+    #[test]
+    fn refuses_video_mp4() {
+        let mut file = m4a_file(10);
+        file[8..12].copy_from_slice(b"isom");
+        assert!(analyze(&file).is_err());
+
+        // A dash file whose second track is video
+        let mut file = m4a_file(10);
+        file[8..12].copy_from_slice(b"dash");
+        assert!(analyze(&file).is_ok());
+        let at = file.windows(4).position(|w| w == b"soun").unwrap();
+        file[at..at + 4].copy_from_slice(b"vide");
+        let trak_at = file.windows(4).position(|w| w == b"trak").unwrap() - 4;
+        let video_trak = file[trak_at..].to_vec();
+        let mut file = m4a_file(10);
+        file[8..12].copy_from_slice(b"dash");
+        file.extend_from_slice(&video_trak);
+        let moov_at = file.windows(4).position(|w| w == b"moov").unwrap() - 4;
+        let moov_len = (file.len() - moov_at) as u32;
+        file[moov_at..moov_at + 4].copy_from_slice(&moov_len.to_be_bytes());
+        assert_eq!(
+            analyze(&file).unwrap_err(),
+            "Voice notes can't contain video"
+        );
+    }
+
+    #[test]
+    fn truncated_files_never_panic() {
+        for file in [opus_file(20), m4a_file(20)] {
+            for len in 0..file.len() {
+                let _ = analyze(&file[..len]);
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_boxes_are_rejected() {
+        // A 32-bit size running past the end of the data
+        let mut file = m4a_file(10);
+        let moov = mp4_box(b"ftyp", b"M4A \0\0\0\0M4A isom").len();
+        assert_eq!(&file[moov + 4..moov + 8], b"moov");
+        file[moov..moov + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(analyze(&file).is_err());
+
+        // A 64-bit size that overflows the offset arithmetic
+        let mut huge = vec![0, 0, 0, 1];
+        huge.extend_from_slice(b"moov");
+        huge.extend_from_slice(&u64::MAX.to_be_bytes());
+        huge.extend_from_slice(&[0; 16]);
+        assert_eq!(find_box(&huge, b"moov"), None);
+        let mut file = mp4_box(b"ftyp", b"M4A \0\0\0\0");
+        file.extend_from_slice(&huge);
+        assert!(analyze(&file).is_err());
+
+        // A box smaller than its own header
+        let mut tiny = 4u32.to_be_bytes().to_vec();
+        tiny.extend_from_slice(b"moov");
+        assert_eq!(find_box(&tiny, b"moov"), None);
+    }
+
+    #[test]
+    fn corrupted_files_never_panic() {
+        let mut rng = Rng(0x5eed_cafe_f00d_beef);
+        for file in [opus_file(20), m4a_file(20)] {
+            for _ in 0..2000 {
+                let mut mutated = file.clone();
+                for _ in 0..=(rng.next() % 4) {
+                    let at = (rng.next() as usize) % mutated.len();
+                    mutated[at] = rng.next() as u8;
+                }
+                let _ = analyze(&mutated);
+            }
+        }
+    }
+}
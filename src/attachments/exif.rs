@@ -0,0 +1,249 @@
+//! Lossless removal of embedded metadata (EXIF/XMP, which carry GPS
+//! coordinates, and PNG text chunks) from uploaded images. Pixel data is
+//! left untouched.
+
+/// Strip metadata from `data`. An image that can't be parsed is rejected
+/// rather than stored with whatever it carries; other types pass through.
+pub fn strip_metadata(content_type: &str, data: Vec<u8>) -> Result<Vec<u8>, String> {
+    let stripped = match content_type {
+        "image/jpeg" => strip_jpeg(&data),
+        "image/png" => strip_png(&data),
+        "image/webp" => strip_webp(&data),
+        _ => return Ok(data),
+    };
+    match stripped {
+        Ok(Some(stripped)) => Ok(stripped),
+        Ok(None) => Ok(data),
+        Err(e) => Err(format!("Could not remove metadata: {}", e)),
+    }
+}
+
+/// Drop APP1 segments (EXIF and XMP) from a JPEG. `None` when there were
+/// none.
+fn strip_jpeg(data: &[u8]) -> Result<Option<Vec<u8>>, &'static str> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return Err("not a JPEG");
+    }
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[..2]);
+    let mut pos = 2;
+    let mut stripped = false;
+
+    loop {
+        if pos + 2 > data.len() {
+            return Err("truncated JPEG");
+        }
+        if data[pos] != 0xFF {
+            return Err("malformed JPEG segment");
+        }
+        let marker = data[pos + 1];
+        // Start of scan: entropy-coded data follows, copy the remainder as-is
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        if pos + 4 > data.len() {
+            return Err("truncated JPEG segment");
+        }
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let end = pos + 2 + len;
+        if len < 2 || end > data.len() {
+            return Err("truncated JPEG segment");
+        }
+        if marker == 0xE1 {
+            stripped = true;
+        } else {
+            out.extend_from_slice(&data[pos..end]);
+        }
+        pos = end;
+    }
+
+    if !stripped {
+        return Ok(None);
+    }
+    out.extend_from_slice(&data[pos..]);
+    Ok(Some(out))
+}
+
+/// PNG chunks that carry metadata rather than pixels: EXIF, and the text
+/// chunks cameras and editors use for comments, locations and XMP.
+const PNG_METADATA_CHUNKS: &[&[u8; 4]] = &[b"eXIf", b"tEXt", b"zTXt", b"iTXt"];
+
+/// Drop metadata chunks from a PNG. `None` when there were none.
+fn strip_png(data: &[u8]) -> Result<Option<Vec<u8>>, &'static str> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !data.starts_with(SIGNATURE) {
+        return Err("not a PNG");
+    }
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(SIGNATURE);
+    let mut pos = SIGNATURE.len();
+    let mut stripped = false;
+
+    while pos < data.len() {
+        if pos + 8 > data.len() {
+            return Err("truncated PNG chunk");
+        }
+        let len = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]);
+        // length + type + data + crc
+        let end = (len as usize)
+            .checked_add(pos + 12)
+            .filter(|end| *end <= data.len())
+            .ok_or("truncated PNG chunk")?;
+        let kind = &data[pos + 4..pos + 8];
+        if PNG_METADATA_CHUNKS.iter().any(|k| k.as_slice() == kind) {
+            stripped = true;
+        } else {
+            out.extend_from_slice(&data[pos..end]);
+        }
+        pos = end;
+        if kind == b"IEND" {
+            // Nothing after the end is part of the image
+            stripped |= pos < data.len();
+            break;
+        }
+    }
+
+    Ok(stripped.then_some(out))
+}
+
+/// Drop `EXIF` and `XMP ` chunks from an extended-format WebP. `None` when
+/// there were none.
+fn strip_webp(data: &[u8]) -> Result<Option<Vec<u8>>, &'static str> {
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return Err("not a WebP");
+    }
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[..12]);
+    let mut pos = 12;
+    let mut stripped = false;
+
+    while pos < data.len() {
+        if pos + 8 > data.len() {
+            return Err("truncated WebP chunk");
+        }
+        let fourcc = &data[pos..pos + 4];
+        let len = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]])
+            as usize;
+        // Chunks are padded to even sizes
+        let end = (len + (len & 1))
+            .checked_add(pos + 8)
+            .filter(|end| *end <= data.len())
+            .ok_or("truncated WebP chunk")?;
+        if fourcc == b"EXIF" || fourcc == b"XMP " {
+            stripped = true;
+        } else {
+            let start = out.len();
+            out.extend_from_slice(&data[pos..end]);
+            if fourcc == b"VP8X" && len >= 1 {
+                // Clear the EXIF (0x08) and XMP (0x04) presence flags
+                out[start + 8] &= !0x0C;
+            }
+        }
+        pos = end;
+    }
+
+    if !stripped {
+        return Ok(None);
+    }
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Ok(Some(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageFormat;
+
+    // 16x12 images carrying an EXIF GPS IFD (48°51'29" N, 122°20'58" W) and
+    // XMP with the same location; the PNG also has tEXt and zTXt notes.
+    const GPS_JPEG: &[u8] = include_bytes!("testdata/gps.jpg");
+    const GPS_PNG: &[u8] = include_bytes!("testdata/gps.png");
+    const GPS_WEBP: &[u8] = include_bytes!("testdata/gps.webp");
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    /// Marks any of the fixtures' location data would leave behind.
+    fn assert_no_location(data: &[u8]) {
+        for marker in [
+            b"Exif\0\0".as_slice(),
+            b"II*\0",
+            b"GPSLatitude",
+            b"48.8581",
+            b"WhisperCam",
+        ] {
+            assert!(
+                !contains(data, marker),
+                "{:?} survived",
+                String::from_utf8_lossy(marker)
+            );
+        }
+    }
+
+    fn assert_same_pixels(original: &[u8], stripped: &[u8], format: ImageFormat) {
+        let before = image::load_from_memory_with_format(original, format).unwrap();
+        let after = image::load_from_memory_with_format(stripped, format).unwrap();
+        assert_eq!((after.width(), after.height()), (16, 12));
+        assert_eq!(before.to_rgb8(), after.to_rgb8());
+    }
+
+    #[test]
+    fn strips_exif_and_xmp_from_jpeg() {
+        assert!(contains(GPS_JPEG, b"Exif\0\0"));
+        let stripped = strip_metadata("image/jpeg", GPS_JPEG.to_vec()).unwrap();
+        assert_no_location(&stripped);
+        assert_same_pixels(GPS_JPEG, &stripped, ImageFormat::Jpeg);
+    }
+
+    #[test]
+    fn strips_exif_and_text_chunks_from_png() {
+        for chunk in PNG_METADATA_CHUNKS {
+            assert!(contains(GPS_PNG, chunk.as_slice()));
+        }
+        let stripped = strip_metadata("image/png", GPS_PNG.to_vec()).unwrap();
+        assert_no_location(&stripped);
+        for chunk in PNG_METADATA_CHUNKS {
+            assert!(!contains(&stripped, chunk.as_slice()));
+        }
+        assert_same_pixels(GPS_PNG, &stripped, ImageFormat::Png);
+    }
+
+    #[test]
+    fn strips_exif_and_xmp_from_webp() {
+        let stripped = strip_metadata("image/webp", GPS_WEBP.to_vec()).unwrap();
+        assert_no_location(&stripped);
+        assert!(!contains(&stripped, b"XMP "));
+        // Presence flags cleared, RIFF size matches the new length
+        assert_eq!(stripped[20] & 0x0C, 0);
+        let riff_size = u32::from_le_bytes(stripped[4..8].try_into().unwrap()) as usize;
+        assert_eq!(riff_size, stripped.len() - 8);
+        assert_same_pixels(GPS_WEBP, &stripped, ImageFormat::WebP);
+    }
+
+    #[test]
+    fn clean_images_pass_through_unchanged() {
+        let clean = strip_metadata("image/png", GPS_PNG.to_vec()).unwrap();
+        assert_eq!(strip_metadata("image/png", clean.clone()).unwrap(), clean);
+        let text = b"hello".to_vec();
+        assert_eq!(strip_metadata("text/plain", text.clone()).unwrap(), text);
+    }
+
+    #[test]
+    fn truncated_images_are_rejected() {
+        for (content_type, data) in [
+            ("image/jpeg", GPS_JPEG),
+            ("image/png", GPS_PNG),
+            ("image/webp", GPS_WEBP),
+        ] {
+            // Cut inside the EXIF block, so a lenient parser would keep it
+            let cut = &data[..60];
+            assert!(
+                strip_metadata(content_type, cut.to_vec()).is_err(),
+                "{} accepted",
+                content_type
+            );
+        }
+    }
+}
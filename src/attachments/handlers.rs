@@ -0,0 +1,367 @@
+use crate::attachments::audio;
+use crate::attachments::exif::strip_metadata;
+use crate::attachments::processing::{
+    needs_processing, MediaProcessor, STATUS_PENDING, STATUS_READY, STATUS_SKIPPED,
+};
+use crate::attachments::signing;
+use crate::attachments::storage::{StorageBackend, StorageError};
+use crate::attachments::types::{
+    AttachmentPayload, DownloadParams, MessageFrame, SignedUrlResponse, UploadResponse, UrlParams,
+    Variant,
+};
+use crate::attachments::validation::validate_upload;
+use crate::auth::CurrentUser;
+use crate::blocks::{is_blocked, BLOCKED_STATUS};
+use crate::entity::{attachments, messages, users, Attachments, Messages, Users};
+use crate::error::AppError;
+use crate::messages::UNREAD_STATUS;
+use crate::retention::RetentionPolicy;
+use crate::sync::SyncEvent;
+use crate::webhooks::types::MessageData;
+use crate::webhooks::{WebhookEvent, MESSAGE_CREATED};
+use crate::ws::{mark_as_delivered, SharedState};
+use axum::{
+    extract::{FromRef, Multipart, Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{Duration, TimeZone, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct AttachmentsState {
+    pub db: DatabaseConnection,
+    pub online: SharedState,
+    pub storage: Arc<dyn StorageBackend>,
+    pub processor: MediaProcessor,
+    pub signing_key: Arc<str>,
+    pub max_bytes: usize,
+    pub url_ttl: Duration,
+    pub retention: RetentionPolicy,
+}
+
+impl FromRef<AttachmentsState> for DatabaseConnection {
+    fn from_ref(state: &AttachmentsState) -> Self {
+        state.db.clone()
+    }
+}
+
+fn bad_request<E: std::fmt::Display>(e: E) -> AppError {
+    AppError::bad_request(e.to_string())
+}
+
+struct UploadedFile {
+    file_name: String,
+    declared_type: Option<String>,
+    data: Vec<u8>,
+}
+
+/// `POST /attachments` — multipart form with `recipient`, optional `caption`
+/// and a single `file` part. Creates the message and delivers it like a WS send.
+pub async fn upload_attachment(
+    State(state): State<AttachmentsState>,
+    CurrentUser(sender): CurrentUser,
+    mut multipart: Multipart,
+) -> Result<(StatusCode, Json<UploadResponse>), AppError> {
+    let mut recipient = None;
+    let mut caption = String::new();
+    let mut file = None;
+
+    while let Some(mut field) = multipart.next_field().await.map_err(bad_request)? {
+        match field.name() {
+            Some("recipient") => recipient = Some(field.text().await.map_err(bad_request)?),
+            Some("caption") => caption = field.text().await.map_err(bad_request)?,
+            Some("file") => {
+                let file_name = field
+                    .file_name()
+                    .map(sanitize_file_name)
+                    .unwrap_or_else(|| "attachment".to_string());
+                let declared_type = field.content_type().map(str::to_string);
+                let mut data = Vec::new();
+                while let Some(chunk) = field.chunk().await.map_err(bad_request)? {
+                    if data.len() + chunk.len() > state.max_bytes {
+                        return Err(AppError::PayloadTooLarge(format!(
+                            "Attachments are limited to {} bytes",
+                            state.max_bytes
+                        )));
+                    }
+                    data.extend_from_slice(&chunk);
+                }
+                file = Some(UploadedFile {
+                    file_name,
+                    declared_type,
+                    data,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    let recipient = recipient.ok_or_else(|| bad_request("Missing `recipient` field"))?;
+    let file = file.ok_or_else(|| bad_request("Missing `file` field"))?;
+    if file.data.is_empty() {
+        return Err(bad_request("Empty file"));
+    }
+    let content_type = validate_upload(file.declared_type.as_deref(), &file.data)
+        .map_err(AppError::UnsupportedMediaType)?;
+
+    let recipient_user = Users::find()
+        .filter(users::Column::Username.eq(recipient.trim()))
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("User '{}'", recipient.trim())))?;
+
+    if is_blocked(&state.db, sender.id, recipient_user.id).await? {
+        return Err(AppError::forbidden(format!(
+            "You have blocked user '{}'",
+            recipient_user.username
+        )));
+    }
+    // Looks like a normal send to the sender, but never reaches the recipient
+    let hidden = is_blocked(&state.db, recipient_user.id, sender.id).await?;
+
+    // Audio metadata is parsed from the container, cheap enough to do inline
+    // so the first delivered frame already carries duration and waveform
+    let voice = if content_type.starts_with("audio/") {
+        Some(audio::analyze(&file.data).map_err(AppError::Unprocessable)?)
+    } else {
+        None
+    };
+
+    // Location data must never reach storage, so this runs inline rather than
+    // in the background pipeline
+    let data = strip_metadata(content_type, file.data).map_err(AppError::Unprocessable)?;
+
+    let storage_key = format!("attachments/{}", Uuid::new_v4());
+    let size_bytes = data.len() as i64;
+    state
+        .storage
+        .put(&storage_key, content_type, data)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    let expires_at = state
+        .retention
+        .expires_at(&state.db, sender.id, recipient_user.id)
+        .await?;
+    let message = messages::ActiveModel {
+        sender_id: Set(sender.id),
+        receiver_id: Set(recipient_user.id),
+        message: Set(caption),
+        status: Set(if hidden {
+            BLOCKED_STATUS.to_string()
+        } else {
+            UNREAD_STATUS.to_string()
+        }),
+        expires_at: Set(expires_at),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await?;
+
+    let attachment = attachments::ActiveModel {
+        message_id: Set(Some(message.id)),
+        uploader_id: Set(sender.id),
+        file_name: Set(file.file_name),
+        content_type: Set(content_type.to_string()),
+        size_bytes: Set(size_bytes),
+        storage_key: Set(storage_key),
+        duration_ms: Set(voice.as_ref().map(|v| v.duration_ms as i32)),
+        waveform: Set(voice.as_ref().map(|v| serde_json::json!(v.waveform))),
+        processing_status: Set(if needs_processing(content_type) {
+            STATUS_PENDING.to_string()
+        } else if voice.is_some() {
+            STATUS_READY.to_string()
+        } else {
+            STATUS_SKIPPED.to_string()
+        }),
+        processing_attempts: Set(0),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await?;
+    if attachment.processing_status == STATUS_PENDING {
+        state.processor.notify();
+    }
+    if !hidden {
+        WebhookEvent::new(
+            MESSAGE_CREATED,
+            MessageData {
+                attachment_ids: vec![attachment.id],
+                ..MessageData::new(&message, &sender.username, &recipient_user.username)
+            },
+        )
+        .emit(&state.db)
+        .await;
+    }
+
+    info!(
+        "📎 {} sent attachment {} to {}",
+        sender.username, attachment.id, recipient_user.username
+    );
+
+    let frame = MessageFrame::new(
+        &sender.username,
+        &message,
+        std::slice::from_ref(&attachment),
+    )
+    .to_text();
+    let pushed = !hidden
+        && SyncEvent::new(frame.clone())
+            .message_id(message.id)
+            .publish(
+                &state.db,
+                &state.online,
+                recipient_user.id,
+                &recipient_user.username,
+            )
+            .await;
+    if recipient_user.id != sender.id {
+        SyncEvent::new(frame)
+            .message_id(message.id)
+            .publish(&state.db, &state.online, sender.id, &sender.username)
+            .await;
+    }
+    if pushed {
+        mark_as_delivered(&state.db, &state.online, &sender, &recipient_user).await?;
+    }
+
+    Ok((
+        StatusCode::CREATED,
+        Json(UploadResponse {
+            message_id: message.id,
+            attachment: AttachmentPayload::from(&attachment),
+        }),
+    ))
+}
+
+/// `GET /attachments/:id/url?variant=` — issue a short-lived download link
+/// bound to the caller.
+pub async fn attachment_url(
+    State(state): State<AttachmentsState>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i32>,
+    Query(UrlParams { variant }): Query<UrlParams>,
+) -> Result<Json<SignedUrlResponse>, AppError> {
+    let attachment = find_for_participant(&state.db, id, user.id).await?;
+    if variant == Variant::Thumbnail && attachment.thumbnail_key.is_none() {
+        return Err(AppError::not_found("Thumbnail"));
+    }
+
+    let expires_at = Utc::now() + state.url_ttl;
+    let expires = expires_at.timestamp();
+    let sig = signing::sign(&state.signing_key, attachment.id, user.id, expires, variant);
+
+    Ok(Json(SignedUrlResponse {
+        url: format!(
+            "/attachments/{}/download?user={}&expires={}&variant={}&sig={}",
+            attachment.id,
+            user.id,
+            expires,
+            variant.as_str(),
+            sig
+        ),
+        expires_at,
+    }))
+}
+
+/// `GET /attachments/:id/download` — serve the body for a valid, unexpired signed link.
+pub async fn download_attachment(
+    State(state): State<AttachmentsState>,
+    Path(id): Path<i32>,
+    Query(params): Query<DownloadParams>,
+) -> Result<Response, AppError> {
+    let denied = || AppError::forbidden("Invalid or expired link");
+
+    if !signing::verify(
+        &state.signing_key,
+        id,
+        params.user,
+        params.expires,
+        params.variant,
+        &params.sig,
+    ) {
+        return Err(denied());
+    }
+    let expires_at = Utc
+        .timestamp_opt(params.expires, 0)
+        .single()
+        .ok_or_else(denied)?;
+    if expires_at < Utc::now() {
+        return Err(denied());
+    }
+
+    // Re-check participation in case the link outlived the user's access
+    let attachment = find_for_participant(&state.db, id, params.user).await?;
+
+    let (key, content_type) = match params.variant {
+        Variant::Original => (attachment.storage_key, attachment.content_type),
+        Variant::Thumbnail => (
+            attachment.thumbnail_key.ok_or_else(denied)?,
+            "image/jpeg".to_string(),
+        ),
+    };
+    let data = state.storage.get(&key).await.map_err(|e| match e {
+        StorageError::NotFound(_) => AppError::not_found("Attachment"),
+        e => AppError::Internal(e.to_string()),
+    })?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", attachment.file_name),
+            ),
+            (header::CACHE_CONTROL, "private, no-store".to_string()),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+/// Load an attachment, failing with 404 unless `user_id` is a participant of
+/// the conversation its message belongs to.
+async fn find_for_participant(
+    db: &DatabaseConnection,
+    attachment_id: i32,
+    user_id: i32,
+) -> Result<attachments::Model, AppError> {
+    let not_found = || AppError::not_found("Attachment");
+
+    let attachment = Attachments::find_by_id(attachment_id)
+        .one(db)
+        .await?
+        .ok_or_else(not_found)?;
+
+    let message = match attachment.message_id {
+        Some(message_id) => Messages::find_by_id(message_id).one(db).await?,
+        None => None,
+    };
+
+    match message {
+        Some(m) if m.sender_id == user_id => Ok(attachment),
+        Some(m) if m.receiver_id == user_id && m.status != BLOCKED_STATUS => Ok(attachment),
+        None if attachment.uploader_id == user_id => Ok(attachment),
+        _ => Err(not_found()),
+    }
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let cleaned: String = base
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(255)
+        .collect();
+    if cleaned.is_empty() {
+        "attachment".to_string()
+    } else {
+        cleaned
+    }
+}
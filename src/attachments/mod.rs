@@ -0,0 +1,11 @@
+pub mod audio;
+pub mod exif;
+pub mod handlers;
+pub mod processing;
+pub mod routes;
+pub mod signing;
+pub mod storage;
+pub mod types;
+pub mod validation;
+pub use processing::MediaProcessor;
+pub use storage::{delete_blobs, storage_from_config, StorageBackend};
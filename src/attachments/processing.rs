@@ -0,0 +1,397 @@
+//! Background media pipeline. Uploads are queued as `pending` rows in
+//! `attachments`; a worker claims them, extracts dimensions, a thumbnail and a
+//! blurhash placeholder, and retries failures with exponential backoff.
+
+use crate::attachments::storage::StorageBackend;
+use crate::attachments::types::AttachmentUpdatedFrame;
+use crate::blocks::BLOCKED_STATUS;
+use crate::entity::{attachments, users, Attachments, Messages, Users};
+use crate::shutdown::Shutdown;
+use crate::sync::SyncEvent;
+use crate::ws::SharedState;
+use chrono::{Duration, Utc};
+use image::{GenericImageView, ImageFormat};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait,
+    QueryFilter, QueryOrder, QuerySelect, Set,
+};
+use std::io::Cursor;
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+const MAX_ATTEMPTS: i32 = 5;
+const BATCH_SIZE: u64 = 10;
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+/// A claim this old belongs to an instance that died mid-job.
+const STALE_AFTER: Duration = Duration::minutes(5);
+const THUMBNAIL_SIZE: u32 = 320;
+const BLURHASH_COMPONENTS: (u32, u32) = (4, 3);
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_PROCESSING: &str = "processing";
+pub const STATUS_READY: &str = "ready";
+pub const STATUS_FAILED: &str = "failed";
+pub const STATUS_SKIPPED: &str = "skipped";
+
+/// Whether the pipeline has anything to do for this content type.
+pub fn needs_processing(content_type: &str) -> bool {
+    content_type.starts_with("image/")
+}
+
+#[derive(Clone)]
+pub struct MediaProcessor {
+    db: DatabaseConnection,
+    storage: Arc<dyn StorageBackend>,
+    online: SharedState,
+    wake: Arc<Notify>,
+}
+
+struct ImageInfo {
+    width: u32,
+    height: u32,
+    content_type: &'static str,
+    thumbnail: Vec<u8>,
+    blurhash: String,
+}
+
+impl MediaProcessor {
+    pub fn new(
+        db: DatabaseConnection,
+        storage: Arc<dyn StorageBackend>,
+        online: SharedState,
+    ) -> Self {
+        Self {
+            db,
+            storage,
+            online,
+            wake: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Nudge the worker after enqueuing work instead of waiting for the next poll.
+    pub fn notify(&self) {
+        self.wake.notify_one();
+    }
+
+    pub fn spawn(self, shutdown: &Shutdown) -> tokio::task::JoinHandle<()> {
+        let shutdown = shutdown.clone();
+        shutdown.clone().spawn(async move {
+            while !shutdown.is_draining() {
+                if let Err(e) = self.requeue_stale().await {
+                    warn!("Media worker failed to check stale claims: {}", e);
+                }
+                match self.run_batch().await {
+                    // A full batch suggests more work is queued
+                    Ok(n) if n as u64 == BATCH_SIZE => continue,
+                    Ok(_) => {}
+                    Err(e) => warn!("Media worker failed to poll queue: {}", e),
+                }
+                tokio::select! {
+                    _ = shutdown.cancelled() => {}
+                    _ = self.wake.notified() => {}
+                    _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                }
+            }
+        })
+    }
+
+    /// A row stuck in `processing` lost its worker, possibly to the image
+    /// itself crashing the process, so the interruption counts as a failed
+    /// attempt. Rows claimed before claims were timestamped have no
+    /// `claimed_at` and are stale by definition.
+    async fn requeue_stale(&self) -> Result<(), sea_orm::DbErr> {
+        let stale = Condition::all()
+            .add(attachments::Column::ProcessingStatus.eq(STATUS_PROCESSING))
+            .add(
+                Condition::any()
+                    .add(attachments::Column::ClaimedAt.is_null())
+                    .add(attachments::Column::ClaimedAt.lt(Utc::now() - STALE_AFTER)),
+            );
+        let failed = Attachments::update_many()
+            .col_expr(
+                attachments::Column::ProcessingStatus,
+                Expr::value(STATUS_FAILED),
+            )
+            .col_expr(
+                attachments::Column::ProcessingAttempts,
+                Expr::col(attachments::Column::ProcessingAttempts).add(1),
+            )
+            .col_expr(
+                attachments::Column::ProcessingError,
+                Expr::value("Processing was interrupted"),
+            )
+            .col_expr(
+                attachments::Column::ClaimedAt,
+                Expr::value(Option::<chrono::DateTime<Utc>>::None),
+            )
+            .filter(stale.clone())
+            .filter(attachments::Column::ProcessingAttempts.gte(MAX_ATTEMPTS - 1))
+            .exec_with_returning(&self.db)
+            .await?;
+        let requeued = Attachments::update_many()
+            .col_expr(
+                attachments::Column::ProcessingStatus,
+                Expr::value(STATUS_PENDING),
+            )
+            .col_expr(
+                attachments::Column::ProcessingAttempts,
+                Expr::col(attachments::Column::ProcessingAttempts).add(1),
+            )
+            .col_expr(
+                attachments::Column::ClaimedAt,
+                Expr::value(Option::<chrono::DateTime<Utc>>::None),
+            )
+            .filter(stale)
+            .exec(&self.db)
+            .await?
+            .rows_affected;
+        if requeued > 0 || !failed.is_empty() {
+            warn!(
+                "Requeued {} and gave up on {} interrupted attachment job(s)",
+                requeued,
+                failed.len()
+            );
+        }
+        for attachment in &failed {
+            self.announce(attachment).await?;
+        }
+        Ok(())
+    }
+
+    async fn run_batch(&self) -> Result<usize, sea_orm::DbErr> {
+        let now = Utc::now();
+        let due = Attachments::find()
+            .filter(attachments::Column::ProcessingStatus.eq(STATUS_PENDING))
+            .filter(
+                Condition::any()
+                    .add(attachments::Column::NextAttemptAt.is_null())
+                    .add(attachments::Column::NextAttemptAt.lte(now)),
+            )
+            .order_by_asc(attachments::Column::Id)
+            .limit(BATCH_SIZE)
+            .all(&self.db)
+            .await?;
+
+        let count = due.len();
+        for job in due {
+            if self.claim(job.id).await? {
+                self.process(job).await?;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Atomically move a job from pending to processing so that concurrent
+    /// workers (or server instances) never pick up the same row.
+    async fn claim(&self, id: i32) -> Result<bool, sea_orm::DbErr> {
+        let result = Attachments::update_many()
+            .col_expr(
+                attachments::Column::ProcessingStatus,
+                Expr::value(STATUS_PROCESSING),
+            )
+            .col_expr(attachments::Column::ClaimedAt, Expr::value(Utc::now()))
+            .filter(attachments::Column::Id.eq(id))
+            .filter(attachments::Column::ProcessingStatus.eq(STATUS_PENDING))
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected == 1)
+    }
+
+    async fn process(&self, job: attachments::Model) -> Result<(), sea_orm::DbErr> {
+        let attempts = job.processing_attempts + 1;
+        let mut update: attachments::ActiveModel = job.clone().into();
+        update.processing_attempts = Set(attempts);
+        update.claimed_at = Set(None);
+
+        match self.extract(&job).await {
+            Ok(info) => {
+                update.width = Set(Some(info.width as i32));
+                update.height = Set(Some(info.height as i32));
+                update.content_type = Set(info.content_type.to_string());
+                update.blurhash = Set(Some(info.blurhash));
+                update.thumbnail_key = Set(Some(thumbnail_key(&job.storage_key)));
+                update.processing_status = Set(STATUS_READY.to_string());
+                update.processing_error = Set(None);
+                update.next_attempt_at = Set(None);
+            }
+            Err(e) if attempts >= MAX_ATTEMPTS => {
+                warn!("Giving up on attachment {}: {}", job.id, e);
+                update.processing_status = Set(STATUS_FAILED.to_string());
+                update.processing_error = Set(Some(e));
+            }
+            Err(e) => {
+                let delay = Duration::seconds(10 * 2i64.pow(attempts as u32 - 1));
+                info!(
+                    "Processing attachment {} failed (attempt {}), retrying in {}s: {}",
+                    job.id,
+                    attempts,
+                    delay.num_seconds(),
+                    e
+                );
+                update.processing_status = Set(STATUS_PENDING.to_string());
+                update.processing_error = Set(Some(e));
+                update.next_attempt_at = Set(Some(Utc::now() + delay));
+            }
+        }
+
+        let updated = update.update(&self.db).await?;
+        if updated.processing_status != STATUS_PENDING {
+            self.announce(&updated).await?;
+        }
+        Ok(())
+    }
+
+    async fn extract(&self, job: &attachments::Model) -> Result<ImageInfo, String> {
+        let data = self
+            .storage
+            .get(&job.storage_key)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // Decoding and resizing are CPU-bound, keep them off the async workers
+        let info = tokio::task::spawn_blocking(move || analyze_image(&data))
+            .await
+            .map_err(|e| e.to_string())??;
+
+        self.storage
+            .put(
+                &thumbnail_key(&job.storage_key),
+                "image/jpeg",
+                info.thumbnail.clone(),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(info)
+    }
+
+    /// Tell online participants that the attachment's metadata changed.
+    async fn announce(&self, attachment: &attachments::Model) -> Result<(), sea_orm::DbErr> {
+        let Some(message_id) = attachment.message_id else {
+            return Ok(());
+        };
+        let Some(message) = Messages::find_by_id(message_id).one(&self.db).await? else {
+            return Ok(());
+        };
+
+        let frame = AttachmentUpdatedFrame::new(message_id, attachment).to_text();
+        // Hidden messages from a blocked sender are only visible to the sender
+        let mut participants = vec![message.sender_id];
+        if message.status != BLOCKED_STATUS {
+            participants.push(message.receiver_id);
+        }
+        let participants = Users::find()
+            .filter(users::Column::Id.is_in(participants))
+            .all(&self.db)
+            .await?;
+        for user in participants {
+            SyncEvent::new(frame.clone())
+                .message_id(message_id)
+                .publish(&self.db, &self.online, user.id, &user.username)
+                .await;
+        }
+        Ok(())
+    }
+}
+
+pub fn thumbnail_key(storage_key: &str) -> String {
+    format!("{}.thumb.jpg", storage_key)
+}
+
+fn analyze_image(data: &[u8]) -> Result<ImageInfo, String> {
+    let format = image::guess_format(data).map_err(|e| e.to_string())?;
+    let content_type = match format {
+        ImageFormat::Jpeg => "image/jpeg",
+        ImageFormat::Png => "image/png",
+        ImageFormat::Gif => "image/gif",
+        ImageFormat::WebP => "image/webp",
+        other => return Err(format!("unsupported image format {:?}", other)),
+    };
+    let img = image::load_from_memory_with_format(data, format).map_err(|e| e.to_string())?;
+    let (width, height) = img.dimensions();
+
+    let thumb = img.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+    let mut thumbnail = Vec::new();
+    thumb
+        .to_rgb8()
+        .write_to(&mut Cursor::new(&mut thumbnail), ImageFormat::Jpeg)
+        .map_err(|e| e.to_string())?;
+
+    // The placeholder is blurry by design, computing it on the thumbnail is plenty
+    let rgba = thumb.to_rgba8();
+    let blurhash = blurhash::encode(
+        BLURHASH_COMPONENTS.0,
+        BLURHASH_COMPONENTS.1,
+        rgba.width(),
+        rgba.height(),
+        rgba.as_raw(),
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(ImageInfo {
+        width,
+        height,
+        content_type,
+        thumbnail,
+        blurhash,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::storage::FsStorage;
+    use crate::test_support::{create_user, test_db};
+
+    async fn stuck_job(db: &DatabaseConnection, uploader_id: i32, attempts: i32) -> i32 {
+        attachments::ActiveModel {
+            uploader_id: Set(uploader_id),
+            file_name: Set("photo.jpg".to_string()),
+            content_type: Set("image/jpeg".to_string()),
+            size_bytes: Set(1),
+            storage_key: Set(format!("attachments/{}", uuid::Uuid::new_v4())),
+            processing_status: Set(STATUS_PROCESSING.to_string()),
+            processing_attempts: Set(attempts),
+            claimed_at: Set(Some(Utc::now() - STALE_AFTER - Duration::minutes(1))),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap()
+        .id
+    }
+
+    #[tokio::test]
+    async fn stale_claims_are_requeued_until_attempts_run_out() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let uploader = create_user(&db, "uploader").await;
+        let retried = stuck_job(&db, uploader.id, 0).await;
+        let exhausted = stuck_job(&db, uploader.id, MAX_ATTEMPTS - 1).await;
+        let processor = MediaProcessor::new(
+            db.clone(),
+            Arc::new(FsStorage::new(std::env::temp_dir())),
+            SharedState::default(),
+        );
+
+        processor.requeue_stale().await.unwrap();
+
+        let retried = Attachments::find_by_id(retried)
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(retried.processing_status, STATUS_PENDING);
+        assert_eq!(retried.processing_attempts, 1);
+        assert_eq!(retried.claimed_at, None);
+        let exhausted = Attachments::find_by_id(exhausted)
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(exhausted.processing_status, STATUS_FAILED);
+        assert_eq!(exhausted.processing_attempts, MAX_ATTEMPTS);
+    }
+}
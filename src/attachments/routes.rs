@@ -0,0 +1,21 @@
+use axum::{
+    extract::DefaultBodyLimit,
+    routing::{get, post},
+    Router,
+};
+
+use crate::attachments::handlers::{
+    attachment_url, download_attachment, upload_attachment, AttachmentsState,
+};
+
+pub fn configure_attachment_routes(state: AttachmentsState) -> Router {
+    // Leave headroom over the file limit for the other multipart fields
+    let body_limit = state.max_bytes + 64 * 1024;
+
+    Router::new()
+        .route("/attachments", post(upload_attachment))
+        .route("/attachments/:id/url", get(attachment_url))
+        .route("/attachments/:id/download", get(download_attachment))
+        .layer(DefaultBodyLimit::max(body_limit))
+        .with_state(state)
+}
@@ -0,0 +1,46 @@
+use crate::attachments::types::Variant;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn mac(key: &str, attachment_id: i32, user_id: i32, expires: i64, variant: Variant) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(
+        format!(
+            "{}:{}:{}:{}",
+            attachment_id,
+            user_id,
+            expires,
+            variant.as_str()
+        )
+        .as_bytes(),
+    );
+    mac
+}
+
+/// Signature binding a download to one attachment rendition, one user and an
+/// expiry (unix seconds).
+pub fn sign(key: &str, attachment_id: i32, user_id: i32, expires: i64, variant: Variant) -> String {
+    hex::encode(
+        mac(key, attachment_id, user_id, expires, variant)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+pub fn verify(
+    key: &str,
+    attachment_id: i32,
+    user_id: i32,
+    expires: i64,
+    variant: Variant,
+    signature: &str,
+) -> bool {
+    match hex::decode(signature) {
+        Ok(bytes) => mac(key, attachment_id, user_id, expires, variant)
+            .verify_slice(&bytes)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,149 @@
+use crate::entity::{attachments, messages};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AttachmentPayload {
+    pub id: i32,
+    /// `image`, `voice` or `file`, so clients can pick a renderer
+    pub kind: &'static str,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub processing_status: String,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub blurhash: Option<String>,
+    pub has_thumbnail: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub waveform: Option<Vec<u8>>,
+}
+
+impl From<&attachments::Model> for AttachmentPayload {
+    fn from(model: &attachments::Model) -> Self {
+        let kind = if model.content_type.starts_with("image/") {
+            "image"
+        } else if model.content_type.starts_with("audio/") {
+            "voice"
+        } else {
+            "file"
+        };
+        Self {
+            id: model.id,
+            kind,
+            file_name: model.file_name.clone(),
+            content_type: model.content_type.clone(),
+            size_bytes: model.size_bytes,
+            processing_status: model.processing_status.clone(),
+            width: model.width,
+            height: model.height,
+            blurhash: model.blurhash.clone(),
+            has_thumbnail: model.thumbnail_key.is_some(),
+            duration_ms: model.duration_ms,
+            waveform: model
+                .waveform
+                .clone()
+                .and_then(|w| serde_json::from_value(w).ok()),
+        }
+    }
+}
+
+/// JSON frame for a message that carries attachments. Plain text messages
+/// keep the `sender: text` line format.
+#[derive(Debug, Serialize)]
+pub struct MessageFrame {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub id: i32,
+    pub from: String,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+    pub attachments: Vec<AttachmentPayload>,
+}
+
+impl MessageFrame {
+    pub fn new(from: &str, message: &messages::Model, attachments: &[attachments::Model]) -> Self {
+        Self {
+            kind: "message",
+            id: message.id,
+            from: from.to_string(),
+            text: message.message.clone(),
+            created_at: message.created_at,
+            attachments: attachments.iter().map(AttachmentPayload::from).collect(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).expect("message frame serializes")
+    }
+}
+
+/// Sent to participants once background processing of an attachment finishes.
+#[derive(Debug, Serialize)]
+pub struct AttachmentUpdatedFrame {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub message_id: i32,
+    pub attachment: AttachmentPayload,
+}
+
+impl AttachmentUpdatedFrame {
+    pub fn new(message_id: i32, attachment: &attachments::Model) -> Self {
+        Self {
+            kind: "attachment_updated",
+            message_id,
+            attachment: AttachmentPayload::from(attachment),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).expect("attachment frame serializes")
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadResponse {
+    pub message_id: i32,
+    pub attachment: AttachmentPayload,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SignedUrlResponse {
+    pub url: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Which stored rendition of an attachment to fetch.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Variant {
+    #[default]
+    Original,
+    Thumbnail,
+}
+
+impl Variant {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Variant::Original => "original",
+            Variant::Thumbnail => "thumbnail",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UrlParams {
+    #[serde(default)]
+    pub variant: Variant,
+}
+
+#[derive(Deserialize)]
+pub struct DownloadParams {
+    pub user: i32,
+    pub expires: i64,
+    #[serde(default)]
+    pub variant: Variant,
+    pub sig: String,
+}
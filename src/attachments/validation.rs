@@ -0,0 +1,159 @@
+/// Content types accepted for upload.
+pub const ALLOWED_CONTENT_TYPES: &[&str] = &[
+    "image/jpeg",
+    "image/png",
+    "image/gif",
+    "image/webp",
+    "application/pdf",
+    "text/plain",
+    "audio/ogg",
+    "audio/mp4",
+];
+
+/// Major brands of MP4 files made for audio alone. Generic brands like
+/// `isom` and `mp42` are mostly video. `dash` segments can be either, so
+/// `audio::analyze` also refuses files with a video track.
+pub const AUDIO_MP4_BRANDS: &[&[u8; 4]] = &[b"M4A ", b"M4B ", b"dash"];
+
+/// Common alternative spellings clients send for the allowed types.
+fn canonical_content_type(declared: &str) -> &str {
+    match declared {
+        "audio/opus" | "audio/x-opus+ogg" => "audio/ogg",
+        "audio/m4a" | "audio/x-m4a" | "audio/aac" => "audio/mp4",
+        "image/jpg" => "image/jpeg",
+        other => other,
+    }
+}
+
+/// Detect the content type from the leading bytes for formats with a known signature.
+pub fn sniff_content_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\xFF\xD8\xFF") {
+        Some("image/jpeg")
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if data.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else if data.starts_with(b"OggS") {
+        Some("audio/ogg")
+    } else if data.len() >= 12
+        && &data[4..8] == b"ftyp"
+        && AUDIO_MP4_BRANDS
+            .iter()
+            .any(|brand| &data[8..12] == brand.as_slice())
+    {
+        Some("audio/mp4")
+    } else {
+        None
+    }
+}
+
+/// Resolve the effective content type of an upload, rejecting anything not
+/// on the allowlist or whose bytes contradict the declared type.
+pub fn validate_upload(declared: Option<&str>, data: &[u8]) -> Result<&'static str, String> {
+    let sniffed = sniff_content_type(data);
+    let declared = declared
+        .map(|d| d.split(';').next().unwrap_or(d).trim().to_ascii_lowercase())
+        .map(|d| canonical_content_type(&d).to_string())
+        .filter(|d| d != "application/octet-stream");
+
+    let content_type = match (declared.as_deref(), sniffed) {
+        (Some(d), Some(s)) if d != s => {
+            return Err(format!(
+                "Declared type {} does not match content ({})",
+                d, s
+            ))
+        }
+        (_, Some(s)) => s,
+        (Some(d), None) => ALLOWED_CONTENT_TYPES
+            .iter()
+            .copied()
+            .find(|allowed| *allowed == d)
+            .ok_or_else(|| format!("Content type {} is not allowed", d))?,
+        (None, None) => return Err("Unable to determine content type".to_string()),
+    };
+
+    // Types with a known signature must actually carry it
+    if content_type != "text/plain" && sniffed.is_none() {
+        return Err(format!("Content does not look like {}", content_type));
+    }
+    if content_type == "text/plain" && std::str::from_utf8(data).is_err() {
+        return Err("text/plain uploads must be valid UTF-8".to_string());
+    }
+
+    Ok(content_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    fn ftyp(brand: &[u8; 4]) -> Vec<u8> {
+        let mut data = 16u32.to_be_bytes().to_vec();
+        data.extend_from_slice(b"ftyp");
+        data.extend_from_slice(brand);
+        data.extend_from_slice(&[0; 4]);
+        data
+    }
+
+    #[test]
+    fn sniffs_known_signatures() {
+        assert_eq!(sniff_content_type(b"\xFF\xD8\xFF\xE0"), Some("image/jpeg"));
+        assert_eq!(sniff_content_type(PNG), Some("image/png"));
+        assert_eq!(sniff_content_type(b"GIF89a..."), Some("image/gif"));
+        assert_eq!(
+            sniff_content_type(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(sniff_content_type(b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(sniff_content_type(b"OggS\0\x02"), Some("audio/ogg"));
+        assert_eq!(sniff_content_type(b"hello"), None);
+    }
+
+    #[test]
+    fn only_audio_mp4_brands_sniff_as_audio() {
+        for brand in AUDIO_MP4_BRANDS {
+            assert_eq!(sniff_content_type(&ftyp(brand)), Some("audio/mp4"));
+        }
+        for brand in [b"isom", b"mp42", b"avc1", b"qt  ", b"3gp4"] {
+            assert_eq!(sniff_content_type(&ftyp(brand)), None, "{:?}", brand);
+        }
+    }
+
+    #[test]
+    fn accepts_matching_or_missing_declarations() {
+        assert_eq!(validate_upload(Some("image/png"), PNG), Ok("image/png"));
+        assert_eq!(validate_upload(None, PNG), Ok("image/png"));
+        assert_eq!(
+            validate_upload(Some("application/octet-stream"), PNG),
+            Ok("image/png")
+        );
+        assert_eq!(
+            validate_upload(Some("audio/x-m4a"), &ftyp(b"M4A ")),
+            Ok("audio/mp4")
+        );
+        assert_eq!(
+            validate_upload(Some("text/plain; charset=utf-8"), b"hi"),
+            Ok("text/plain")
+        );
+    }
+
+    #[test]
+    fn rejects_mismatches_and_unknown_types() {
+        // Bytes contradict the declared type
+        assert!(validate_upload(Some("image/jpeg"), PNG).is_err());
+        // An HTML page passed off as text with a script
+        assert!(validate_upload(Some("text/html"), b"<script>").is_err());
+        // Declared type with a signature the bytes don't carry
+        assert!(validate_upload(Some("image/png"), b"not a png").is_err());
+        // A video passed off as a voice note
+        assert!(validate_upload(Some("audio/mp4"), &ftyp(b"isom")).is_err());
+        assert!(validate_upload(Some("text/plain"), b"\xFF\xFE\xFD").is_err());
+        assert!(validate_upload(None, b"hello").is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use crate::config::TrustedProxies;
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::request::Parts,
+};
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+
+/// Best-effort client address for the audit log: the socket peer, unless the
+/// peer is one of `TRUSTED_PROXIES`. Then the nearest `X-Forwarded-For` hop
+/// that isn't a trusted proxy is the client, since anything to the left of it
+/// was written by the client itself.
+pub struct ClientIp(pub Option<String>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(peer) = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip())
+        else {
+            return Ok(ClientIp(None));
+        };
+        let trusted = parts
+            .extensions
+            .get::<TrustedProxies>()
+            .cloned()
+            .unwrap_or_default();
+        if !trusted.contains(&peer) {
+            return Ok(ClientIp(Some(peer.to_string())));
+        }
+
+        let header = |name: &str| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let forwarded = header("x-forwarded-for").map(|list| {
+            let hops: Vec<&str> = list
+                .split(',')
+                .map(str::trim)
+                .filter(|hop| !hop.is_empty())
+                .collect();
+            hops.iter()
+                .rev()
+                .find(|hop| {
+                    hop.parse::<IpAddr>()
+                        .map_or(true, |ip| !trusted.contains(&ip))
+                })
+                .or(hops.first())
+                .map(|hop| hop.to_string())
+        });
+        let ip = forwarded
+            .flatten()
+            .or_else(|| header("x-real-ip").map(|v| v.trim().to_string()))
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| peer.to_string());
+        Ok(ClientIp(Some(ip)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+
+    async fn client_ip(peer: &str, trusted: &[&str], headers: &[(&str, &str)]) -> Option<String> {
+        let mut request = Request::builder();
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let (mut parts, ()) = request.body(()).unwrap().into_parts();
+        parts
+            .extensions
+            .insert(ConnectInfo(SocketAddr::new(peer.parse().unwrap(), 443)));
+        parts.extensions.insert(TrustedProxies(
+            trusted.iter().map(|net| net.parse().unwrap()).collect(),
+        ));
+        let ClientIp(ip) = ClientIp::from_request_parts(&mut parts, &()).await.unwrap();
+        ip
+    }
+
+    #[tokio::test]
+    async fn untrusted_peers_cannot_claim_an_address() {
+        let ip = client_ip(
+            "203.0.113.9",
+            &["10.0.0.0/8"],
+            &[("x-forwarded-for", "1.2.3.4"), ("x-real-ip", "1.2.3.4")],
+        )
+        .await;
+        assert_eq!(ip.as_deref(), Some("203.0.113.9"));
+
+        let ip = client_ip("10.0.0.7", &[], &[("x-forwarded-for", "1.2.3.4")]).await;
+        assert_eq!(ip.as_deref(), Some("10.0.0.7"));
+    }
+
+    #[tokio::test]
+    async fn trusted_proxies_are_skipped_from_the_right() {
+        // The client prepended a fake hop; only the part our proxies wrote counts
+        let ip = client_ip(
+            "10.0.0.7",
+            &["10.0.0.0/8"],
+            &[("x-forwarded-for", "1.2.3.4, 198.51.100.20, 10.0.0.3")],
+        )
+        .await;
+        assert_eq!(ip.as_deref(), Some("198.51.100.20"));
+    }
+
+    #[tokio::test]
+    async fn trusted_proxy_without_forwarding_headers_is_the_client() {
+        let ip = client_ip("10.0.0.7", &["10.0.0.7/32"], &[]).await;
+        assert_eq!(ip.as_deref(), Some("10.0.0.7"));
+
+        let ip = client_ip(
+            "10.0.0.7",
+            &["10.0.0.7/32"],
+            &[("x-real-ip", "198.51.100.20")],
+        )
+        .await;
+        assert_eq!(ip.as_deref(), Some("198.51.100.20"));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::audit::service::ChainVerification;
+use crate::audit::types::{AuditEventView, AuditQueryParams};
+use crate::audit::{verify_chain, AuditEvent, ClientIp, AUDIT_EXPORTED};
+use crate::auth::Admin;
+use crate::entity::{audit_events, users, AuditEvents, Users};
+use crate::error::AppError;
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::stream;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Select,
+};
+
+const DEFAULT_LIMIT: u64 = 100;
+const MAX_LIMIT: u64 = 1000;
+const EXPORT_BATCH: u64 = 500;
+
+async fn user_id_by_name(db: &DatabaseConnection, username: &str) -> Result<i32, AppError> {
+    Users::find()
+        .filter(users::Column::Username.eq(username))
+        .one(db)
+        .await?
+        .map(|u| u.id)
+        .ok_or_else(|| AppError::not_found(format!("User '{}'", username)))
+}
+
+async fn filtered(
+    db: &DatabaseConnection,
+    params: &AuditQueryParams,
+) -> Result<Select<AuditEvents>, AppError> {
+    let mut query = AuditEvents::find();
+
+    if let Some(event_type) = params.event_type.as_deref() {
+        query = if event_type.ends_with('.') {
+            query.filter(audit_events::Column::EventType.starts_with(event_type))
+        } else {
+            query.filter(audit_events::Column::EventType.eq(event_type))
+        };
+    }
+    if let Some(actor) = params.actor.as_deref() {
+        let id = user_id_by_name(db, actor).await?;
+        query = query.filter(audit_events::Column::ActorId.eq(id));
+    }
+    if let Some(target) = params.target.as_deref() {
+        let id = user_id_by_name(db, target).await?;
+        query = query.filter(audit_events::Column::TargetUserId.eq(id));
+    }
+    if let Some(after) = params.after {
+        query = query.filter(audit_events::Column::CreatedAt.gte(after));
+    }
+    if let Some(before) = params.before {
+        query = query.filter(audit_events::Column::CreatedAt.lt(before));
+    }
+    Ok(query)
+}
+
+/// `GET /admin/audit` — filtered audit events, newest first.
+pub async fn list_events(
+    State(db): State<DatabaseConnection>,
+    Admin(_): Admin,
+    Query(params): Query<AuditQueryParams>,
+) -> Result<Json<Vec<AuditEventView>>, AppError> {
+    let events = filtered(&db, &params)
+        .await?
+        .order_by_desc(audit_events::Column::Id)
+        .limit(params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT))
+        .offset(params.offset.unwrap_or(0))
+        .all(&db)
+        .await?;
+
+    Ok(Json(events.into_iter().map(AuditEventView::from).collect()))
+}
+
+/// `GET /admin/audit/export` — every matching event as newline-delimited
+/// JSON in chain order, hashes included so the export can be verified offline.
+pub async fn export_events(
+    State(db): State<DatabaseConnection>,
+    Admin(admin): Admin,
+    ClientIp(ip): ClientIp,
+    Query(params): Query<AuditQueryParams>,
+) -> Result<Response, AppError> {
+    let query = filtered(&db, &params).await?;
+
+    AuditEvent::new(AUDIT_EXPORTED)
+        .actor(admin.id)
+        .ip(ip)
+        .detail("event_type", params.event_type.clone())
+        .detail("actor", params.actor.clone())
+        .detail("target", params.target.clone())
+        .record(&db)
+        .await?;
+
+    // Page through by id so large logs are never held in memory at once
+    let pages = stream::try_unfold(Some(0i64), move |cursor| {
+        let db = db.clone();
+        let query = query.clone();
+        async move {
+            let Some(last_id) = cursor else {
+                return Ok(None);
+            };
+            let batch = query
+                .filter(audit_events::Column::Id.gt(last_id))
+                .order_by_asc(audit_events::Column::Id)
+                .limit(EXPORT_BATCH)
+                .all(&db)
+                .await?;
+            if batch.is_empty() {
+                return Ok(None);
+            }
+
+            let next = (batch.len() as u64 == EXPORT_BATCH).then(|| batch[batch.len() - 1].id);
+            let mut chunk = String::new();
+            for event in batch {
+                let line = serde_json::to_string(&AuditEventView::from(event))
+                    .expect("audit event serializes");
+                chunk.push_str(&line);
+                chunk.push('\n');
+            }
+            Ok::<_, sea_orm::DbErr>(Some((chunk, next)))
+        }
+    });
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-ndjson"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"audit-log.ndjson\"",
+            ),
+        ],
+        Body::from_stream(pages),
+    )
+        .into_response())
+}
+
+/// `GET /admin/audit/verify` — recompute the hash chain.
+pub async fn verify_events(
+    State(db): State<DatabaseConnection>,
+    Admin(_): Admin,
+) -> Result<Json<ChainVerification>, AppError> {
+    Ok(Json(verify_chain(&db).await?))
+}
@@ -0,0 +1,33 @@
+pub mod client_ip;
+pub mod handlers;
+pub mod routes;
+pub mod service;
+pub mod types;
+
+pub use client_ip::ClientIp;
+pub use service::{verify_chain, AuditEvent};
+
+// Event types. Moderation and admin actions are recorded as
+// `moderation.<action>`, mirroring `moderation_actions.action`.
+pub const AUTH_OTP_SENT: &str = "auth.otp_sent";
+pub const AUTH_OTP_SEND_FAILED: &str = "auth.otp_send_failed";
+pub const AUTH_OTP_VERIFIED: &str = "auth.otp_verified";
+pub const AUTH_OTP_FAILED: &str = "auth.otp_failed";
+pub const AUTH_LOGIN: &str = "auth.login";
+pub const AUTH_USER_CREATED: &str = "auth.user_created";
+pub const AUTH_ACCESS_DENIED: &str = "auth.access_denied";
+pub const AUTH_ADMINS_BOOTSTRAPPED: &str = "auth.admins_bootstrapped";
+pub const WS_CONNECTED: &str = "ws.connected";
+pub const WS_DISCONNECTED: &str = "ws.disconnected";
+pub const WS_AUTH_FAILED: &str = "ws.auth_failed";
+pub const BOT_CREATED: &str = "bot.created";
+pub const BOT_DELETED: &str = "bot.deleted";
+pub const BOT_KEY_CREATED: &str = "bot.key_created";
+pub const BOT_KEY_ROTATED: &str = "bot.key_rotated";
+pub const BOT_KEY_REVOKED: &str = "bot.key_revoked";
+pub const WEBHOOK_CREATED: &str = "webhook.created";
+pub const WEBHOOK_UPDATED: &str = "webhook.updated";
+pub const WEBHOOK_DELETED: &str = "webhook.deleted";
+pub const ACCOUNT_DELETED: &str = "account.deleted";
+pub const ACCOUNT_EXPORT_REQUESTED: &str = "account.export_requested";
+pub const AUDIT_EXPORTED: &str = "audit.exported";
@@ -0,0 +1,12 @@
+use axum::{routing::get, Router};
+use sea_orm::DatabaseConnection;
+
+use crate::audit::handlers::{export_events, list_events, verify_events};
+
+pub fn configure_audit_routes(db: DatabaseConnection) -> Router {
+    Router::new()
+        .route("/admin/audit", get(list_events))
+        .route("/admin/audit/export", get(export_events))
+        .route("/admin/audit/verify", get(verify_events))
+        .with_state(db)
+}
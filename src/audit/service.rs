@@ -0,0 +1,277 @@
+use crate::entity::{audit_events, AuditEvents};
+use chrono::{DateTime, SubsecRound, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, DbErr,
+    EntityTrait, QueryFilter, QueryOrder, QuerySelect, Set, Statement, TransactionTrait,
+};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use tracing::error;
+
+/// `prev_hash` of the first entry in the chain.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Advisory lock key serializing appends across instances, so every entry
+/// links to the one actually written before it.
+const CHAIN_LOCK_KEY: i64 = 0x6175_6469_7400;
+
+const VERIFY_BATCH: u64 = 1000;
+
+/// One event on its way into `audit_events`.
+pub struct AuditEvent {
+    event_type: String,
+    actor_id: Option<i32>,
+    target_user_id: Option<i32>,
+    ip_address: Option<String>,
+    details: Map<String, Value>,
+}
+
+impl AuditEvent {
+    pub fn new(event_type: impl Into<String>) -> Self {
+        Self {
+            event_type: event_type.into(),
+            actor_id: None,
+            target_user_id: None,
+            ip_address: None,
+            details: Map::new(),
+        }
+    }
+
+    /// The user who performed the action.
+    pub fn actor(mut self, user_id: i32) -> Self {
+        self.actor_id = Some(user_id);
+        self
+    }
+
+    /// The user the action was performed on.
+    pub fn target(mut self, user_id: i32) -> Self {
+        self.target_user_id = Some(user_id);
+        self
+    }
+
+    pub fn ip(mut self, ip: Option<String>) -> Self {
+        self.ip_address = ip;
+        self
+    }
+
+    pub fn detail(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.details.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Append the event to the chain.
+    pub async fn record(self, db: &DatabaseConnection) -> Result<audit_events::Model, DbErr> {
+        // Postgres keeps microseconds; hash what will be read back
+        let created_at = Utc::now().trunc_subsecs(6);
+        let details = Value::Object(self.details);
+
+        let txn = db.begin().await?;
+        txn.execute(Statement::from_string(
+            DbBackend::Postgres,
+            format!("SELECT pg_advisory_xact_lock({})", CHAIN_LOCK_KEY),
+        ))
+        .await?;
+
+        let prev_hash = AuditEvents::find()
+            .order_by_desc(audit_events::Column::Id)
+            .one(&txn)
+            .await?
+            .map(|e| e.hash)
+            .unwrap_or_else(|| GENESIS_HASH.to_string());
+
+        let hash = entry_hash(
+            &prev_hash,
+            &self.event_type,
+            self.actor_id,
+            self.target_user_id,
+            self.ip_address.as_deref(),
+            &details,
+            created_at,
+        );
+
+        let event = audit_events::ActiveModel {
+            event_type: Set(self.event_type),
+            actor_id: Set(self.actor_id),
+            target_user_id: Set(self.target_user_id),
+            ip_address: Set(self.ip_address),
+            details: Set(details),
+            prev_hash: Set(prev_hash),
+            hash: Set(hash),
+            created_at: Set(created_at),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        txn.commit().await?;
+        Ok(event)
+    }
+
+    /// Like [`AuditEvent::record`], for callers that must not fail because
+    /// the audit write did.
+    pub async fn log(self, db: &DatabaseConnection) {
+        let event_type = self.event_type.clone();
+        if let Err(e) = self.record(db).await {
+            error!("❌ Failed to write audit event {}: {}", event_type, e);
+        }
+    }
+}
+
+/// SHA-256 over the previous hash and the entry's fields, encoded as a JSON
+/// array so field boundaries are unambiguous.
+fn entry_hash(
+    prev_hash: &str,
+    event_type: &str,
+    actor_id: Option<i32>,
+    target_user_id: Option<i32>,
+    ip_address: Option<&str>,
+    details: &Value,
+    created_at: DateTime<Utc>,
+) -> String {
+    let canonical = json!([
+        prev_hash,
+        event_type,
+        actor_id,
+        target_user_id,
+        ip_address,
+        details,
+        created_at.timestamp_micros(),
+    ]);
+    hex::encode(Sha256::digest(canonical.to_string().as_bytes()))
+}
+
+/// Outcome of walking the whole chain.
+#[derive(serde::Serialize)]
+pub struct ChainVerification {
+    pub valid: bool,
+    pub checked: u64,
+    /// First entry whose hash or link does not match
+    pub first_invalid_id: Option<i64>,
+    /// Hash of the newest entry. Keep a copy outside the database to also
+    /// detect entries deleted from the end of the log.
+    pub head_hash: Option<String>,
+}
+
+/// Recompute every hash in id order and check each entry links to the one
+/// before it.
+pub async fn verify_chain(db: &DatabaseConnection) -> Result<ChainVerification, DbErr> {
+    let mut expected_prev = GENESIS_HASH.to_string();
+    let mut last_id = 0;
+    let mut checked = 0;
+
+    loop {
+        let batch = AuditEvents::find()
+            .filter(audit_events::Column::Id.gt(last_id))
+            .order_by_asc(audit_events::Column::Id)
+            .limit(VERIFY_BATCH)
+            .all(db)
+            .await?;
+        if batch.is_empty() {
+            break;
+        }
+
+        for event in batch {
+            let recomputed = entry_hash(
+                &event.prev_hash,
+                &event.event_type,
+                event.actor_id,
+                event.target_user_id,
+                event.ip_address.as_deref(),
+                &event.details,
+                event.created_at,
+            );
+            if event.prev_hash != expected_prev || event.hash != recomputed {
+                return Ok(ChainVerification {
+                    valid: false,
+                    checked,
+                    first_invalid_id: Some(event.id),
+                    head_hash: None,
+                });
+            }
+            checked += 1;
+            last_id = event.id;
+            expected_prev = event.hash;
+        }
+    }
+
+    Ok(ChainVerification {
+        valid: true,
+        checked,
+        first_invalid_id: None,
+        head_hash: (checked > 0).then_some(expected_prev),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{create_user, test_db};
+
+    /// Verify with the given change applied to one row, then put it back so
+    /// the shared test database keeps a valid chain.
+    async fn verify_tampered(
+        db: &DatabaseConnection,
+        original: &audit_events::Model,
+        tamper: audit_events::ActiveModel,
+    ) -> ChainVerification {
+        tamper.update(db).await.unwrap();
+        let verification = verify_chain(db).await.unwrap();
+        let mut restore: audit_events::ActiveModel = original.clone().into();
+        restore.details = Set(original.details.clone());
+        restore.prev_hash = Set(original.prev_hash.clone());
+        restore.update(db).await.unwrap();
+        verification
+    }
+
+    #[tokio::test]
+    async fn edits_and_broken_links_are_detected() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let actor = create_user(&db, "auditor").await;
+        let mut events = Vec::new();
+        for n in 0..3 {
+            let event = AuditEvent::new("test.chain")
+                .actor(actor.id)
+                .ip(Some("198.51.100.20".to_string()))
+                .detail("n", n)
+                .record(&db)
+                .await
+                .unwrap();
+            events.push(event);
+        }
+
+        let verification = verify_chain(&db).await.unwrap();
+        assert!(verification.valid);
+        assert_eq!(verification.first_invalid_id, None);
+
+        let middle = &events[1];
+        let edited = verify_tampered(
+            &db,
+            middle,
+            audit_events::ActiveModel {
+                id: Set(middle.id),
+                details: Set(json!({ "n": 99 })),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(!edited.valid);
+        assert_eq!(edited.first_invalid_id, Some(middle.id));
+
+        let unlinked = verify_tampered(
+            &db,
+            middle,
+            audit_events::ActiveModel {
+                id: Set(middle.id),
+                prev_hash: Set(GENESIS_HASH.to_string()),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(!unlinked.valid);
+        assert_eq!(unlinked.first_invalid_id, Some(middle.id));
+
+        assert!(verify_chain(&db).await.unwrap().valid);
+    }
+}
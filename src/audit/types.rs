@@ -0,0 +1,46 @@
+use crate::entity::audit_events;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct AuditQueryParams {
+    /// Exact event type, or a prefix ending in `.` such as `auth.`
+    pub event_type: Option<String>,
+    /// Username of the acting user
+    pub actor: Option<String>,
+    /// Username of the affected user
+    pub target: Option<String>,
+    pub after: Option<DateTime<Utc>>,
+    pub before: Option<DateTime<Utc>>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct AuditEventView {
+    pub id: i64,
+    pub event_type: String,
+    pub actor_id: Option<i32>,
+    pub target_user_id: Option<i32>,
+    pub ip_address: Option<String>,
+    pub details: serde_json::Value,
+    pub prev_hash: String,
+    pub hash: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<audit_events::Model> for AuditEventView {
+    fn from(event: audit_events::Model) -> Self {
+        Self {
+            id: event.id,
+            event_type: event.event_type,
+            actor_id: event.actor_id,
+            target_user_id: event.target_user_id,
+            ip_address: event.ip_address,
+            details: event.details,
+            prev_hash: event.prev_hash,
+            hash: event.hash,
+            created_at: event.created_at,
+        }
+    }
+}
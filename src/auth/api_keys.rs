@@ -0,0 +1,289 @@
+//! API keys for bot accounts. A key looks like `wsk_<prefix>_<secret>`; only
+//! its SHA-256 is stored, which is plenty for a 244-bit random secret.
+
+use crate::auth::firebase_auth::extract_token;
+use crate::auth::CurrentUser;
+use crate::entity::{api_keys, users, ApiKeys, Users};
+use crate::error::AppError;
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+    response::{IntoResponse, Response},
+};
+use chrono::{Duration, Utc};
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Every API key starts with this, so it can't be mistaken for a Firebase token.
+pub const KEY_PREFIX: &str = "wsk_";
+
+/// `last_used_at` is refreshed at most this often per key.
+const LAST_USED_RESOLUTION: Duration = Duration::minutes(1);
+
+/// What an API key lets a bot do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    /// Connect to `/ws` or `/events` and receive frames
+    #[serde(rename = "messages:read")]
+    MessagesRead,
+    /// Send messages, over REST or a connection
+    #[serde(rename = "messages:write")]
+    MessagesWrite,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::MessagesRead => "messages:read",
+            Scope::MessagesWrite => "messages:write",
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "messages:read" => Ok(Scope::MessagesRead),
+            "messages:write" => Ok(Scope::MessagesWrite),
+            other => Err(format!("Unknown scope '{}'", other)),
+        }
+    }
+}
+
+/// The scopes a caller holds. People signed in with Firebase hold all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scopes(Vec<Scope>);
+
+impl Scopes {
+    pub fn all() -> Self {
+        Self(vec![Scope::MessagesRead, Scope::MessagesWrite])
+    }
+
+    pub fn new(mut scopes: Vec<Scope>) -> Self {
+        scopes.sort_by_key(|scope| scope.as_str());
+        scopes.dedup();
+        Self(scopes)
+    }
+
+    /// Parse the stored, space-separated form. Unknown names grant nothing.
+    pub fn from_stored(stored: &str) -> Self {
+        Self::new(
+            stored
+                .split_whitespace()
+                .filter_map(|scope| scope.parse().ok())
+                .collect(),
+        )
+    }
+
+    pub fn to_stored(&self) -> String {
+        self.0
+            .iter()
+            .map(|scope| scope.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn contains(&self, scope: Scope) -> bool {
+        self.0.contains(&scope)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn to_vec(&self) -> Vec<Scope> {
+        self.0.clone()
+    }
+}
+
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(KEY_PREFIX)
+}
+
+/// A fresh key, and the prefix stored alongside it for display.
+pub fn generate_key() -> (String, String) {
+    let prefix = format!(
+        "{}{}",
+        KEY_PREFIX,
+        &Uuid::new_v4().simple().to_string()[..8]
+    );
+    let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    (format!("{}_{}", prefix, secret), prefix)
+}
+
+pub fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Resolve an API key to its bot and the key's scopes. Revoked and expired
+/// keys are refused, as are keys of a bot or owner that can't sign in.
+pub async fn authenticate_api_key(
+    db: &DatabaseConnection,
+    key: &str,
+) -> Result<(users::Model, Scopes), AppError> {
+    let api_key = ApiKeys::find()
+        .filter(api_keys::Column::KeyHash.eq(hash_key(key)))
+        .one(db)
+        .await?
+        .filter(api_keys::Model::is_active)
+        .ok_or_else(|| AppError::unauthorized("Invalid API key"))?;
+    let bot = Users::find_by_id(api_key.user_id)
+        .one(db)
+        .await?
+        .filter(|bot| bot.is_bot && !bot.is_deleted())
+        .ok_or_else(|| AppError::unauthorized("Invalid API key"))?;
+    if bot.is_banned() || bot.is_suspended() {
+        return Err(AppError::Forbidden(
+            "Account is suspended or banned".to_string(),
+        ));
+    }
+    let owner = match bot.bot_owner_id {
+        Some(owner_id) => Users::find_by_id(owner_id).one(db).await?,
+        None => None,
+    };
+    if !owner.is_some_and(|o| !o.is_deleted() && !o.is_banned() && !o.is_suspended()) {
+        return Err(AppError::Forbidden(
+            "The bot's owner can no longer sign in".to_string(),
+        ));
+    }
+
+    // Throttled, so a busy bot doesn't write on every request
+    let now = Utc::now();
+    ApiKeys::update_many()
+        .col_expr(api_keys::Column::LastUsedAt, Expr::value(now))
+        .filter(api_keys::Column::Id.eq(api_key.id))
+        .filter(
+            Condition::any()
+                .add(api_keys::Column::LastUsedAt.is_null())
+                .add(api_keys::Column::LastUsedAt.lt(now - LAST_USED_RESOLUTION)),
+        )
+        .exec(db)
+        .await?;
+    tracing::Span::current().record("user_id", bot.id);
+
+    Ok((bot, Scopes::from_stored(&api_key.scopes)))
+}
+
+async fn require_scope<S>(
+    parts: &mut Parts,
+    state: &S,
+    scope: Scope,
+) -> Result<users::Model, Response>
+where
+    DatabaseConnection: FromRef<S>,
+    S: Send + Sync,
+{
+    match extract_token(&parts.headers) {
+        Some(token) if is_api_key(&token) => {
+            let db = DatabaseConnection::from_ref(state);
+            let (bot, scopes) = authenticate_api_key(&db, &token)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            if !scopes.contains(scope) {
+                return Err(AppError::Forbidden(format!(
+                    "API key lacks the {} scope",
+                    scope.as_str()
+                ))
+                .into_response());
+            }
+            Ok(bot)
+        }
+        _ => CurrentUser::from_request_parts(parts, state)
+            .await
+            .map(|CurrentUser(user)| user)
+            .map_err(IntoResponse::into_response),
+    }
+}
+
+/// A caller allowed to read messages: a signed-in person, or a bot whose
+/// API key has `messages:read`.
+pub struct MessageReader(pub users::Model);
+
+/// A caller allowed to send messages: a signed-in person, or a bot whose
+/// API key has `messages:write`.
+pub struct MessageSender(pub users::Model);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for MessageReader
+where
+    DatabaseConnection: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        require_scope(parts, state, Scope::MessagesRead)
+            .await
+            .map(MessageReader)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for MessageSender
+where
+    DatabaseConnection: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        require_scope(parts, state, Scope::MessagesWrite)
+            .await
+            .map(MessageSender)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scopes_round_trip_through_storage() {
+        let scopes = Scopes::new(vec![
+            Scope::MessagesWrite,
+            Scope::MessagesRead,
+            Scope::MessagesWrite,
+        ]);
+        assert_eq!(scopes.to_stored(), "messages:read messages:write");
+        assert_eq!(Scopes::from_stored(&scopes.to_stored()), scopes);
+        assert_eq!(scopes, Scopes::all());
+    }
+
+    #[test]
+    fn unknown_stored_scopes_grant_nothing() {
+        let scopes = Scopes::from_stored("  messages:read admin   messages:READ ");
+        assert!(scopes.contains(Scope::MessagesRead));
+        assert!(!scopes.contains(Scope::MessagesWrite));
+        assert_eq!(scopes.to_stored(), "messages:read");
+        assert!(Scopes::from_stored("").is_empty());
+    }
+
+    #[test]
+    fn scope_names_parse_strictly() {
+        assert_eq!("messages:write".parse(), Ok(Scope::MessagesWrite));
+        assert!("messages:*".parse::<Scope>().is_err());
+        assert_eq!(
+            serde_json::from_str::<Vec<Scope>>(r#"["messages:read"]"#).unwrap(),
+            vec![Scope::MessagesRead]
+        );
+        assert!(serde_json::from_str::<Scope>(r#""messages""#).is_err());
+    }
+
+    #[test]
+    fn generated_keys_are_recognised_and_hashed() {
+        let (key, prefix) = generate_key();
+        assert!(is_api_key(&key));
+        assert!(key.starts_with(&format!("{}_", prefix)));
+        assert!(!is_api_key("eyJhbGciOiJSUzI1NiJ9.e30.sig"));
+        assert_eq!(hash_key(&key).len(), 64);
+        assert_ne!(hash_key(&key), hash_key(&generate_key().0));
+    }
+}
@@ -0,0 +1,51 @@
+use crate::auth::api_keys::is_api_key;
+use crate::auth::firebase_auth::{extract_token, FirebaseAuth};
+use crate::entity::{users, Users};
+use crate::error::AppError;
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+
+/// The authenticated caller, resolved from the Firebase token to its `users` row.
+pub struct CurrentUser(pub users::Model);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CurrentUser
+where
+    DatabaseConnection: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // Bots only get the endpoints that take `MessageReader` or `MessageSender`
+        if extract_token(&parts.headers).is_some_and(|token| is_api_key(&token)) {
+            return Err(AppError::forbidden(
+                "API keys can't be used for this endpoint",
+            ));
+        }
+        let FirebaseAuth(claims) = FirebaseAuth::from_request_parts(parts, state).await?;
+        let db = DatabaseConnection::from_ref(state);
+
+        // Firebase UID is stored as username (see `verify_token_and_upsert_user`)
+        let user = Users::find()
+            .filter(users::Column::Username.eq(&claims.sub))
+            .one(&db)
+            .await?
+            .ok_or_else(|| AppError::unauthorized("User not registered, call /auth/me first"))?;
+        // Opened by the trace layer in `run()`
+        tracing::Span::current().record("user_id", user.id);
+
+        if user.is_banned() {
+            return Err(AppError::forbidden("Account banned"));
+        }
+        if user.is_suspended() {
+            return Err(AppError::forbidden("Account suspended"));
+        }
+
+        Ok(CurrentUser(user))
+    }
+}
@@ -1,12 +1,20 @@
 use crate::auth::claims::Claims;
+use crate::config::FirebaseConfig;
+use crate::error::AppError;
+use crate::metrics::{metrics, outcome};
 use axum::{
     async_trait,
     extract::FromRequestParts,
-    http::{request::Parts, HeaderMap, StatusCode},
+    http::{request::Parts, HeaderMap},
 };
 use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
 use reqwest;
 use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::warn;
 
 pub struct FirebaseAuth(pub Claims);
 
@@ -15,27 +23,31 @@ impl<S> FromRequestParts<S> for FirebaseAuth
 where
     S: Send + Sync,
 {
-    type Rejection = (StatusCode, String);
+    type Rejection = AppError;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
         let headers = &parts.headers;
-        let token = extract_token(headers).ok_or((
-            StatusCode::UNAUTHORIZED,
-            "Missing or invalid Authorization header".to_string(),
-        ))?;
-
-        let claims = verify_firebase_token(&token).await.map_err(|e| {
-            (
-                StatusCode::UNAUTHORIZED,
-                format!("Token verification failed: {}", e),
-            )
-        })?;
+        let token = extract_token(headers)
+            .ok_or_else(|| AppError::unauthorized("Missing or invalid Authorization header"))?;
+        // Installed app-wide as an `Extension` in `run()`
+        let firebase = parts
+            .extensions
+            .get::<FirebaseConfig>()
+            .ok_or_else(|| AppError::Internal("Authentication is not configured".to_string()))?;
+
+        let verified = verify_firebase_token(&token, &firebase.project_id).await;
+        metrics()
+            .auth_verifications
+            .with_label_values(&["http", outcome(verified.is_ok())])
+            .inc();
+        let claims = verified
+            .map_err(|e| AppError::unauthorized(format!("Token verification failed: {}", e)))?;
 
         Ok(FirebaseAuth(claims))
     }
 }
 
-fn extract_token(headers: &HeaderMap) -> Option<String> {
+pub(crate) fn extract_token(headers: &HeaderMap) -> Option<String> {
     let auth_header = headers.get("Authorization")?.to_str().ok()?;
     if auth_header.starts_with("Bearer ") {
         Some(auth_header.trim_start_matches("Bearer ").to_string())
@@ -44,29 +56,120 @@ fn extract_token(headers: &HeaderMap) -> Option<String> {
     }
 }
 
-pub async fn verify_firebase_token(id_token: &str) -> Result<Claims, String> {
+const FIREBASE_KEYS_URL: &str =
+    "https://www.googleapis.com/robot/v1/metadata/x509/securetoken@system.gserviceaccount.com";
+/// How long a fetched key set is used before asking Google again. Keys are
+/// published hours before they start signing tokens.
+const KEY_TTL: Duration = Duration::from_secs(60 * 60);
+/// When refreshes keep failing, the cached set is trusted up to this age;
+/// past it, rotated keys would start being missed.
+const KEY_MAX_AGE: Duration = Duration::from_secs(6 * 60 * 60);
+/// Least time between refreshes forced by an unknown `kid`, so forged
+/// tokens can't turn into a request to Google each.
+const MIN_FORCED_REFRESH: Duration = Duration::from_secs(60);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// PEM-encoded certificates by key id.
+type KeySet = HashMap<String, String>;
+
+struct CachedKeys {
+    keys: Arc<KeySet>,
+    fetched_at: Instant,
+}
+
+fn key_cache() -> &'static Mutex<Option<CachedKeys>> {
+    static CACHE: OnceLock<Mutex<Option<CachedKeys>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(None))
+}
+
+/// Google's current token signing certificates, PEM-encoded, by key id.
+async fn fetch_firebase_keys() -> Result<KeySet, String> {
+    let fetch = async {
+        reqwest::get(FIREBASE_KEYS_URL)
+            .await
+            .map_err(|e| e.to_string())?
+            .json::<KeySet>()
+            .await
+            .map_err(|e| e.to_string())
+    };
+    match tokio::time::timeout(FETCH_TIMEOUT, fetch).await {
+        Ok(Ok(keys)) if keys.is_empty() => Err("no signing keys published".to_string()),
+        Ok(result) => result,
+        Err(_) => Err(format!("timed out after {:?}", FETCH_TIMEOUT)),
+    }
+}
+
+/// The signing keys, fetched at most once per `KEY_TTL` across the process.
+/// Also what `/readyz` checks, so probes don't each call Google.
+pub async fn firebase_keys() -> Result<Arc<KeySet>, String> {
+    cached_keys(key_cache(), false, fetch_firebase_keys).await
+}
+
+/// Serve `cache` while it is fresh, or while `force` asks for a refresh the
+/// last fetch is too recent for. Otherwise fetch, falling back to the
+/// cached set until it reaches `KEY_MAX_AGE`. The lock is held across the
+/// fetch so concurrent callers share one request.
+async fn cached_keys<F, Fut>(
+    cache: &Mutex<Option<CachedKeys>>,
+    force: bool,
+    fetch: F,
+) -> Result<Arc<KeySet>, String>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<KeySet, String>>,
+{
+    let mut cache = cache.lock().await;
+    if let Some(cached) = cache.as_ref() {
+        let age = cached.fetched_at.elapsed();
+        let fresh_enough = if force { MIN_FORCED_REFRESH } else { KEY_TTL };
+        if age < fresh_enough {
+            return Ok(cached.keys.clone());
+        }
+    }
+
+    match fetch().await {
+        Ok(keys) => {
+            let keys = Arc::new(keys);
+            *cache = Some(CachedKeys {
+                keys: keys.clone(),
+                fetched_at: Instant::now(),
+            });
+            Ok(keys)
+        }
+        Err(e) => match cache.as_ref() {
+            Some(cached) if cached.fetched_at.elapsed() < KEY_MAX_AGE => {
+                warn!(
+                    "Refreshing Firebase signing keys failed, keeping cached set: {}",
+                    e
+                );
+                Ok(cached.keys.clone())
+            }
+            Some(cached) => Err(format!(
+                "signing keys are {}s old and refreshing failed: {}",
+                cached.fetched_at.elapsed().as_secs(),
+                e
+            )),
+            None => Err(e),
+        },
+    }
+}
+
+pub async fn verify_firebase_token(id_token: &str, project_id: &str) -> Result<Claims, String> {
     let header = decode_header(id_token).map_err(|e| e.to_string())?;
     let kid = header.kid.ok_or("Missing `kid` in token header")?;
 
-    let keys_url =
-        "https://www.googleapis.com/robot/v1/metadata/x509/securetoken@system.gserviceaccount.com";
-    let keys: HashMap<String, String> = reqwest::get(keys_url)
-        .await
-        .map_err(|e| e.to_string())?
-        .json()
-        .await
-        .map_err(|e| e.to_string())?;
-
+    let mut keys = firebase_keys().await?;
+    if !keys.contains_key(&kid) {
+        // Possibly a key published since the last fetch
+        keys = cached_keys(key_cache(), true, fetch_firebase_keys).await?;
+    }
     let public_key_pem = keys.get(&kid).ok_or("Public key not found")?;
 
     let decoding_key =
         DecodingKey::from_rsa_pem(public_key_pem.as_bytes()).map_err(|e| e.to_string())?;
 
-    let project_id = std::env::var("FIREBASE_PROJECT_ID")
-        .map_err(|_| "Missing FIREBASE_PROJECT_ID env var".to_string())?;
-
     let mut validation = Validation::new(Algorithm::RS256);
-    validation.set_audience(&[&project_id]);
+    validation.set_audience(&[project_id]);
     let mut issuers = HashSet::new();
     issuers.insert(format!("https://securetoken.google.com/{}", project_id));
     validation.iss = Some(issuers);
@@ -76,3 +179,71 @@ pub async fn verify_firebase_token(id_token: &str) -> Result<Claims, String> {
 
     Ok(token_data.claims)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn key_set(kid: &str) -> KeySet {
+        HashMap::from([(kid.to_string(), "pem".to_string())])
+    }
+
+    fn cache_aged(age: Duration) -> Mutex<Option<CachedKeys>> {
+        Mutex::new(Some(CachedKeys {
+            keys: Arc::new(key_set("old")),
+            fetched_at: Instant::now() - age,
+        }))
+    }
+
+    #[tokio::test]
+    async fn fresh_keys_are_served_from_cache() {
+        let cache = Mutex::new(None);
+        let fetches = AtomicUsize::new(0);
+        let fetch = || async {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            Ok(key_set("a"))
+        };
+
+        for _ in 0..3 {
+            let keys = cached_keys(&cache, false, fetch).await.unwrap();
+            assert!(keys.contains_key("a"));
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        // A forced refresh right after a fetch is held back too
+        cached_keys(&cache, true, fetch).await.unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn expired_keys_are_refetched() {
+        let cache = cache_aged(KEY_TTL + Duration::from_secs(1));
+        let keys = cached_keys(&cache, false, || async { Ok(key_set("new")) })
+            .await
+            .unwrap();
+        assert!(keys.contains_key("new"));
+
+        let cache = cache_aged(MIN_FORCED_REFRESH + Duration::from_secs(1));
+        let keys = cached_keys(&cache, true, || async { Ok(key_set("new")) })
+            .await
+            .unwrap();
+        assert!(keys.contains_key("new"));
+    }
+
+    #[tokio::test]
+    async fn failed_refresh_falls_back_until_keys_are_too_old() {
+        let failing = || async { Err("unreachable".to_string()) };
+
+        let cache = cache_aged(KEY_TTL + Duration::from_secs(1));
+        let keys = cached_keys(&cache, false, failing).await.unwrap();
+        assert!(keys.contains_key("old"));
+
+        let cache = cache_aged(KEY_MAX_AGE + Duration::from_secs(1));
+        assert!(cached_keys(&cache, false, failing).await.is_err());
+
+        assert!(cached_keys(&Mutex::new(None), false, failing)
+            .await
+            .is_err());
+    }
+}
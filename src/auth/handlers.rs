@@ -1,8 +1,18 @@
+use crate::audit::{
+    AuditEvent, ClientIp, AUTH_LOGIN, AUTH_OTP_FAILED, AUTH_OTP_SEND_FAILED, AUTH_OTP_SENT,
+    AUTH_OTP_VERIFIED, AUTH_USER_CREATED,
+};
 use crate::auth::firebase_auth::FirebaseAuth;
+use crate::auth::roles::Role;
 use crate::auth::service;
 use crate::auth::types::{SendOtpRequest, VerifyOtpRequest};
+use crate::config::FirebaseConfig;
 use crate::entity::users;
-use axum::extract::State;
+use crate::error::AppError;
+use crate::metrics::{metrics, outcome};
+use crate::webhooks::types::UserJoinedData;
+use crate::webhooks::{WebhookEvent, USER_JOINED};
+use axum::extract::{FromRef, State};
 use axum::{
     extract::Json,
     http::StatusCode,
@@ -11,8 +21,56 @@ use axum::{
 };
 use chrono::Utc;
 use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
-pub async fn send_otp_handler(Json(payload): Json<SendOtpRequest>) -> Response {
-    let response = service::send_otp(payload.phone_number).await;
+
+#[derive(Clone)]
+pub struct AuthState {
+    pub db: DatabaseConnection,
+    pub firebase: FirebaseConfig,
+}
+
+impl FromRef<AuthState> for DatabaseConnection {
+    fn from_ref(state: &AuthState) -> Self {
+        state.db.clone()
+    }
+}
+
+/// Keep only the country code prefix and last four digits for the audit log.
+fn mask_phone(phone_number: &str) -> String {
+    let chars: Vec<char> = phone_number.chars().collect();
+    if chars.len() <= 7 {
+        return "*".repeat(chars.len());
+    }
+    let (head, rest) = chars.split_at(3);
+    let (middle, tail) = rest.split_at(rest.len() - 4);
+    format!(
+        "{}{}{}",
+        head.iter().collect::<String>(),
+        "*".repeat(middle.len()),
+        tail.iter().collect::<String>()
+    )
+}
+
+pub async fn send_otp_handler(
+    State(state): State<AuthState>,
+    ClientIp(ip): ClientIp,
+    Json(payload): Json<SendOtpRequest>,
+) -> Response {
+    let masked = mask_phone(&payload.phone_number);
+    let response = service::send_otp(&state.firebase.api_key, payload.phone_number).await;
+    metrics()
+        .auth_otp
+        .with_label_values(&["send", outcome(response.error.is_none())])
+        .inc();
+
+    let event = match &response.error {
+        Some(e) => AuditEvent::new(AUTH_OTP_SEND_FAILED).detail("error", e.message.clone()),
+        None => AuditEvent::new(AUTH_OTP_SENT),
+    };
+    event
+        .ip(ip)
+        .detail("phone_number", masked)
+        .log(&state.db)
+        .await;
 
     if response.error.is_some() {
         // Return error with HTTP 422
@@ -23,8 +81,23 @@ pub async fn send_otp_handler(Json(payload): Json<SendOtpRequest>) -> Response {
     (StatusCode::OK, JsonResponse(response)).into_response()
 }
 
-pub async fn verify_otp_handler(Json(payload): Json<VerifyOtpRequest>) -> Response {
-    let response = service::verify_otp(payload.session_info, payload.code).await;
+pub async fn verify_otp_handler(
+    State(state): State<AuthState>,
+    ClientIp(ip): ClientIp,
+    Json(payload): Json<VerifyOtpRequest>,
+) -> Response {
+    let response =
+        service::verify_otp(&state.firebase.api_key, payload.session_info, payload.code).await;
+    metrics()
+        .auth_otp
+        .with_label_values(&["verify", outcome(response.error.is_none())])
+        .inc();
+
+    let event = match &response.error {
+        Some(e) => AuditEvent::new(AUTH_OTP_FAILED).detail("error", e.message.clone()),
+        None => AuditEvent::new(AUTH_OTP_VERIFIED),
+    };
+    event.ip(ip).log(&state.db).await;
 
     if response.error.is_some() {
         return (StatusCode::UNPROCESSABLE_ENTITY, JsonResponse(response)).into_response();
@@ -44,19 +117,19 @@ pub struct UserResponse {
 
 pub async fn verify_token_and_upsert_user(
     State(db): State<DatabaseConnection>,
+    ClientIp(ip): ClientIp,
     FirebaseAuth(claims): FirebaseAuth,
-) -> Result<JsonResponse<UserResponse>, (StatusCode, String)> {
-    let phone_number = claims.phone_number.clone().ok_or((
-        StatusCode::BAD_REQUEST,
-        "No phone number in token".to_string(),
-    ))?;
+) -> Result<JsonResponse<UserResponse>, AppError> {
+    let phone_number = claims
+        .phone_number
+        .clone()
+        .ok_or_else(|| AppError::bad_request("No phone number in token"))?;
     let uid = claims.user_id.clone();
 
     let existing_user = users::Entity::find()
         .filter(users::Column::PhoneNumber.eq(phone_number.clone()))
         .one(&db)
-        .await
-        .map_err(internal_error)?;
+        .await?;
 
     let user = if let Some(user) = existing_user {
         user
@@ -65,13 +138,40 @@ pub async fn verify_token_and_upsert_user(
         let new_user = users::ActiveModel {
             username: Set(uid.clone()),
             phone_number: Set(phone_number.clone()),
+            role: Set(Role::User.as_str().to_string()),
             created_at: Set(Some(now)),
             updated_at: Set(Some(now)),
             ..Default::default()
         };
 
-        new_user.insert(&db).await.map_err(internal_error)?
+        let user = new_user.insert(&db).await?;
+        AuditEvent::new(AUTH_USER_CREATED)
+            .actor(user.id)
+            .ip(ip.clone())
+            .detail("phone_number", mask_phone(&user.phone_number))
+            .log(&db)
+            .await;
+        WebhookEvent::new(
+            USER_JOINED,
+            UserJoinedData {
+                username: &user.username,
+                is_bot: false,
+                bot_owner: None,
+                created_at: user.created_at,
+            },
+        )
+        .emit(&db)
+        .await;
+        user
     };
+    // `CurrentUser` does this for other endpoints
+    tracing::Span::current().record("user_id", user.id);
+
+    AuditEvent::new(AUTH_LOGIN)
+        .actor(user.id)
+        .ip(ip)
+        .log(&db)
+        .await;
 
     Ok(JsonResponse(UserResponse {
         id: user.id,
@@ -85,7 +185,3 @@ pub async fn verify_token_and_upsert_user(
 // fn generate_username(phone: &str) -> String {
 //     format!("user_{}", &phone[phone.len().saturating_sub(4)..])
 // }
-
-fn internal_error<E: std::fmt::Display>(e: E) -> (StatusCode, String) {
-    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
-}
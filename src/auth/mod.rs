@@ -1,7 +1,13 @@
+pub mod api_keys;
 pub mod claims;
+pub mod current_user;
 pub mod firebase_auth;
 pub mod handlers;
+pub mod roles;
 pub mod routes;
 pub mod service;
 pub mod types;
-pub use firebase_auth::verify_firebase_token;
+pub use api_keys::{MessageReader, MessageSender, Scope, Scopes};
+pub use current_user::CurrentUser;
+pub use firebase_auth::{firebase_keys, verify_firebase_token};
+pub use roles::{check_sanction, Admin, Moderator, Role};
@@ -0,0 +1,219 @@
+use crate::audit::{AuditEvent, ClientIp, AUTH_ACCESS_DENIED, AUTH_ADMINS_BOOTSTRAPPED};
+use crate::auth::CurrentUser;
+use crate::entity::{users, Users};
+use crate::error::AppError;
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+};
+use sea_orm::{sea_query::Expr, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tracing::info;
+
+/// Privilege level stored in `users.role`. Ordered, so a higher role
+/// satisfies any lower requirement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Moderator,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!("Unknown role '{}'", other)),
+        }
+    }
+}
+
+async fn require_role<S>(
+    parts: &mut Parts,
+    state: &S,
+    required: Role,
+) -> Result<users::Model, AppError>
+where
+    DatabaseConnection: FromRef<S>,
+    S: Send + Sync,
+{
+    let CurrentUser(user) = CurrentUser::from_request_parts(parts, state).await?;
+    if user.role() < required {
+        let ClientIp(ip) = ClientIp::from_request_parts(parts, state)
+            .await
+            .unwrap_or(ClientIp(None));
+        AuditEvent::new(AUTH_ACCESS_DENIED)
+            .actor(user.id)
+            .ip(ip)
+            .detail("path", parts.uri.path())
+            .detail("required_role", required.as_str())
+            .log(&DatabaseConnection::from_ref(state))
+            .await;
+        return Err(AppError::Forbidden(format!(
+            "{} access required",
+            required.as_str()
+        )));
+    }
+    Ok(user)
+}
+
+/// Whether `actor` may `action` (suspend, ban) `target`: never themselves,
+/// and only accounts ranked strictly below them.
+pub fn check_sanction(
+    actor: &users::Model,
+    target: &users::Model,
+    action: &str,
+) -> Result<(), AppError> {
+    if target.id == actor.id {
+        return Err(AppError::bad_request(format!(
+            "You cannot {} yourself",
+            action
+        )));
+    }
+    if target.role() >= actor.role() {
+        return Err(AppError::Forbidden(format!(
+            "You cannot {} a {}",
+            action,
+            target.role().as_str()
+        )));
+    }
+    Ok(())
+}
+
+/// A caller with at least the moderator role.
+pub struct Moderator(pub users::Model);
+
+/// A caller with the admin role.
+pub struct Admin(pub users::Model);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Moderator
+where
+    DatabaseConnection: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        require_role(parts, state, Role::Moderator)
+            .await
+            .map(Moderator)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Admin
+where
+    DatabaseConnection: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        require_role(parts, state, Role::Admin).await.map(Admin)
+    }
+}
+
+/// Grant the admin role to the usernames in `ADMIN_USERNAMES`, so a fresh
+/// deployment has someone who can hand out roles.
+pub async fn promote_bootstrap_admins(
+    db: &DatabaseConnection,
+    usernames: &[String],
+) -> Result<(), sea_orm::DbErr> {
+    if usernames.is_empty() {
+        return Ok(());
+    }
+
+    let result = Users::update_many()
+        .col_expr(users::Column::Role, Expr::value(Role::Admin.as_str()))
+        .filter(users::Column::Username.is_in(usernames.to_vec()))
+        .filter(users::Column::Role.ne(Role::Admin.as_str()))
+        .exec(db)
+        .await?;
+    if result.rows_affected > 0 {
+        info!("Promoted {} bootstrap admin(s)", result.rows_affected);
+        AuditEvent::new(AUTH_ADMINS_BOOTSTRAPPED)
+            .detail("usernames", usernames)
+            .detail("promoted", result.rows_affected)
+            .log(db)
+            .await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    fn account(id: i32, role: Role) -> users::Model {
+        users::Model {
+            id,
+            username: format!("user{}", id),
+            phone_number: format!("+1555000{:04}", id),
+            created_at: None,
+            updated_at: None,
+            role: role.as_str().to_string(),
+            suspended_until: None,
+            banned_at: None,
+            deleted_at: None,
+            event_seq: 0,
+            is_bot: false,
+            bot_owner_id: None,
+        }
+    }
+
+    #[test]
+    fn sanctions_only_reach_lower_roles() {
+        let admin = account(1, Role::Admin);
+        let moderator = account(2, Role::Moderator);
+        let user = account(3, Role::User);
+
+        assert!(check_sanction(&moderator, &user, "suspend").is_ok());
+        assert!(check_sanction(&admin, &moderator, "ban").is_ok());
+        assert_eq!(
+            check_sanction(&moderator, &account(4, Role::Moderator), "suspend")
+                .unwrap_err()
+                .status(),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            check_sanction(&moderator, &admin, "suspend")
+                .unwrap_err()
+                .status(),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            check_sanction(&admin, &account(5, Role::Admin), "ban")
+                .unwrap_err()
+                .status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[test]
+    fn nobody_sanctions_themselves() {
+        let admin = account(1, Role::Admin);
+        assert_eq!(
+            check_sanction(&admin, &admin, "ban").unwrap_err().status(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+}
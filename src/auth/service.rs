@@ -1,14 +1,8 @@
 use crate::auth::types::{FirebaseError, FirebaseResponse};
 use reqwest::Client;
 use serde_json::json;
-use std::env;
 
-fn get_api_key() -> String {
-    env::var("FIREBASE_API_KEY").expect("FIREBASE_API_KEY must be set")
-}
-
-pub async fn send_otp(phone_number: String) -> FirebaseResponse {
-    let api_key = get_api_key();
+pub async fn send_otp(api_key: &str, phone_number: String) -> FirebaseResponse {
     let client = Client::new();
     let url = format!(
         "https://identitytoolkit.googleapis.com/v1/accounts:sendVerificationCode?key={}",
@@ -50,8 +44,7 @@ pub async fn send_otp(phone_number: String) -> FirebaseResponse {
     }
 }
 
-pub async fn verify_otp(session_info: String, code: String) -> FirebaseResponse {
-    let api_key = get_api_key();
+pub async fn verify_otp(api_key: &str, session_info: String, code: String) -> FirebaseResponse {
     let client = Client::new();
     let url = format!(
         "https://identitytoolkit.googleapis.com/v1/accounts:signInWithPhoneNumber?key={}",
@@ -0,0 +1,342 @@
+use crate::auth::CurrentUser;
+use crate::entity::{user_blocks, users, UserBlocks, Users};
+use crate::error::AppError;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, Set,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Status stored on messages from a blocked sender. They are kept so the
+/// sender's own history looks normal, but never shown to the recipient.
+pub const BLOCKED_STATUS: &str = "blocked";
+
+/// Whether `blocker_id` has blocked `blocked_id`.
+pub async fn is_blocked(
+    db: &DatabaseConnection,
+    blocker_id: i32,
+    blocked_id: i32,
+) -> Result<bool, sea_orm::DbErr> {
+    Ok(UserBlocks::find()
+        .filter(user_blocks::Column::BlockerId.eq(blocker_id))
+        .filter(user_blocks::Column::BlockedId.eq(blocked_id))
+        .one(db)
+        .await?
+        .is_some())
+}
+
+/// Users on either side of a block with `user_id`. Presence is hidden in
+/// both directions.
+pub async fn blocked_either_way(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<HashSet<i32>, sea_orm::DbErr> {
+    let blocks = UserBlocks::find()
+        .filter(
+            Condition::any()
+                .add(user_blocks::Column::BlockerId.eq(user_id))
+                .add(user_blocks::Column::BlockedId.eq(user_id)),
+        )
+        .all(db)
+        .await?;
+
+    Ok(blocks
+        .into_iter()
+        .map(|b| {
+            if b.blocker_id == user_id {
+                b.blocked_id
+            } else {
+                b.blocker_id
+            }
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+pub struct BlockRequest {
+    username: String,
+}
+
+#[derive(Serialize)]
+pub struct BlockedUser {
+    username: String,
+    blocked_at: DateTime<Utc>,
+}
+
+async fn find_user(db: &DatabaseConnection, username: &str) -> Result<users::Model, AppError> {
+    Users::find()
+        .filter(users::Column::Username.eq(username))
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("User '{}'", username)))
+}
+
+/// `POST /blocks` — block a user. Idempotent.
+pub async fn block_user(
+    State(db): State<DatabaseConnection>,
+    CurrentUser(user): CurrentUser,
+    Json(payload): Json<BlockRequest>,
+) -> Result<StatusCode, AppError> {
+    let target = find_user(&db, payload.username.trim()).await?;
+    if target.id == user.id {
+        return Err(AppError::bad_request("You cannot block yourself"));
+    }
+
+    if !is_blocked(&db, user.id, target.id).await? {
+        user_blocks::ActiveModel {
+            blocker_id: Set(user.id),
+            blocked_id: Set(target.id),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `DELETE /blocks/:username` — lift a block. Idempotent.
+pub async fn unblock_user(
+    State(db): State<DatabaseConnection>,
+    CurrentUser(user): CurrentUser,
+    Path(username): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let target = find_user(&db, &username).await?;
+
+    UserBlocks::delete_many()
+        .filter(user_blocks::Column::BlockerId.eq(user.id))
+        .filter(user_blocks::Column::BlockedId.eq(target.id))
+        .exec(&db)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /blocks` — users the caller has blocked, newest first.
+pub async fn list_blocks(
+    State(db): State<DatabaseConnection>,
+    CurrentUser(user): CurrentUser,
+) -> Result<Json<Vec<BlockedUser>>, AppError> {
+    let blocks = UserBlocks::find()
+        .filter(user_blocks::Column::BlockerId.eq(user.id))
+        .order_by_desc(user_blocks::Column::CreatedAt)
+        .find_also_related(Users)
+        .all(&db)
+        .await?;
+
+    Ok(Json(
+        blocks
+            .into_iter()
+            .filter_map(|(block, blocked)| {
+                blocked.map(|u| BlockedUser {
+                    username: u.username,
+                    blocked_at: block.created_at,
+                })
+            })
+            .collect(),
+    ))
+}
+
+pub fn block_routes(db: DatabaseConnection) -> Router {
+    Router::new()
+        .route("/blocks", get(list_blocks).post(block_user))
+        .route("/blocks/:username", delete(unblock_user))
+        .with_state(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{messages, Messages};
+    use crate::messages::{DELIVERED_STATUS, UNREAD_STATUS};
+    use crate::retention::RetentionPolicy;
+    use crate::search::search_messages;
+    use crate::test_support::{create_user, test_db};
+    use crate::ws::{
+        route_text_message, send_message_history, Connection, OutboundQueue, SendOutcome,
+        SharedState,
+    };
+    use axum::extract::{ws::Message, Query};
+    use axum::http::Uri;
+    use tokio_util::sync::CancellationToken;
+    use uuid::Uuid;
+
+    async fn block(db: &DatabaseConnection, blocker: &users::Model, blocked: &users::Model) {
+        block_user(
+            State(db.clone()),
+            CurrentUser(blocker.clone()),
+            Json(BlockRequest {
+                username: blocked.username.clone(),
+            }),
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn unblock(db: &DatabaseConnection, blocker: &users::Model, blocked: &users::Model) {
+        unblock_user(
+            State(db.clone()),
+            CurrentUser(blocker.clone()),
+            Path(blocked.username.clone()),
+        )
+        .await
+        .unwrap();
+    }
+
+    /// Route `text` from `sender` to `recipient` and return the stored row
+    /// as it ended up, or `None` if nothing was stored.
+    async fn send(
+        db: &DatabaseConnection,
+        online: &SharedState,
+        sender: &users::Model,
+        recipient: &users::Model,
+        text: &str,
+    ) -> Option<messages::Model> {
+        let outcome = route_text_message(
+            db,
+            online,
+            RetentionPolicy::from_days(None),
+            sender,
+            &recipient.username,
+            text.to_string(),
+        )
+        .await
+        .unwrap();
+        match outcome {
+            SendOutcome::Sent(message) => Messages::find_by_id(message.id).one(db).await.unwrap(),
+            SendOutcome::RecipientBlocked => None,
+            SendOutcome::UnknownRecipient => panic!("{} should exist", recipient.username),
+        }
+    }
+
+    fn online(users: &[&users::Model]) -> SharedState {
+        let online = SharedState::default();
+        let mut connections = online.try_lock().unwrap();
+        for user in users {
+            connections.insert(
+                user.username.clone(),
+                Connection {
+                    id: Uuid::new_v4(),
+                    queue: OutboundQueue::new(16),
+                    cancel: CancellationToken::new(),
+                },
+            );
+        }
+        drop(connections);
+        online
+    }
+
+    /// Frames `user` gets as history when they connect.
+    async fn history(db: &DatabaseConnection, user: &users::Model) -> Vec<String> {
+        let mut frames: Vec<Message> = Vec::new();
+        send_message_history(&mut frames, user, db, &SharedState::default())
+            .await
+            .unwrap();
+        frames
+            .into_iter()
+            .filter_map(|frame| match frame {
+                Message::Text(text) => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Ids of the messages `user` finds searching for `q`.
+    async fn search(db: &DatabaseConnection, user: &users::Model, q: &str) -> Vec<i32> {
+        let uri: Uri = format!("/search?q={}", q).parse().unwrap();
+        let Json(response) = search_messages(
+            State(db.clone()),
+            CurrentUser(user.clone()),
+            Query::try_from_uri(&uri).unwrap(),
+        )
+        .await
+        .unwrap();
+        let response = serde_json::to_value(response).unwrap();
+        let mut ids: Vec<i32> = response["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|hit| hit["id"].as_i64().unwrap() as i32)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    fn unique_word() -> String {
+        format!("kw{}", &Uuid::new_v4().simple().to_string()[..12])
+    }
+
+    #[tokio::test]
+    async fn a_block_stops_delivery_until_lifted() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let alice = create_user(&db, "alice").await;
+        let bob = create_user(&db, "bob").await;
+        let online = online(&[&alice, &bob]);
+
+        block(&db, &alice, &bob).await;
+        // Blocking again is a no-op
+        block(&db, &alice, &bob).await;
+        assert!(is_blocked(&db, alice.id, bob.id).await.unwrap());
+        assert!(!is_blocked(&db, bob.id, alice.id).await.unwrap());
+
+        // Alice can't write to someone she blocked
+        assert!(send(&db, &online, &alice, &bob, "hi bob").await.is_none());
+        // Bob's message is stored but never pushed to Alice's open socket
+        let hidden = send(&db, &online, &bob, &alice, "hi alice").await.unwrap();
+        assert_eq!(hidden.status, BLOCKED_STATUS);
+
+        unblock(&db, &alice, &bob).await;
+        assert!(!is_blocked(&db, alice.id, bob.id).await.unwrap());
+        let sent = send(&db, &online, &alice, &bob, "hi again").await.unwrap();
+        assert_eq!(sent.status, DELIVERED_STATUS);
+        let received = send(&db, &online, &bob, &alice, "welcome back")
+            .await
+            .unwrap();
+        assert_eq!(received.status, DELIVERED_STATUS);
+    }
+
+    #[tokio::test]
+    async fn a_block_hides_messages_from_history_and_search() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let word = unique_word();
+        let alice = create_user(&db, "alice").await;
+        let bob = create_user(&db, "bob").await;
+        let offline = SharedState::default();
+
+        block(&db, &alice, &bob).await;
+        let text = format!("during the block {}", word);
+        let hidden = send(&db, &offline, &bob, &alice, &text).await.unwrap();
+        let line = format!("{}: {}", bob.username, text);
+
+        // Alice doesn't see it anywhere; Bob's own history looks normal
+        assert!(!history(&db, &alice).await.contains(&line));
+        assert!(search(&db, &alice, &word).await.is_empty());
+        assert!(history(&db, &bob).await.contains(&line));
+        assert_eq!(search(&db, &bob, &word).await, vec![hidden.id]);
+
+        unblock(&db, &alice, &bob).await;
+        let text = format!("after the block {}", word);
+        let shown = send(&db, &offline, &bob, &alice, &text).await.unwrap();
+        assert_eq!(shown.status, UNREAD_STATUS);
+        let after = format!("{}: {}", bob.username, text);
+
+        // New messages show up again; the swallowed one stays swallowed
+        let alice_history = history(&db, &alice).await;
+        assert!(alice_history.contains(&after));
+        assert!(!alice_history.contains(&line));
+        assert_eq!(search(&db, &alice, &word).await, vec![shown.id]);
+    }
+}
@@ -0,0 +1,393 @@
+use crate::account::deletion::delete_account;
+use crate::account::types::DeletionPolicy;
+use crate::attachments::StorageBackend;
+use crate::audit::{
+    AuditEvent, ClientIp, BOT_CREATED, BOT_DELETED, BOT_KEY_CREATED, BOT_KEY_REVOKED,
+    BOT_KEY_ROTATED,
+};
+use crate::auth::api_keys::{generate_key, hash_key};
+use crate::auth::{CurrentUser, MessageReader, Role, Scopes};
+use crate::bots::types::{
+    ApiKeyView, BotView, CreateBotRequest, CreateKeyRequest, NewApiKey, ProfileView,
+};
+use crate::bots::{bot_phone, validate_bot_username, MAX_BOTS_PER_OWNER, MAX_KEYS_PER_BOT};
+use crate::entity::{api_keys, users, ApiKeys, Users};
+use crate::error::AppError;
+use crate::webhooks::types::UserJoinedData;
+use crate::webhooks::{WebhookEvent, USER_JOINED};
+use crate::ws::{disconnect_user, SharedState};
+use axum::{
+    extract::{FromRef, Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait,
+    PaginatorTrait, QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+use std::sync::Arc;
+use tracing::info;
+
+/// Longest lifetime a key can be created with; keys may also never expire.
+const MAX_KEY_LIFETIME_DAYS: i64 = 365;
+
+#[derive(Clone)]
+pub struct BotsState {
+    pub db: DatabaseConnection,
+    pub online: SharedState,
+    pub storage: Arc<dyn StorageBackend>,
+    pub deletion_policy: DeletionPolicy,
+}
+
+impl FromRef<BotsState> for DatabaseConnection {
+    fn from_ref(state: &BotsState) -> Self {
+        state.db.clone()
+    }
+}
+
+/// One of `owner`'s bots, by username.
+async fn find_own_bot(
+    db: &DatabaseConnection,
+    owner: &users::Model,
+    username: &str,
+) -> Result<users::Model, AppError> {
+    Users::find()
+        .filter(users::Column::Username.eq(username))
+        .filter(users::Column::IsBot.eq(true))
+        .filter(users::Column::BotOwnerId.eq(owner.id))
+        .filter(users::Column::DeletedAt.is_null())
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::not_found("Bot"))
+}
+
+async fn find_key(
+    db: &DatabaseConnection,
+    bot: &users::Model,
+    id: i32,
+) -> Result<api_keys::Model, AppError> {
+    ApiKeys::find_by_id(id)
+        .filter(api_keys::Column::UserId.eq(bot.id))
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::not_found("API key"))
+}
+
+/// Store a fresh key for `bot` and return it with its secret.
+async fn insert_key<C: ConnectionTrait>(
+    db: &C,
+    bot: &users::Model,
+    created_by: &users::Model,
+    name: String,
+    scopes: &Scopes,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<NewApiKey, AppError> {
+    let (secret, prefix) = generate_key();
+    let key = api_keys::ActiveModel {
+        user_id: Set(bot.id),
+        name: Set(name),
+        prefix: Set(prefix),
+        key_hash: Set(hash_key(&secret)),
+        scopes: Set(scopes.to_stored()),
+        created_by: Set(Some(created_by.id)),
+        created_at: Set(Utc::now()),
+        expires_at: Set(expires_at),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+    Ok(NewApiKey {
+        view: key.into(),
+        api_key: secret,
+    })
+}
+
+/// `POST /bots` — create a bot owned by the caller.
+pub async fn create_bot(
+    State(state): State<BotsState>,
+    CurrentUser(owner): CurrentUser,
+    ClientIp(ip): ClientIp,
+    Json(payload): Json<CreateBotRequest>,
+) -> Result<(StatusCode, Json<BotView>), AppError> {
+    let username = payload.username.trim();
+    validate_bot_username(username).map_err(AppError::BadRequest)?;
+
+    let owned = Users::find()
+        .filter(users::Column::BotOwnerId.eq(owner.id))
+        .filter(users::Column::DeletedAt.is_null())
+        .count(&state.db)
+        .await?;
+    if owned >= MAX_BOTS_PER_OWNER {
+        return Err(AppError::Conflict(format!(
+            "At most {} bots per account",
+            MAX_BOTS_PER_OWNER
+        )));
+    }
+    let taken = Users::find()
+        .filter(users::Column::Username.eq(username))
+        .one(&state.db)
+        .await?
+        .is_some();
+    if taken {
+        return Err(AppError::Conflict(format!(
+            "Username '{}' is taken",
+            username
+        )));
+    }
+
+    let now = Utc::now();
+    // The placeholder phone number needs the row's id, so it is set right
+    // after the insert in the same transaction
+    let txn = state.db.begin().await?;
+    let inserted = users::ActiveModel {
+        username: Set(username.to_string()),
+        phone_number: Set(String::new()),
+        role: Set(Role::User.as_str().to_string()),
+        is_bot: Set(true),
+        bot_owner_id: Set(Some(owner.id)),
+        created_at: Set(Some(now)),
+        updated_at: Set(Some(now)),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    let id = inserted.id;
+    let mut update: users::ActiveModel = inserted.into();
+    update.phone_number = Set(bot_phone(id));
+    let bot = update.update(&txn).await?;
+    txn.commit().await?;
+
+    AuditEvent::new(BOT_CREATED)
+        .actor(owner.id)
+        .target(bot.id)
+        .ip(ip)
+        .detail("username", bot.username.clone())
+        .log(&state.db)
+        .await;
+    WebhookEvent::new(
+        USER_JOINED,
+        UserJoinedData {
+            username: &bot.username,
+            is_bot: true,
+            bot_owner: Some(&owner.username),
+            created_at: bot.created_at,
+        },
+    )
+    .emit(&state.db)
+    .await;
+    info!("🤖 {} created bot {}", owner.username, bot.username);
+    Ok((StatusCode::CREATED, Json(bot.into())))
+}
+
+/// `GET /bots` — the caller's bots.
+pub async fn list_bots(
+    State(state): State<BotsState>,
+    CurrentUser(owner): CurrentUser,
+) -> Result<Json<Vec<BotView>>, AppError> {
+    let bots = Users::find()
+        .filter(users::Column::BotOwnerId.eq(owner.id))
+        .filter(users::Column::DeletedAt.is_null())
+        .order_by_asc(users::Column::Username)
+        .all(&state.db)
+        .await?;
+    Ok(Json(bots.into_iter().map(BotView::from).collect()))
+}
+
+/// `DELETE /bots/:username` — delete a bot like any account, keys included.
+pub async fn delete_bot(
+    State(state): State<BotsState>,
+    CurrentUser(owner): CurrentUser,
+    ClientIp(ip): ClientIp,
+    Path(username): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let bot = find_own_bot(&state.db, &owner, &username).await?;
+    let bot_id = bot.id;
+    let summary = delete_account(
+        &state.db,
+        state.storage.as_ref(),
+        &state.online,
+        bot,
+        state.deletion_policy,
+    )
+    .await?;
+
+    AuditEvent::new(BOT_DELETED)
+        .actor(owner.id)
+        .target(bot_id)
+        .ip(ip)
+        .detail("username", username)
+        .detail("policy", state.deletion_policy.as_str())
+        .detail("messages_deleted", summary.messages_deleted)
+        .log(&state.db)
+        .await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /bots/:username/keys` — every key of the bot, revoked ones included.
+pub async fn list_keys(
+    State(state): State<BotsState>,
+    CurrentUser(owner): CurrentUser,
+    Path(username): Path<String>,
+) -> Result<Json<Vec<ApiKeyView>>, AppError> {
+    let bot = find_own_bot(&state.db, &owner, &username).await?;
+    let keys = ApiKeys::find()
+        .filter(api_keys::Column::UserId.eq(bot.id))
+        .order_by_desc(api_keys::Column::Id)
+        .all(&state.db)
+        .await?;
+    Ok(Json(keys.into_iter().map(ApiKeyView::from).collect()))
+}
+
+/// `POST /bots/:username/keys` — issue a key. The secret is in this
+/// response only.
+pub async fn create_key(
+    State(state): State<BotsState>,
+    CurrentUser(owner): CurrentUser,
+    ClientIp(ip): ClientIp,
+    Path(username): Path<String>,
+    Json(payload): Json<CreateKeyRequest>,
+) -> Result<(StatusCode, Json<NewApiKey>), AppError> {
+    let bot = find_own_bot(&state.db, &owner, &username).await?;
+    let name = payload.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > 64 {
+        return Err(AppError::bad_request("Key name must be 1 to 64 characters"));
+    }
+    let scopes = Scopes::new(payload.scopes);
+    if scopes.is_empty() {
+        return Err(AppError::bad_request("A key needs at least one scope"));
+    }
+    let expires_at = match payload.expires_in_days {
+        None => None,
+        Some(days) if (1..=MAX_KEY_LIFETIME_DAYS).contains(&days) => {
+            Some(Utc::now() + Duration::days(days))
+        }
+        Some(_) => {
+            return Err(AppError::bad_request(format!(
+                "expires_in_days must be between 1 and {}",
+                MAX_KEY_LIFETIME_DAYS
+            )))
+        }
+    };
+
+    let active = ApiKeys::find()
+        .filter(api_keys::Column::UserId.eq(bot.id))
+        .filter(api_keys::Column::RevokedAt.is_null())
+        .count(&state.db)
+        .await?;
+    if active >= MAX_KEYS_PER_BOT {
+        return Err(AppError::Conflict(format!(
+            "At most {} unrevoked keys per bot",
+            MAX_KEYS_PER_BOT
+        )));
+    }
+
+    let key = insert_key(&state.db, &bot, &owner, name, &scopes, expires_at).await?;
+    AuditEvent::new(BOT_KEY_CREATED)
+        .actor(owner.id)
+        .target(bot.id)
+        .ip(ip)
+        .detail("key_id", key.view.id)
+        .detail("scopes", scopes.to_stored())
+        .log(&state.db)
+        .await;
+    Ok((StatusCode::CREATED, Json(key)))
+}
+
+/// `POST /bots/:username/keys/:id/rotate` — replace a key with a new secret
+/// under the same name, scopes and expiry. The old key stops working at once.
+pub async fn rotate_key(
+    State(state): State<BotsState>,
+    CurrentUser(owner): CurrentUser,
+    ClientIp(ip): ClientIp,
+    Path((username, id)): Path<(String, i32)>,
+) -> Result<(StatusCode, Json<NewApiKey>), AppError> {
+    let bot = find_own_bot(&state.db, &owner, &username).await?;
+    let old = find_key(&state.db, &bot, id).await?;
+    if !old.is_active() {
+        return Err(AppError::Conflict(
+            "Only active keys can be rotated".to_string(),
+        ));
+    }
+
+    let txn = state.db.begin().await?;
+    let scopes = Scopes::from_stored(&old.scopes);
+    let key = insert_key(
+        &txn,
+        &bot,
+        &owner,
+        old.name.clone(),
+        &scopes,
+        old.expires_at,
+    )
+    .await?;
+    let mut revoked: api_keys::ActiveModel = old.into();
+    revoked.revoked_at = Set(Some(Utc::now()));
+    revoked.update(&txn).await?;
+    txn.commit().await?;
+
+    // Sockets don't remember which key they used; make them all reconnect
+    disconnect_user(&state.online, &bot.username, "API key rotated").await;
+    AuditEvent::new(BOT_KEY_ROTATED)
+        .actor(owner.id)
+        .target(bot.id)
+        .ip(ip)
+        .detail("key_id", id)
+        .detail("new_key_id", key.view.id)
+        .log(&state.db)
+        .await;
+    Ok((StatusCode::CREATED, Json(key)))
+}
+
+/// `DELETE /bots/:username/keys/:id` — revoke a key. Idempotent.
+pub async fn revoke_key(
+    State(state): State<BotsState>,
+    CurrentUser(owner): CurrentUser,
+    ClientIp(ip): ClientIp,
+    Path((username, id)): Path<(String, i32)>,
+) -> Result<StatusCode, AppError> {
+    let bot = find_own_bot(&state.db, &owner, &username).await?;
+    let key = find_key(&state.db, &bot, id).await?;
+    if key.revoked_at.is_some() {
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    let mut revoked: api_keys::ActiveModel = key.into();
+    revoked.revoked_at = Set(Some(Utc::now()));
+    revoked.update(&state.db).await?;
+
+    disconnect_user(&state.online, &bot.username, "API key revoked").await;
+    AuditEvent::new(BOT_KEY_REVOKED)
+        .actor(owner.id)
+        .target(bot.id)
+        .ip(ip)
+        .detail("key_id", id)
+        .log(&state.db)
+        .await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /profiles/:username` — enough for clients to show who is a bot.
+pub async fn get_profile(
+    State(state): State<BotsState>,
+    MessageReader(_): MessageReader,
+    Path(username): Path<String>,
+) -> Result<Json<ProfileView>, AppError> {
+    let user = Users::find()
+        .filter(users::Column::Username.eq(&username))
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AppError::not_found("User"))?;
+    let bot_owner = match user.bot_owner_id {
+        Some(owner_id) => Users::find_by_id(owner_id)
+            .one(&state.db)
+            .await?
+            .map(|owner| owner.username),
+        None => None,
+    };
+    Ok(Json(ProfileView {
+        username: user.username,
+        is_bot: user.is_bot,
+        bot_owner,
+        created_at: user.created_at,
+    }))
+}
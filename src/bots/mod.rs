@@ -0,0 +1,39 @@
+//! Bot accounts. A bot is a `users` row owned by the person who created it;
+//! it signs in with API keys instead of Firebase and otherwise sends and
+//! receives like anyone else.
+
+pub mod handlers;
+pub mod routes;
+pub mod types;
+
+/// Bots one person may own at a time.
+pub const MAX_BOTS_PER_OWNER: u64 = 10;
+
+/// Unrevoked keys one bot may hold at a time.
+pub const MAX_KEYS_PER_BOT: u64 = 10;
+
+/// Bot usernames end in this, which also keeps them clear of Firebase UIDs.
+pub const BOT_USERNAME_SUFFIX: &str = "_bot";
+
+/// Placeholder `phone_number` for a bot, in the style of a deleted
+/// account's tombstone, so no bot row ever matches a phone sign-in.
+pub fn bot_phone(user_id: i32) -> String {
+    format!("bot-{}", user_id)
+}
+
+/// Lowercase letters, digits and underscores, 5 to 32 characters, ending
+/// in `_bot`.
+pub fn validate_bot_username(username: &str) -> Result<(), String> {
+    if !(5..=32).contains(&username.len())
+        || !username
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        || !username.ends_with(BOT_USERNAME_SUFFIX)
+    {
+        return Err(format!(
+            "Bot usernames are 5 to 32 lowercase letters, digits or underscores and end in '{}'",
+            BOT_USERNAME_SUFFIX
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,20 @@
+use axum::{
+    routing::{delete, get, post},
+    Router,
+};
+
+use crate::bots::handlers::{
+    create_bot, create_key, delete_bot, get_profile, list_bots, list_keys, revoke_key, rotate_key,
+    BotsState,
+};
+
+pub fn configure_bot_routes(state: BotsState) -> Router {
+    Router::new()
+        .route("/bots", get(list_bots).post(create_bot))
+        .route("/bots/:username", delete(delete_bot))
+        .route("/bots/:username/keys", get(list_keys).post(create_key))
+        .route("/bots/:username/keys/:id", delete(revoke_key))
+        .route("/bots/:username/keys/:id/rotate", post(rotate_key))
+        .route("/profiles/:username", get(get_profile))
+        .with_state(state)
+}
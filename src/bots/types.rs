@@ -0,0 +1,85 @@
+use crate::admin::types::account_status;
+use crate::auth::{Scope, Scopes};
+use crate::entity::{api_keys, users};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct CreateBotRequest {
+    pub username: String,
+}
+
+#[derive(Serialize)]
+pub struct BotView {
+    pub id: i32,
+    pub username: String,
+    pub status: &'static str,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl From<users::Model> for BotView {
+    fn from(bot: users::Model) -> Self {
+        Self {
+            status: account_status(&bot),
+            id: bot.id,
+            username: bot.username,
+            created_at: bot.created_at,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateKeyRequest {
+    /// Label to tell keys apart, e.g. where the key is deployed
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// Leave out for a key that never expires
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct ApiKeyView {
+    pub id: i32,
+    pub name: String,
+    /// Start of the key, e.g. `wsk_1a2b3c4d`
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<api_keys::Model> for ApiKeyView {
+    fn from(key: api_keys::Model) -> Self {
+        Self {
+            scopes: Scopes::from_stored(&key.scopes).to_vec(),
+            id: key.id,
+            name: key.name,
+            prefix: key.prefix,
+            created_at: key.created_at,
+            expires_at: key.expires_at,
+            last_used_at: key.last_used_at,
+            revoked_at: key.revoked_at,
+        }
+    }
+}
+
+/// A key as returned by create and rotate: the only time the secret is shown.
+#[derive(Serialize)]
+pub struct NewApiKey {
+    #[serde(flatten)]
+    pub view: ApiKeyView,
+    pub api_key: String,
+}
+
+/// What anyone signed in may see about an account.
+#[derive(Serialize)]
+pub struct ProfileView {
+    pub username: String,
+    /// Render messages from this account as coming from a bot
+    pub is_bot: bool,
+    /// Username of the person who runs the bot
+    pub bot_owner: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
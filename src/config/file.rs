@@ -0,0 +1,124 @@
+use serde::Deserialize;
+
+/// Shape of the optional TOML config file. Every key is optional; environment
+/// variables override whatever is set here. Unknown keys are rejected so
+/// typos surface at startup.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    pub server: ServerSection,
+    pub database: DatabaseSection,
+    pub redis: RedisSection,
+    pub auth: AuthSection,
+    pub storage: StorageSection,
+    pub limits: LimitsSection,
+    pub websocket: WebSocketSection,
+    pub retention: RetentionSection,
+    pub accounts: AccountsSection,
+    pub webhooks: WebhooksSection,
+    pub features: FeaturesSection,
+    pub telemetry: TelemetrySection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSection {
+    pub port: Option<u16>,
+    pub cors_origins: Option<Vec<String>>,
+    pub trusted_proxies: Option<Vec<String>>,
+    pub shutdown_delay_secs: Option<u64>,
+    pub shutdown_timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSection {
+    pub url: Option<String>,
+    pub max_connections: Option<u32>,
+    pub min_connections: Option<u32>,
+    pub connect_timeout_secs: Option<u64>,
+    pub auto_migrate: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedisSection {
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthSection {
+    pub jwt_secret: Option<String>,
+    pub url_signing_secret: Option<String>,
+    pub firebase_api_key: Option<String>,
+    pub firebase_project_id: Option<String>,
+    pub admin_usernames: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageSection {
+    pub backend: Option<String>,
+    pub dir: Option<String>,
+    pub s3_endpoint: Option<String>,
+    pub s3_bucket: Option<String>,
+    pub s3_region: Option<String>,
+    pub s3_access_key_id: Option<String>,
+    pub s3_secret_access_key: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsSection {
+    pub attachment_max_bytes: Option<usize>,
+    pub attachment_url_ttl_secs: Option<i64>,
+    pub export_url_ttl_secs: Option<i64>,
+    pub export_retention_hours: Option<i64>,
+    pub event_log_retention_hours: Option<i64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebSocketSection {
+    pub ping_interval_secs: Option<u64>,
+    pub max_missed_pongs: Option<u32>,
+    pub idle_timeout_secs: Option<u64>,
+    pub outbound_queue_size: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionSection {
+    pub message_retention_days: Option<i64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccountsSection {
+    pub deletion_policy: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhooksSection {
+    pub max_attempts: Option<i32>,
+    pub timeout_secs: Option<u64>,
+    pub delivery_retention_hours: Option<i64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeaturesSection {
+    pub search: Option<bool>,
+    pub scheduled_messages: Option<bool>,
+    pub metrics: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetrySection {
+    pub log_format: Option<String>,
+    pub otlp_endpoint: Option<String>,
+    pub service_name: Option<String>,
+}
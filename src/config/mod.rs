@@ -0,0 +1,872 @@
+pub mod file;
+
+use crate::account::types::DeletionPolicy;
+use crate::retention::RetentionPolicy;
+use axum::http::HeaderValue;
+use chrono::Duration;
+use file::FileConfig;
+use ipnet::IpNet;
+use reqwest::Url;
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Config file read when `CONFIG_FILE` is not set, if it exists.
+pub const DEFAULT_CONFIG_FILE: &str = "whisper.toml";
+
+/// All server settings, loaded once at startup from the environment and an
+/// optional TOML file. Environment variables win over the file.
+#[derive(Clone)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub redis: RedisConfig,
+    pub auth: AuthConfig,
+    pub storage: StorageConfig,
+    pub limits: LimitsConfig,
+    pub websocket: WebSocketConfig,
+    pub retention: RetentionPolicy,
+    pub deletion_policy: DeletionPolicy,
+    pub webhooks: WebhookConfig,
+    pub features: Features,
+    pub telemetry: TelemetryConfig,
+}
+
+#[derive(Clone)]
+pub struct ServerConfig {
+    pub port: u16,
+    pub cors_origins: CorsOrigins,
+    pub trusted_proxies: TrustedProxies,
+    /// How long `/readyz` fails after SIGTERM before connections are refused
+    pub shutdown_delay: std::time::Duration,
+    /// How long a SIGTERM drain may take before the process exits anyway
+    pub shutdown_timeout: std::time::Duration,
+}
+
+#[derive(Clone)]
+pub enum CorsOrigins {
+    Any,
+    List(Vec<HeaderValue>),
+}
+
+/// Peers whose `X-Forwarded-For` is believed. Empty, the default, means the
+/// socket peer is always the client.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(pub Vec<IpNet>);
+
+impl TrustedProxies {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(ip))
+    }
+}
+
+#[derive(Clone)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub connect_timeout: std::time::Duration,
+    /// Apply pending migrations at startup instead of refusing to serve
+    pub auto_migrate: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct RedisConfig {
+    /// `redis://` or `rediss://` URL; readiness only checks Redis when set
+    pub url: Option<Url>,
+}
+
+#[derive(Clone)]
+pub struct AuthConfig {
+    pub jwt_secret: String,
+    /// Signs attachment and export download links. Kept apart from
+    /// `jwt_secret` so a leaked link key can't mint tokens, or the reverse
+    pub url_signing_secret: String,
+    pub firebase: FirebaseConfig,
+    /// Promoted to admin at startup, see `promote_bootstrap_admins`
+    pub admin_usernames: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct FirebaseConfig {
+    pub api_key: String,
+    pub project_id: String,
+}
+
+#[derive(Clone)]
+pub enum StorageConfig {
+    Fs { dir: PathBuf },
+    S3(S3Config),
+}
+
+#[derive(Clone)]
+pub struct S3Config {
+    pub endpoint: Url,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+#[derive(Clone)]
+pub struct LimitsConfig {
+    pub attachment_max_bytes: usize,
+    pub attachment_url_ttl: Duration,
+    pub export_url_ttl: Duration,
+    pub export_retention: Duration,
+    /// How long sync events are kept for session resume
+    pub event_log_retention: Duration,
+}
+
+#[derive(Clone, Copy)]
+pub struct WebSocketConfig {
+    /// How often the server pings each socket
+    pub ping_interval: std::time::Duration,
+    /// Pings that may go unanswered before the socket is treated as dead
+    pub max_missed_pongs: u32,
+    /// Close sockets that send nothing but pongs and heartbeats for this
+    /// long; `None` keeps them open
+    pub idle_timeout: Option<std::time::Duration>,
+    /// Frames buffered per socket before ephemeral ones are dropped and,
+    /// failing that, the socket is closed for resync
+    pub outbound_queue_size: usize,
+}
+
+#[derive(Clone, Copy)]
+pub struct WebhookConfig {
+    /// Attempts per delivery, the first included, before it is marked failed
+    pub max_attempts: i32,
+    /// How long a receiver has to answer one attempt
+    pub timeout: std::time::Duration,
+    /// How long finished deliveries stay in the delivery log
+    pub delivery_retention: Duration,
+}
+
+/// Optional subsystems that can be switched off per deployment.
+#[derive(Clone, Copy)]
+pub struct Features {
+    pub search: bool,
+    pub scheduled_messages: bool,
+    /// Serve Prometheus metrics on `/metrics`
+    pub metrics: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct TelemetryConfig {
+    pub log_format: LogFormat,
+    /// OTLP/gRPC collector spans are exported to; no export when unset
+    pub otlp_endpoint: Option<Url>,
+    pub service_name: String,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines, for local development
+    #[default]
+    Text,
+    /// One JSON object per line with the active spans, for log shippers
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "unknown log format '{}', expected 'text' or 'json'",
+                other
+            )),
+        }
+    }
+}
+
+/// Every problem found while loading the config, reported together.
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "invalid configuration ({} problem(s)):", self.0.len())?;
+        for problem in &self.0 {
+            writeln!(f, "  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Load from `CONFIG_FILE` (or `whisper.toml` if present) and the environment.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut loader = Loader::new(|name| std::env::var(name).ok());
+        let file = match loader.env_var("CONFIG_FILE") {
+            Some(path) => loader.read_file(Path::new(&path)),
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                loader.read_file(Path::new(DEFAULT_CONFIG_FILE))
+            }
+            None => FileConfig::default(),
+        };
+        let config = loader.build(file);
+        if loader.errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(loader.errors))
+        }
+    }
+}
+
+fn parse_bool(raw: &str) -> Result<bool, String> {
+    match raw.to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Ok(true),
+        "false" | "0" | "no" | "off" => Ok(false),
+        _ => Err("expected true or false".to_string()),
+    }
+}
+
+fn parse_list(raw: &str) -> Result<Vec<String>, String> {
+    Ok(raw
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Looks up an environment variable; `std::env::var` outside of tests.
+type EnvLookup = Box<dyn Fn(&str) -> Option<String>>;
+
+struct Loader {
+    env: EnvLookup,
+    errors: Vec<String>,
+}
+
+impl Loader {
+    fn new(env: impl Fn(&str) -> Option<String> + 'static) -> Self {
+        Self {
+            env: Box::new(env),
+            errors: Vec::new(),
+        }
+    }
+
+    /// The trimmed variable, treating blank as unset.
+    fn env_var(&self, name: &str) -> Option<String> {
+        (self.env)(name)
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    }
+
+    fn error(&mut self, problem: impl Into<String>) {
+        self.errors.push(problem.into());
+    }
+
+    fn read_file(&mut self, path: &Path) -> FileConfig {
+        let parsed = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| toml::from_str(&text).map_err(|e| e.to_string()));
+        parsed.unwrap_or_else(|e| {
+            self.error(format!("config file {}: {}", path.display(), e));
+            FileConfig::default()
+        })
+    }
+
+    /// `env` if set, parsed with `parse`, otherwise the file value.
+    fn value_with<T>(
+        &mut self,
+        env: &str,
+        file: Option<T>,
+        parse: impl FnOnce(&str) -> Result<T, String>,
+    ) -> Option<T> {
+        match self.env_var(env) {
+            Some(raw) => match parse(&raw) {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    self.error(format!("{}: invalid value '{}': {}", env, raw, e));
+                    None
+                }
+            },
+            None => file,
+        }
+    }
+
+    fn value<T: FromStr>(&mut self, env: &str, file: Option<T>) -> Option<T>
+    where
+        T::Err: Display,
+    {
+        self.value_with(env, file, |raw| {
+            raw.parse().map_err(|e: T::Err| e.to_string())
+        })
+    }
+
+    fn required<T: FromStr + Default>(&mut self, env: &str, key: &str, file: Option<T>) -> T
+    where
+        T::Err: Display,
+    {
+        let before = self.errors.len();
+        match self.value(env, file) {
+            Some(value) => value,
+            None => {
+                // Don't report a value that was set but unparsable twice
+                if self.errors.len() == before {
+                    self.error(format!(
+                        "{} (or `{}` in the config file) must be set",
+                        env, key
+                    ));
+                }
+                T::default()
+            }
+        }
+    }
+
+    fn positive<T: PartialOrd + Default + Display + Copy>(&mut self, name: &str, value: T) -> T {
+        if value <= T::default() {
+            self.error(format!("{} must be greater than zero, got {}", name, value));
+        }
+        value
+    }
+
+    fn build(&mut self, file: FileConfig) -> Config {
+        let FileConfig {
+            server,
+            database,
+            redis,
+            auth,
+            storage,
+            limits,
+            websocket,
+            retention,
+            accounts,
+            webhooks,
+            features,
+            telemetry,
+        } = file;
+
+        // [server]
+        let port = self.value("PORT", server.port).unwrap_or(3000);
+        if port == 0 {
+            self.error("PORT must not be 0");
+        }
+        let origins = self
+            .value_with("CORS_ORIGINS", server.cors_origins, parse_list)
+            .unwrap_or_else(|| vec!["*".to_string()]);
+        let cors_origins = self.cors_origins(origins);
+        let trusted_proxies = self
+            .value_with("TRUSTED_PROXIES", server.trusted_proxies, parse_list)
+            .unwrap_or_default();
+        let trusted_proxies = self.trusted_proxies(trusted_proxies);
+        let shutdown_delay_secs = self
+            .value("SHUTDOWN_DELAY_SECS", server.shutdown_delay_secs)
+            .unwrap_or(5);
+        let shutdown_timeout_secs = self
+            .value("SHUTDOWN_TIMEOUT_SECS", server.shutdown_timeout_secs)
+            .unwrap_or(20);
+        let shutdown_timeout_secs = self.positive("SHUTDOWN_TIMEOUT_SECS", shutdown_timeout_secs);
+
+        // [database]
+        let url = self.required("DATABASE_URL", "database.url", database.url);
+        let max_connections = self
+            .value("DB_MAX_CONNECTIONS", database.max_connections)
+            .unwrap_or(10);
+        let max_connections = self.positive("DB_MAX_CONNECTIONS", max_connections);
+        let min_connections = self
+            .value("DB_MIN_CONNECTIONS", database.min_connections)
+            .unwrap_or(1);
+        if min_connections > max_connections {
+            self.error(format!(
+                "DB_MIN_CONNECTIONS ({}) must not exceed DB_MAX_CONNECTIONS ({})",
+                min_connections, max_connections
+            ));
+        }
+        let connect_timeout_secs = self
+            .value("DB_CONNECT_TIMEOUT_SECS", database.connect_timeout_secs)
+            .unwrap_or(8);
+        let connect_timeout_secs = self.positive("DB_CONNECT_TIMEOUT_SECS", connect_timeout_secs);
+        let auto_migrate = self
+            .value_with("AUTO_MIGRATE", database.auto_migrate, parse_bool)
+            .unwrap_or(false);
+
+        // [redis]
+        let redis_url = self
+            .value_with("REDIS_URL", redis.url, |raw| Ok(raw.to_string()))
+            .and_then(|raw| match Url::parse(&raw) {
+                Ok(url) if matches!(url.scheme(), "redis" | "rediss") => Some(url),
+                _ => {
+                    self.error("REDIS_URL: expected a redis:// or rediss:// URL");
+                    None
+                }
+            });
+
+        // [auth]
+        let jwt_secret = self.required("JWT_SECRET", "auth.jwt_secret", auth.jwt_secret);
+        let url_signing_secret = self.required(
+            "URL_SIGNING_SECRET",
+            "auth.url_signing_secret",
+            auth.url_signing_secret,
+        );
+        if !url_signing_secret.is_empty() && url_signing_secret == jwt_secret {
+            self.error("URL_SIGNING_SECRET must differ from JWT_SECRET");
+        }
+        let api_key = self.required(
+            "FIREBASE_API_KEY",
+            "auth.firebase_api_key",
+            auth.firebase_api_key,
+        );
+        let project_id = self.required(
+            "FIREBASE_PROJECT_ID",
+            "auth.firebase_project_id",
+            auth.firebase_project_id,
+        );
+        let admin_usernames = self
+            .value_with("ADMIN_USERNAMES", auth.admin_usernames, parse_list)
+            .unwrap_or_default();
+
+        // [storage]
+        let storage = self.storage(storage);
+
+        // [limits]
+        let attachment_max_bytes = self
+            .value("ATTACHMENT_MAX_BYTES", limits.attachment_max_bytes)
+            .unwrap_or(10 * 1024 * 1024);
+        let attachment_max_bytes = self.positive("ATTACHMENT_MAX_BYTES", attachment_max_bytes);
+        let attachment_url_ttl = self
+            .value("ATTACHMENT_URL_TTL_SECS", limits.attachment_url_ttl_secs)
+            .unwrap_or(300);
+        let attachment_url_ttl = self.positive("ATTACHMENT_URL_TTL_SECS", attachment_url_ttl);
+        let export_url_ttl = self
+            .value("EXPORT_URL_TTL_SECS", limits.export_url_ttl_secs)
+            .unwrap_or(3600);
+        let export_url_ttl = self.positive("EXPORT_URL_TTL_SECS", export_url_ttl);
+        let export_retention = self
+            .value("EXPORT_RETENTION_HOURS", limits.export_retention_hours)
+            .unwrap_or(72);
+        let export_retention = self.positive("EXPORT_RETENTION_HOURS", export_retention);
+        let event_log_retention = self
+            .value(
+                "EVENT_LOG_RETENTION_HOURS",
+                limits.event_log_retention_hours,
+            )
+            .unwrap_or(72);
+        let event_log_retention = self.positive("EVENT_LOG_RETENTION_HOURS", event_log_retention);
+
+        // [websocket]
+        let ping_interval_secs = self
+            .value("WS_PING_INTERVAL_SECS", websocket.ping_interval_secs)
+            .unwrap_or(30);
+        let ping_interval_secs = self.positive("WS_PING_INTERVAL_SECS", ping_interval_secs);
+        let max_missed_pongs = self
+            .value("WS_MAX_MISSED_PONGS", websocket.max_missed_pongs)
+            .unwrap_or(2);
+        let max_missed_pongs = self.positive("WS_MAX_MISSED_PONGS", max_missed_pongs);
+        let idle_timeout_secs = self
+            .value("WS_IDLE_TIMEOUT_SECS", websocket.idle_timeout_secs)
+            .unwrap_or(0);
+        let outbound_queue_size = self
+            .value("WS_OUTBOUND_QUEUE_SIZE", websocket.outbound_queue_size)
+            .unwrap_or(256);
+        let outbound_queue_size = self.positive("WS_OUTBOUND_QUEUE_SIZE", outbound_queue_size);
+
+        // [retention], [accounts]
+        let retention_days = self.value("MESSAGE_RETENTION_DAYS", retention.message_retention_days);
+        if retention_days.is_some_and(|days| days < 0) {
+            self.error("MESSAGE_RETENTION_DAYS must not be negative");
+        }
+        let deletion_policy = self
+            .value_with("ACCOUNT_DELETION_POLICY", accounts.deletion_policy, |raw| {
+                Ok(raw.to_string())
+            })
+            .map(|raw| {
+                raw.parse().unwrap_or_else(|e| {
+                    self.error(format!("ACCOUNT_DELETION_POLICY: {}", e));
+                    DeletionPolicy::Anonymize
+                })
+            })
+            .unwrap_or(DeletionPolicy::Anonymize);
+
+        // [webhooks]
+        let webhook_max_attempts = self
+            .value("WEBHOOK_MAX_ATTEMPTS", webhooks.max_attempts)
+            .unwrap_or(10);
+        let webhook_max_attempts = self.positive("WEBHOOK_MAX_ATTEMPTS", webhook_max_attempts);
+        let webhook_timeout_secs = self
+            .value("WEBHOOK_TIMEOUT_SECS", webhooks.timeout_secs)
+            .unwrap_or(10);
+        let webhook_timeout_secs = self.positive("WEBHOOK_TIMEOUT_SECS", webhook_timeout_secs);
+        let webhook_retention = self
+            .value(
+                "WEBHOOK_DELIVERY_RETENTION_HOURS",
+                webhooks.delivery_retention_hours,
+            )
+            .unwrap_or(168);
+        let webhook_retention =
+            self.positive("WEBHOOK_DELIVERY_RETENTION_HOURS", webhook_retention);
+
+        // [features]
+        let features = Features {
+            search: self
+                .value_with("FEATURE_SEARCH", features.search, parse_bool)
+                .unwrap_or(true),
+            scheduled_messages: self
+                .value_with(
+                    "FEATURE_SCHEDULED_MESSAGES",
+                    features.scheduled_messages,
+                    parse_bool,
+                )
+                .unwrap_or(true),
+            metrics: self
+                .value_with("FEATURE_METRICS", features.metrics, parse_bool)
+                .unwrap_or(true),
+        };
+
+        // [telemetry]
+        let log_format = self
+            .value_with(
+                "LOG_FORMAT",
+                telemetry.log_format,
+                |raw| Ok(raw.to_string()),
+            )
+            .map(|raw| {
+                raw.parse().unwrap_or_else(|e| {
+                    self.error(format!("LOG_FORMAT: {}", e));
+                    LogFormat::Text
+                })
+            })
+            .unwrap_or_default();
+        let otlp_endpoint = self.value_with(
+            "OTEL_EXPORTER_OTLP_ENDPOINT",
+            telemetry.otlp_endpoint,
+            |raw| Ok(raw.to_string()),
+        );
+        let otlp_endpoint = otlp_endpoint.and_then(|raw| match Url::parse(&raw) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => Some(url),
+            _ => {
+                self.error(format!(
+                    "OTEL_EXPORTER_OTLP_ENDPOINT: '{}' is not an http(s) URL",
+                    raw
+                ));
+                None
+            }
+        });
+        let service_name = self
+            .value("OTEL_SERVICE_NAME", telemetry.service_name)
+            .unwrap_or_else(|| "whisper".to_string());
+
+        Config {
+            server: ServerConfig {
+                port,
+                cors_origins,
+                trusted_proxies,
+                shutdown_delay: std::time::Duration::from_secs(shutdown_delay_secs),
+                shutdown_timeout: std::time::Duration::from_secs(shutdown_timeout_secs),
+            },
+            database: DatabaseConfig {
+                url,
+                max_connections,
+                min_connections,
+                connect_timeout: std::time::Duration::from_secs(connect_timeout_secs),
+                auto_migrate,
+            },
+            redis: RedisConfig { url: redis_url },
+            auth: AuthConfig {
+                jwt_secret,
+                url_signing_secret,
+                firebase: FirebaseConfig {
+                    api_key,
+                    project_id,
+                },
+                admin_usernames,
+            },
+            storage,
+            limits: LimitsConfig {
+                attachment_max_bytes,
+                attachment_url_ttl: Duration::seconds(attachment_url_ttl),
+                export_url_ttl: Duration::seconds(export_url_ttl),
+                export_retention: Duration::hours(export_retention),
+                event_log_retention: Duration::hours(event_log_retention),
+            },
+            websocket: WebSocketConfig {
+                ping_interval: std::time::Duration::from_secs(ping_interval_secs),
+                max_missed_pongs,
+                idle_timeout: (idle_timeout_secs > 0)
+                    .then(|| std::time::Duration::from_secs(idle_timeout_secs)),
+                outbound_queue_size,
+            },
+            retention: RetentionPolicy::from_days(retention_days),
+            deletion_policy,
+            webhooks: WebhookConfig {
+                max_attempts: webhook_max_attempts,
+                timeout: std::time::Duration::from_secs(webhook_timeout_secs),
+                delivery_retention: Duration::hours(webhook_retention),
+            },
+            features,
+            telemetry: TelemetryConfig {
+                log_format,
+                otlp_endpoint,
+                service_name,
+            },
+        }
+    }
+
+    /// Addresses (`10.0.0.7`) or networks (`10.0.0.0/8`).
+    fn trusted_proxies(&mut self, entries: Vec<String>) -> TrustedProxies {
+        let mut nets = Vec::with_capacity(entries.len());
+        for entry in entries {
+            match entry.parse::<IpNet>() {
+                Ok(net) => nets.push(net),
+                Err(_) => match entry.parse::<IpAddr>() {
+                    Ok(ip) => nets.push(IpNet::from(ip)),
+                    Err(_) => self.error(format!(
+                        "TRUSTED_PROXIES: '{}' is not an IP address or network",
+                        entry
+                    )),
+                },
+            }
+        }
+        TrustedProxies(nets)
+    }
+
+    fn cors_origins(&mut self, origins: Vec<String>) -> CorsOrigins {
+        if origins.iter().any(|o| o == "*") {
+            if origins.len() > 1 {
+                self.error("CORS_ORIGINS: '*' cannot be combined with explicit origins");
+            }
+            return CorsOrigins::Any;
+        }
+        let mut values = Vec::with_capacity(origins.len());
+        for origin in origins {
+            match Url::parse(&origin) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => {
+                    match HeaderValue::from_str(origin.trim_end_matches('/')) {
+                        Ok(value) => values.push(value),
+                        Err(e) => self.error(format!("CORS_ORIGINS: '{}': {}", origin, e)),
+                    }
+                }
+                _ => self.error(format!(
+                    "CORS_ORIGINS: '{}' is not an http(s) origin",
+                    origin
+                )),
+            }
+        }
+        CorsOrigins::List(values)
+    }
+
+    fn storage(&mut self, storage: file::StorageSection) -> StorageConfig {
+        let backend = self
+            .value("STORAGE_BACKEND", storage.backend)
+            .unwrap_or_else(|| "fs".to_string());
+        let fs = |dir: Option<String>| StorageConfig::Fs {
+            dir: dir
+                .unwrap_or_else(|| "./data/attachments".to_string())
+                .into(),
+        };
+        match backend.as_str() {
+            "fs" => {
+                let dir = self.value("STORAGE_DIR", storage.dir);
+                fs(dir)
+            }
+            "s3" => {
+                let endpoint: String =
+                    self.required("S3_ENDPOINT", "storage.s3_endpoint", storage.s3_endpoint);
+                let bucket = self.required("S3_BUCKET", "storage.s3_bucket", storage.s3_bucket);
+                let region = self
+                    .value("S3_REGION", storage.s3_region)
+                    .unwrap_or_else(|| "us-east-1".to_string());
+                let access_key_id = self.required(
+                    "S3_ACCESS_KEY_ID",
+                    "storage.s3_access_key_id",
+                    storage.s3_access_key_id,
+                );
+                let secret_access_key = self.required(
+                    "S3_SECRET_ACCESS_KEY",
+                    "storage.s3_secret_access_key",
+                    storage.s3_secret_access_key,
+                );
+                if endpoint.is_empty() {
+                    return fs(None);
+                }
+                match Url::parse(&endpoint) {
+                    Ok(endpoint) => StorageConfig::S3(S3Config {
+                        endpoint,
+                        bucket,
+                        region,
+                        access_key_id,
+                        secret_access_key,
+                    }),
+                    Err(e) => {
+                        self.error(format!("S3_ENDPOINT: invalid URL '{}': {}", endpoint, e));
+                        fs(None)
+                    }
+                }
+            }
+            other => {
+                self.error(format!(
+                    "STORAGE_BACKEND: unknown backend '{}', expected 'fs' or 's3'",
+                    other
+                ));
+                fs(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const EXAMPLE: &str = include_str!("../../whisper.example.toml");
+    const SECRETS: &str = r#"[auth]
+jwt_secret = "secret"
+url_signing_secret = "url-secret"
+firebase_api_key = "key"
+firebase_project_id = "whisper-test"
+"#;
+
+    /// Load `toml` with `env` as the whole environment.
+    fn load_with_env(toml: &str, env: &[(&str, &str)]) -> Result<Config, Vec<String>> {
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let file: FileConfig = toml::from_str(toml).map_err(|e| vec![e.to_string()])?;
+        let mut loader = Loader::new(move |name| env.get(name).cloned());
+        let config = loader.build(file);
+        if loader.errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(loader.errors)
+        }
+    }
+
+    fn load(toml: &str) -> Result<Config, Vec<String>> {
+        load_with_env(toml, &[])
+    }
+
+    /// What `load` reports; empty if it succeeded.
+    fn problems(toml: &str) -> Vec<String> {
+        load(toml).err().unwrap_or_default()
+    }
+
+    #[test]
+    fn example_file_is_valid() {
+        let config = load(&EXAMPLE.replacen("[auth]\n", SECRETS, 1)).unwrap();
+        assert_eq!(config.server.port, 3000);
+        assert!(matches!(config.storage, StorageConfig::Fs { .. }));
+        assert_eq!(config.websocket.idle_timeout, None);
+        assert_eq!(config.auth.firebase.project_id, "whisper-test");
+    }
+
+    #[test]
+    fn missing_secrets_are_named() {
+        let errors = problems("");
+        for name in [
+            "DATABASE_URL",
+            "JWT_SECRET",
+            "URL_SIGNING_SECRET",
+            "FIREBASE_API_KEY",
+            "FIREBASE_PROJECT_ID",
+        ] {
+            assert!(
+                errors.iter().any(|e| e.starts_with(name)),
+                "{} not reported in {:?}",
+                name,
+                errors
+            );
+        }
+    }
+
+    #[test]
+    fn url_signing_secret_must_differ_from_jwt_secret() {
+        let toml = EXAMPLE.replacen("[auth]\n", SECRETS, 1);
+        let errors = load_with_env(&toml, &[("URL_SIGNING_SECRET", "secret")])
+            .err()
+            .unwrap();
+        assert_eq!(
+            errors,
+            vec!["URL_SIGNING_SECRET must differ from JWT_SECRET"]
+        );
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let errors = problems(&format!(
+            r#"{}
+[server]
+port = 0
+cors_origins = ["*", "https://example.com"]
+trusted_proxies = ["10.0.0.0/8", "proxy.internal"]
+
+[database]
+url = "postgres://localhost/whisper"
+max_connections = 2
+min_connections = 5
+
+[storage]
+backend = "s3"
+s3_endpoint = "not a url"
+
+[websocket]
+ping_interval_secs = 0
+
+[retention]
+message_retention_days = -1
+
+[telemetry]
+log_format = "yaml"
+"#,
+            SECRETS
+        ));
+
+        for expected in [
+            "PORT must not be 0",
+            "CORS_ORIGINS: '*' cannot be combined",
+            "TRUSTED_PROXIES: 'proxy.internal' is not an IP address or network",
+            "DB_MIN_CONNECTIONS (5) must not exceed DB_MAX_CONNECTIONS (2)",
+            "S3_BUCKET",
+            "S3_ENDPOINT: invalid URL",
+            "WS_PING_INTERVAL_SECS must be greater than zero",
+            "MESSAGE_RETENTION_DAYS must not be negative",
+            "LOG_FORMAT: unknown log format 'yaml'",
+        ] {
+            assert!(
+                errors.iter().any(|e| e.starts_with(expected)),
+                "{:?} not reported in {:?}",
+                expected,
+                errors
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_file_keys_are_rejected() {
+        let errors = problems("[server]\nprot = 3000\n");
+        assert!(errors[0].contains("unknown field `prot`"), "{:?}", errors);
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let toml = EXAMPLE.replacen("[auth]\n", SECRETS, 1);
+        let config = load_with_env(&toml, &[("PORT", "8080"), ("JWT_SECRET", "  ")]).unwrap();
+        assert_eq!(config.server.port, 8080);
+
+        let errors = load_with_env(&toml, &[("PORT", "eighty")]).err().unwrap();
+        assert_eq!(
+            errors,
+            vec!["PORT: invalid value 'eighty': invalid digit found in string"]
+        );
+    }
+
+    #[test]
+    fn parses_env_style_values() {
+        assert_eq!(parse_bool("Yes"), Ok(true));
+        assert_eq!(parse_bool("off"), Ok(false));
+        assert!(parse_bool("maybe").is_err());
+        assert_eq!(
+            parse_list(" a, b ,,c "),
+            Ok(vec!["a".to_string(), "b".to_string(), "c".to_string()])
+        );
+    }
+}
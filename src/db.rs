@@ -1,9 +1,46 @@
-use sea_orm::{Database, DatabaseConnection};
-use std::env;
+use crate::config::DatabaseConfig;
+use crate::metrics;
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{ConnectOptions, Database, DatabaseConnection, DbErr};
+use tracing::{error, info};
 
-pub async fn connect_database() -> DatabaseConnection {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    Database::connect(&database_url)
+pub async fn connect_database(config: &DatabaseConfig) -> DatabaseConnection {
+    let mut options = ConnectOptions::new(config.url.clone());
+    options
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .connect_timeout(config.connect_timeout)
+        .sqlx_logging(false);
+    let mut db = Database::connect(options)
         .await
-        .expect("Failed to connect to database")
+        .expect("Failed to connect to database");
+    db.set_metric_callback(metrics::observe_db_query);
+    db
+}
+
+/// Applies pending migrations when `auto_migrate` is set; otherwise returns
+/// the names of migrations the database is missing. The server must not serve
+/// against an outdated schema, so callers treat a non-empty list as fatal.
+pub async fn ensure_schema(
+    db: &DatabaseConnection,
+    auto_migrate: bool,
+) -> Result<Vec<String>, DbErr> {
+    if auto_migrate {
+        let pending = Migrator::get_pending_migrations(db).await?.len();
+        if pending > 0 {
+            info!("🗄️ Applying {} pending migration(s)", pending);
+            Migrator::up(db, None).await?;
+        }
+        return Ok(Vec::new());
+    }
+
+    let pending: Vec<String> = Migrator::get_pending_migrations(db)
+        .await?
+        .iter()
+        .map(|m| m.name().to_string())
+        .collect();
+    for name in &pending {
+        error!("🗄️ Pending migration: {}", name);
+    }
+    Ok(pending)
 }
@@ -0,0 +1,47 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A long-lived credential for a bot. Only a hash of the key is stored;
+/// the key itself is shown once, when created or rotated.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "api_keys")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// The bot the key authenticates as
+    pub user_id: i32,
+    pub name: String,
+    /// Leading part of the key, safe to show so owners can tell keys apart
+    pub prefix: String,
+    /// Hex SHA-256 of the full key
+    pub key_hash: String,
+    /// Space-separated, e.g. `messages:read messages:write`
+    pub scopes: String,
+    pub created_by: Option<i32>,
+    #[sea_orm(created_at)]
+    pub created_at: DateTimeUtc,
+    pub expires_at: Option<DateTimeUtc>,
+    pub last_used_at: Option<DateTimeUtc>,
+    pub revoked_at: Option<DateTimeUtc>,
+}
+
+impl Model {
+    pub fn is_active(&self) -> bool {
+        let expired = self
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= chrono::Utc::now());
+        self.revoked_at.is_none() && !expired
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::users::Entity",
+        from = "Column::UserId",
+        to = "super::users::Column::Id"
+    )]
+    User,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,56 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "attachments")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub message_id: Option<i32>,
+    pub uploader_id: i32,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    #[sea_orm(unique)]
+    pub storage_key: String,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub blurhash: Option<String>,
+    pub thumbnail_key: Option<String>,
+    pub duration_ms: Option<i32>,
+    /// Voice note amplitudes as a JSON array of 0..=100 values
+    pub waveform: Option<Json>,
+    /// `pending`, `processing`, `ready`, `failed` or `skipped` (nothing to extract)
+    pub processing_status: String,
+    pub processing_attempts: i32,
+    pub processing_error: Option<String>,
+    pub next_attempt_at: Option<DateTimeUtc>,
+    /// When a worker took the row for processing
+    pub claimed_at: Option<DateTimeUtc>,
+    #[sea_orm(created_at)]
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::messages::Entity",
+        from = "Column::MessageId",
+        to = "super::messages::Column::Id"
+    )]
+    Message,
+    #[sea_orm(
+        belongs_to = "super::users::Entity",
+        from = "Column::UploaderId",
+        to = "super::users::Column::Id"
+    )]
+    Uploader,
+}
+
+impl Related<super::messages::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Message.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,26 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Hash-chained log of security-relevant events. Rows are only ever
+/// appended; `hash` covers the row's contents and the previous row's hash,
+/// so editing or deleting an entry breaks the chain from that point on.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "audit_events")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub event_type: String,
+    pub actor_id: Option<i32>,
+    pub target_user_id: Option<i32>,
+    pub ip_address: Option<String>,
+    pub details: Json,
+    pub prev_hash: String,
+    #[sea_orm(unique)]
+    pub hash: String,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,23 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Per-conversation settings shared by both participants. A conversation is
+/// identified by its two user ids, stored lowest first.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "conversation_settings")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_low_id: i32,
+    pub user_high_id: i32,
+    /// Disappearing-message timer; `None` keeps messages until the server
+    /// retention limit, if any
+    pub disappearing_seconds: Option<i64>,
+    pub updated_by: Option<i32>,
+    pub updated_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
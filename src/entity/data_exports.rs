@@ -0,0 +1,35 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A user's request for a copy of their data. The archive is built in the
+/// background and removed from storage once `expires_at` passes.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "data_exports")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_id: i32,
+    /// `pending`, `processing`, `ready`, `failed` or `expired`
+    pub status: String,
+    pub storage_key: Option<String>,
+    pub size_bytes: Option<i64>,
+    pub error: Option<String>,
+    /// When a worker claimed the job; cleared once it finishes
+    pub claimed_at: Option<DateTimeUtc>,
+    #[sea_orm(created_at)]
+    pub created_at: DateTimeUtc,
+    pub completed_at: Option<DateTimeUtc>,
+    pub expires_at: Option<DateTimeUtc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::users::Entity",
+        from = "Column::UserId",
+        to = "super::users::Column::Id"
+    )]
+    User,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
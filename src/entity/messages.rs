@@ -12,6 +12,9 @@ pub struct Model {
     #[sea_orm(created_at)]
     pub created_at: DateTimeUtc,
     pub status: String,
+    /// When the sweeper deletes the message; from the conversation's
+    /// disappearing timer or the server-wide retention limit
+    pub expires_at: Option<DateTimeUtc>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -28,6 +31,8 @@ pub enum Relation {
         to = "super::users::Column::Id"
     )]
     Receiver,
+    #[sea_orm(has_many = "super::attachments::Entity")]
+    Attachments,
 }
 
 impl Related<super::users::Entity> for Entity {
@@ -36,4 +41,10 @@ impl Related<super::users::Entity> for Entity {
     }
 }
 
+impl Related<super::attachments::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Attachments.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
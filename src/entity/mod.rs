@@ -1,5 +1,29 @@
+pub mod api_keys;
+pub mod attachments;
+pub mod audit_events;
+pub mod conversation_settings;
+pub mod data_exports;
 pub mod messages;
+pub mod moderation_actions;
+pub mod reports;
+pub mod scheduled_messages;
+pub mod user_blocks;
+pub mod user_events;
 pub mod users;
+pub mod webhook_deliveries;
+pub mod webhooks;
 
+pub use api_keys::Entity as ApiKeys;
+pub use attachments::Entity as Attachments;
+pub use audit_events::Entity as AuditEvents;
+pub use conversation_settings::Entity as ConversationSettings;
+pub use data_exports::Entity as DataExports;
 pub use messages::Entity as Messages;
+pub use moderation_actions::Entity as ModerationActions;
+pub use reports::Entity as Reports;
+pub use scheduled_messages::Entity as ScheduledMessages;
+pub use user_blocks::Entity as UserBlocks;
+pub use user_events::Entity as UserEvents;
 pub use users::Entity as Users;
+pub use webhook_deliveries::Entity as WebhookDeliveries;
+pub use webhooks::Entity as Webhooks;
@@ -0,0 +1,30 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Append-only log of every moderator action.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "moderation_actions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub moderator_id: i32,
+    pub action: String,
+    pub report_id: Option<i32>,
+    pub target_user_id: Option<i32>,
+    pub target_message_id: Option<i32>,
+    pub note: Option<String>,
+    #[sea_orm(created_at)]
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::users::Entity",
+        from = "Column::ModeratorId",
+        to = "super::users::Column::Id"
+    )]
+    Moderator,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,48 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "reports")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub reporter_id: i32,
+    pub reported_user_id: i32,
+    pub message_id: Option<i32>,
+    /// Copy of the reported message text at report time, kept even if the
+    /// message is later edited or removed
+    pub message_snapshot: Option<String>,
+    pub reason: String,
+    pub details: Option<String>,
+    /// `open`, `resolved` or `dismissed`
+    pub status: String,
+    pub resolution_note: Option<String>,
+    pub resolved_by: Option<i32>,
+    pub resolved_at: Option<DateTimeUtc>,
+    #[sea_orm(created_at)]
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::users::Entity",
+        from = "Column::ReporterId",
+        to = "super::users::Column::Id"
+    )]
+    Reporter,
+    #[sea_orm(
+        belongs_to = "super::users::Entity",
+        from = "Column::ReportedUserId",
+        to = "super::users::Column::Id"
+    )]
+    ReportedUser,
+    #[sea_orm(
+        belongs_to = "super::messages::Entity",
+        from = "Column::MessageId",
+        to = "super::messages::Column::Id"
+    )]
+    Message,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
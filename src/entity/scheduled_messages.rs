@@ -0,0 +1,42 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A message composed now and sent by the scheduler at `send_at`.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "scheduled_messages")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub sender_id: i32,
+    pub recipient_id: i32,
+    pub message: String,
+    pub send_at: DateTimeUtc,
+    /// `pending`, `sending`, `sent`, `cancelled` or `failed`
+    pub status: String,
+    /// When a scheduler instance claimed the row for sending
+    pub claimed_at: Option<DateTimeUtc>,
+    /// The delivered message, once sent
+    pub message_id: Option<i32>,
+    pub error: Option<String>,
+    #[sea_orm(created_at)]
+    pub created_at: DateTimeUtc,
+    pub updated_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::users::Entity",
+        from = "Column::SenderId",
+        to = "super::users::Column::Id"
+    )]
+    Sender,
+    #[sea_orm(
+        belongs_to = "super::users::Entity",
+        from = "Column::RecipientId",
+        to = "super::users::Column::Id"
+    )]
+    Recipient,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
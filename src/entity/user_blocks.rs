@@ -0,0 +1,37 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "user_blocks")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub blocker_id: i32,
+    pub blocked_id: i32,
+    #[sea_orm(created_at)]
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::users::Entity",
+        from = "Column::BlockerId",
+        to = "super::users::Column::Id"
+    )]
+    Blocker,
+    #[sea_orm(
+        belongs_to = "super::users::Entity",
+        from = "Column::BlockedId",
+        to = "super::users::Column::Id"
+    )]
+    Blocked,
+}
+
+impl Related<super::users::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Blocked.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
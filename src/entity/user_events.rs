@@ -0,0 +1,33 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// One entry in a user's event log: a frame they were sent, kept so a
+/// reconnecting client can replay what it missed.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "user_events")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub user_id: i32,
+    /// Per-user, gap-free; this is the client's sync cursor
+    pub seq: i64,
+    /// The frame's `type`, or `message` for plain text messages
+    pub kind: String,
+    pub frame: String,
+    /// The message the frame carries, if any; see `sync::redact_messages`
+    pub message_id: Option<i32>,
+    #[sea_orm(created_at)]
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::users::Entity",
+        from = "Column::UserId",
+        to = "super::users::Column::Id"
+    )]
+    User,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -13,6 +13,41 @@ pub struct Model {
     #[sea_orm(created_at)]
     pub created_at: Option<DateTimeUtc>,
     pub updated_at: Option<DateTimeUtc>,
+    /// `user`, `moderator` or `admin`
+    pub role: String,
+    /// Set by moderators; the account cannot sign in or connect until then
+    pub suspended_until: Option<DateTimeUtc>,
+    /// Set by admins; permanent until explicitly lifted
+    pub banned_at: Option<DateTimeUtc>,
+    /// Set when the owner deletes the account; the row is kept, anonymized,
+    /// so conversations and moderation records still resolve
+    pub deleted_at: Option<DateTimeUtc>,
+    /// Last sequence number in the user's event log, see `sync`
+    pub event_seq: i64,
+    /// Automated account; authenticates with API keys instead of Firebase
+    pub is_bot: bool,
+    /// The human who created the bot, `None` for people
+    pub bot_owner_id: Option<i32>,
+}
+
+impl Model {
+    pub fn role(&self) -> crate::auth::roles::Role {
+        // Unknown values grant nothing
+        self.role.parse().unwrap_or(crate::auth::roles::Role::User)
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended_until
+            .is_some_and(|until| until > chrono::Utc::now())
+    }
+
+    pub fn is_banned(&self) -> bool {
+        self.banned_at.is_some()
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -0,0 +1,47 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// One event queued for one webhook, and the outcome of its latest attempt.
+/// Rows double as the delivery log.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "webhook_deliveries")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub webhook_id: i32,
+    /// Same for every attempt and redelivery, so receivers can deduplicate
+    pub event_id: String,
+    pub event_type: String,
+    /// JSON body, stored as sent so retries are signed over the same bytes
+    pub payload: String,
+    /// `pending`, `sending`, `delivered` or `failed`
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTimeUtc,
+    pub claimed_at: Option<DateTimeUtc>,
+    pub last_attempt_at: Option<DateTimeUtc>,
+    /// HTTP status of the latest attempt, if the receiver answered
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    #[sea_orm(created_at)]
+    pub created_at: DateTimeUtc,
+    pub delivered_at: Option<DateTimeUtc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::webhooks::Entity",
+        from = "Column::WebhookId",
+        to = "super::webhooks::Column::Id"
+    )]
+    Webhook,
+}
+
+impl Related<super::webhooks::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Webhook.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,123 @@
+use crate::auth::firebase_keys;
+use crate::shutdown::Shutdown;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use sea_orm::DatabaseConnection;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Longest a single dependency check may take before it counts as down.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone)]
+pub struct HealthState {
+    pub db: DatabaseConnection,
+    /// `None` when `REDIS_URL` is not set
+    pub redis: Option<redis::Client>,
+    pub shutdown: Shutdown,
+}
+
+#[derive(Serialize)]
+struct CheckResult {
+    ok: bool,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    ready: bool,
+    shutting_down: bool,
+    checks: BTreeMap<&'static str, CheckResult>,
+}
+
+/// `GET /livez` — the process is up and serving requests. Deliberately
+/// checks nothing else, so a database outage doesn't get the pod restarted.
+pub async fn livez() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// `GET /readyz` — 200 when every dependency answered within
+/// `CHECK_TIMEOUT`, 503 otherwise or while draining for shutdown.
+pub async fn readyz(State(state): State<HealthState>) -> Response {
+    let redis = async {
+        match &state.redis {
+            Some(client) => Some(check(ping_redis(client)).await),
+            None => None,
+        }
+    };
+    let (database, auth_keys, redis) = tokio::join!(
+        check(async { state.db.ping().await.map_err(|e| e.to_string()) }),
+        check(async { firebase_keys().await.map(|_| ()) }),
+        redis,
+    );
+
+    let mut checks = BTreeMap::from([("database", database), ("auth_keys", auth_keys)]);
+    if let Some(redis) = redis {
+        checks.insert("redis", redis);
+    }
+    for (name, result) in &checks {
+        if let Some(e) = &result.error {
+            warn!("⚠️ Readiness check {} failed: {}", name, e);
+        }
+    }
+
+    let shutting_down = state.shutdown.is_stopping();
+    let ready = !shutting_down && checks.values().all(|c| c.ok);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(Readiness {
+            ready,
+            shutting_down,
+            checks,
+        }),
+    )
+        .into_response()
+}
+
+async fn check(probe: impl Future<Output = Result<(), String>>) -> CheckResult {
+    let started = Instant::now();
+    let result = tokio::time::timeout(CHECK_TIMEOUT, probe)
+        .await
+        .unwrap_or_else(|_| Err(format!("timed out after {:?}", CHECK_TIMEOUT)));
+    CheckResult {
+        ok: result.is_ok(),
+        latency_ms: started.elapsed().as_millis(),
+        error: result.err(),
+    }
+}
+
+async fn ping_redis(client: &redis::Client) -> Result<(), String> {
+    let mut connection = client
+        .get_multiplexed_async_connection()
+        .await
+        .map_err(|e| e.to_string())?;
+    redis::cmd("PING")
+        .query_async::<_, String>(&mut connection)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+pub fn health_routes(state: HealthState) -> Router {
+    Router::new()
+        // Kept for existing uptime checks; same as `/livez`
+        .route("/health", get(|| async { "OK" }))
+        .route("/livez", get(livez))
+        .route("/readyz", get(readyz))
+        .with_state(state)
+}
@@ -1,49 +1,263 @@
+pub mod account;
+pub mod admin;
+pub mod attachments;
+pub mod audit;
 pub mod auth;
+pub mod blocks;
+pub mod bots;
+pub mod config;
 pub mod db;
 pub mod entity;
+pub mod error;
 pub mod handlers;
+pub mod health;
+pub mod messages;
+pub mod metrics;
 pub mod models;
+pub mod moderation;
+pub mod retention;
 pub mod routes;
+pub mod scheduled;
+pub mod search;
+pub mod shutdown;
+pub mod sync;
+pub mod telemetry;
+#[cfg(test)]
+mod test_support;
+pub mod webhooks;
 pub mod ws;
 
-use axum::http::Method;
-use axum::{routing::get, Router};
+use attachments::handlers::AttachmentsState;
+use axum::http::{HeaderName, Method};
+use axum::{middleware, routing::get, Extension, Router};
 use dotenvy::dotenv;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use tokio::net::TcpListener;
-use tower_http::cors::{Any, CorsLayer};
-use tracing_subscriber;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::TraceLayer;
+use tracing::{error, info, warn};
 
 pub async fn run() {
     dotenv().ok();
-    tracing_subscriber::fmt::init();
 
-    let db = db::connect_database().await;
+    let config = match config::Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            telemetry::init(&config::TelemetryConfig::default());
+            error!("❌ {}", e);
+            std::process::exit(1);
+        }
+    };
+    let telemetry = telemetry::init(&config.telemetry);
 
-    let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    let firebase_api_key = std::env::var("FIREBASE_API_KEY").expect("FIREBASE_API_KEY must be set");
+    let db = db::connect_database(&config.database).await;
+    let pending = db::ensure_schema(&db, config.database.auto_migrate)
+        .await
+        .expect("Failed to check database schema");
+    if !pending.is_empty() {
+        panic!(
+            "Database schema is behind by {} migration(s); run `cargo run -p migration -- up` or set AUTO_MIGRATE=true",
+            pending.len()
+        );
+    }
+    auth::roles::promote_bootstrap_admins(&db, &config.auth.admin_usernames)
+        .await
+        .expect("Failed to promote bootstrap admins");
+
+    let url_signing_secret = config.auth.url_signing_secret.clone();
+    let storage = attachments::storage_from_config(&config.storage);
+    let online = ws::SharedState::default();
+    let shutdown = shutdown::Shutdown::new();
+    let retention_policy = config.retention;
+    retention::RetentionSweeper::new(
+        db.clone(),
+        storage.clone(),
+        online.clone(),
+        retention_policy,
+    )
+    .spawn(&shutdown);
+    sync::EventLogPruner::new(db.clone(), config.limits.event_log_retention).spawn(&shutdown);
+    webhooks::WebhookDispatcher::new(db.clone(), &config.webhooks).spawn(&shutdown);
+    let processor = attachments::MediaProcessor::new(db.clone(), storage.clone(), online.clone());
+    processor.clone().spawn(&shutdown);
+    let attachments_state = AttachmentsState {
+        db: db.clone(),
+        online: online.clone(),
+        storage: storage.clone(),
+        processor,
+        signing_key: Arc::from(url_signing_secret.as_str()),
+        max_bytes: config.limits.attachment_max_bytes,
+        url_ttl: config.limits.attachment_url_ttl,
+        retention: retention_policy,
+    };
+    let moderation_state = moderation::handlers::ModerationState {
+        db: db.clone(),
+        online: online.clone(),
+        storage: storage.clone(),
+    };
+    let exports = account::ExportWorker::new(
+        db.clone(),
+        storage.clone(),
+        online.clone(),
+        config.limits.export_retention,
+    );
+    exports.clone().spawn(&shutdown);
+    let bots_state = bots::handlers::BotsState {
+        db: db.clone(),
+        online: online.clone(),
+        storage: storage.clone(),
+        deletion_policy: config.deletion_policy,
+    };
+    let account_state = account::handlers::AccountState {
+        db: db.clone(),
+        online: online.clone(),
+        storage,
+        exports,
+        deletion_policy: config.deletion_policy,
+        signing_key: Arc::from(url_signing_secret.as_str()),
+        url_ttl: config.limits.export_url_ttl,
+    };
+    let admin_state = admin::handlers::AdminState {
+        db: db.clone(),
+        online: online.clone(),
+    };
+    let messages_state = messages::handlers::MessagesState {
+        db: db.clone(),
+        online: online.clone(),
+        retention: retention_policy,
+    };
+    let retention_state = retention::handlers::RetentionState {
+        db: db.clone(),
+        online: online.clone(),
+        policy: retention_policy,
+    };
+    let scheduler = config.features.scheduled_messages.then(|| {
+        let scheduler =
+            scheduled::MessageScheduler::new(db.clone(), online.clone(), retention_policy);
+        scheduler.clone().spawn(&shutdown);
+        scheduler
+    });
+    let health_state =
+        health::HealthState {
+            db: db.clone(),
+            redis: config.redis.url.as_ref().map(|url| {
+                redis::Client::open(url.as_str()).expect("REDIS_URL validated by config")
+            }),
+            shutdown: shutdown.clone(),
+        };
+    let ws_state = ws::WsState {
+        db: db.clone(),
+        online,
+        retention: retention_policy,
+        scheduler: scheduler.clone(),
+        firebase: config.auth.firebase.clone(),
+        shutdown: shutdown.clone(),
+        config: config.websocket,
+    };
+    let allow_origin = match &config.server.cors_origins {
+        config::CorsOrigins::Any => AllowOrigin::any(),
+        config::CorsOrigins::List(origins) => AllowOrigin::list(origins.clone()),
+    };
     let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods([Method::GET, Method::POST])
+        .allow_origin(allow_origin)
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
         .allow_headers(Any);
 
-    let app = Router::new()
+    let request_id_header = HeaderName::from_static(telemetry::REQUEST_ID_HEADER);
+
+    let mut app = Router::new()
         .route("/", get(|| async { "Whisper Chat" }))
-        .merge(routes::get_routes(db.clone()))
+        .merge(routes::get_routes(ws_state))
+        .merge(health::health_routes(health_state));
+    if config.features.search {
+        app = app.merge(search::search_routes(db.clone()));
+    }
+    if let Some(scheduler) = scheduler {
+        app = app.merge(scheduled::routes::configure_scheduled_routes(
+            scheduled::handlers::ScheduledState {
+                db: db.clone(),
+                scheduler,
+            },
+        ));
+    }
+    if config.features.metrics {
+        app = app.merge(metrics::metrics_routes());
+    }
+    let app = app
+        .merge(retention::routes::configure_retention_routes(
+            retention_state,
+        ))
+        .merge(messages::routes::configure_message_routes(messages_state))
+        .merge(blocks::block_routes(db.clone()))
+        .merge(moderation::routes::configure_moderation_routes(
+            moderation_state,
+        ))
+        .merge(admin::routes::configure_admin_routes(admin_state))
+        .merge(audit::routes::configure_audit_routes(db.clone()))
+        .merge(account::routes::configure_account_routes(account_state))
+        .merge(bots::routes::configure_bot_routes(bots_state))
+        .merge(webhooks::routes::configure_webhook_routes(
+            webhooks::handlers::WebhooksState { db: db.clone() },
+        ))
+        .merge(attachments::routes::configure_attachment_routes(
+            attachments_state,
+        ))
         .merge(auth::routes::configure_auth_routes(
-            db,
-            &firebase_api_key,
-            jwt_secret,
+            auth::handlers::AuthState {
+                db,
+                firebase: config.auth.firebase.clone(),
+            },
         ))
-        .layer(cors);
+        .route_layer(middleware::from_fn(metrics::track_http))
+        // Token verification in the auth extractors reads this
+        .layer(Extension(config.auth.firebase.clone()))
+        // Read by `ClientIp` to decide whether to believe `X-Forwarded-For`
+        .layer(Extension(config.server.trusted_proxies.clone()))
+        .layer(cors)
+        // Outermost, so every log line of a request carries its id
+        .layer(PropagateRequestIdLayer::new(request_id_header.clone()))
+        .layer(TraceLayer::new_for_http().make_span_with(telemetry::http_span))
+        .layer(SetRequestIdLayer::new(request_id_header, MakeRequestUuid));
 
-    let port = std::env::var("PORT")
-        .ok()
-        .and_then(|p| p.parse().ok())
-        .unwrap_or(3000);
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.server.port));
     // let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
     let listener = TcpListener::bind(addr).await.unwrap();
     println!("🚀 Server running on http://{}", addr);
-    axum::serve(listener, app).await.unwrap();
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown.clone().on_signal(config.server.shutdown_delay));
+
+    // `serve` returns once in-flight HTTP requests are done; sockets and
+    // workers are tracked separately since upgraded connections leave hyper
+    let drained = async {
+        if let Err(e) = server.await {
+            error!("❌ Server error: {}", e);
+        }
+        // No-op after a signal; stops the workers if the server failed instead
+        shutdown.trigger();
+        shutdown.drain().await;
+    };
+    let deadline = async {
+        shutdown.cancelled().await;
+        tokio::time::sleep(config.server.shutdown_timeout).await;
+    };
+    tokio::select! {
+        _ = drained => info!("👋 Shutdown complete"),
+        _ = deadline => warn!(
+            "⏱️ Shutdown deadline of {:?} reached with {} task(s) still running",
+            config.server.shutdown_timeout,
+            shutdown.pending()
+        ),
+    }
+    telemetry.shutdown();
 }
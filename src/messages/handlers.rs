@@ -0,0 +1,200 @@
+use crate::auth::MessageSender;
+use crate::entity::users;
+use crate::error::AppError;
+use crate::messages::types::{DirectMessageRequest, MessageView, SendMessageRequest};
+use crate::retention::RetentionPolicy;
+use crate::ws::{route_text_message, SendOutcome, SharedState};
+use axum::{
+    extract::{FromRef, Path, State},
+    http::StatusCode,
+    Json,
+};
+use sea_orm::DatabaseConnection;
+
+#[derive(Clone)]
+pub struct MessagesState {
+    pub db: DatabaseConnection,
+    pub online: SharedState,
+    pub retention: RetentionPolicy,
+}
+
+impl FromRef<MessagesState> for DatabaseConnection {
+    fn from_ref(state: &MessagesState) -> Self {
+        state.db.clone()
+    }
+}
+
+/// `POST /conversations/:username/messages`
+pub async fn send_to_conversation(
+    State(state): State<MessagesState>,
+    MessageSender(user): MessageSender,
+    Path(username): Path<String>,
+    Json(payload): Json<SendMessageRequest>,
+) -> Result<(StatusCode, Json<MessageView>), AppError> {
+    send(&state, user, &username, &payload.text).await
+}
+
+/// `POST /direct-messages`
+pub async fn send_direct(
+    State(state): State<MessagesState>,
+    MessageSender(user): MessageSender,
+    Json(payload): Json<DirectMessageRequest>,
+) -> Result<(StatusCode, Json<MessageView>), AppError> {
+    send(&state, user, payload.to.trim(), &payload.text).await
+}
+
+/// Store and deliver a message exactly as a `recipient: text` socket frame
+/// would, answering with the stored row.
+async fn send(
+    state: &MessagesState,
+    sender: users::Model,
+    to: &str,
+    text: &str,
+) -> Result<(StatusCode, Json<MessageView>), AppError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(AppError::bad_request("Message text must not be empty"));
+    }
+    let outcome = route_text_message(
+        &state.db,
+        &state.online,
+        state.retention,
+        &sender,
+        to,
+        text.to_string(),
+    )
+    .await?;
+    match outcome {
+        SendOutcome::Sent(message) => Ok((
+            StatusCode::CREATED,
+            Json(MessageView::sent(message, &sender, to.to_string())),
+        )),
+        SendOutcome::RecipientBlocked => Err(AppError::Forbidden(format!(
+            "You have blocked user '{}'",
+            to
+        ))),
+        SendOutcome::UnknownRecipient => Err(AppError::not_found(format!("User '{}'", to))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{user_blocks, Messages};
+    use crate::messages::UNREAD_STATUS;
+    use crate::test_support::{create_user, test_db};
+    use chrono::Utc;
+    use sea_orm::{ActiveModelTrait, EntityTrait, Set};
+
+    fn state(db: &DatabaseConnection) -> MessagesState {
+        MessagesState {
+            db: db.clone(),
+            online: SharedState::default(),
+            retention: RetentionPolicy::from_days(None),
+        }
+    }
+
+    async fn send_to(
+        db: &DatabaseConnection,
+        sender: &users::Model,
+        to: &str,
+        text: &str,
+    ) -> Result<MessageView, AppError> {
+        let (status, Json(view)) = send_direct(
+            State(state(db)),
+            MessageSender(sender.clone()),
+            Json(DirectMessageRequest {
+                to: to.to_string(),
+                text: text.to_string(),
+            }),
+        )
+        .await?;
+        assert_eq!(status, StatusCode::CREATED);
+        Ok(view)
+    }
+
+    #[tokio::test]
+    async fn a_sent_message_is_stored_and_returned() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let alice = create_user(&db, "alice").await;
+        let bob = create_user(&db, "bob").await;
+
+        let view = send_to(&db, &alice, &format!(" {} ", bob.username), "  hi bob ")
+            .await
+            .unwrap();
+        assert_eq!(view.from, alice.username);
+        assert!(!view.from_bot);
+        assert_eq!(view.to, bob.username);
+        assert_eq!(view.text, "hi bob");
+        assert_eq!(view.status, UNREAD_STATUS);
+
+        let stored = Messages::find_by_id(view.id)
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.sender_id, alice.id);
+        assert_eq!(stored.receiver_id, bob.id);
+        assert_eq!(stored.message, "hi bob");
+
+        // The conversation route sends the same way
+        let (status, Json(view)) = send_to_conversation(
+            State(state(&db)),
+            MessageSender(alice.clone()),
+            Path(bob.username.clone()),
+            Json(SendMessageRequest {
+                text: "again".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(view.to, bob.username);
+        assert_eq!(view.text, "again");
+    }
+
+    #[tokio::test]
+    async fn unknown_recipients_and_empty_text_are_refused() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let alice = create_user(&db, "alice").await;
+        let bob = create_user(&db, "bob").await;
+
+        let missing = format!("{}-gone", bob.username);
+        let err = send_to(&db, &alice, &missing, "hello").await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
+        let err = send_to(&db, &alice, &bob.username, "   ")
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn a_blocked_recipient_is_refused() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let alice = create_user(&db, "alice").await;
+        let bob = create_user(&db, "bob").await;
+        user_blocks::ActiveModel {
+            blocker_id: Set(alice.id),
+            blocked_id: Set(bob.id),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        let err = send_to(&db, &alice, &bob.username, "hello")
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::FORBIDDEN);
+        // Bob can't tell he's blocked; his message looks sent
+        let view = send_to(&db, &bob, &alice.username, "hello?").await.unwrap();
+        assert_eq!(view.status, UNREAD_STATUS);
+    }
+}
@@ -0,0 +1,14 @@
+//! Sending messages over plain HTTP, for bots, backends and scripts that
+//! don't want to hold a socket open. Messages go through the same routing
+//! as the WebSocket, so recipients can't tell the difference.
+
+pub mod handlers;
+pub mod routes;
+pub mod types;
+
+/// Status of a stored message that hasn't reached the recipient yet.
+pub const UNREAD_STATUS: &str = "unread";
+/// Pushed to a socket the recipient had open.
+pub const DELIVERED_STATUS: &str = "delivered";
+/// Shown to the recipient in their history.
+pub const READ_STATUS: &str = "read";
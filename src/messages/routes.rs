@@ -0,0 +1,13 @@
+use axum::{routing::post, Router};
+
+use crate::messages::handlers::{send_direct, send_to_conversation, MessagesState};
+
+pub fn configure_message_routes(state: MessagesState) -> Router {
+    Router::new()
+        .route(
+            "/conversations/:username/messages",
+            post(send_to_conversation),
+        )
+        .route("/direct-messages", post(send_direct))
+        .with_state(state)
+}
@@ -0,0 +1,81 @@
+use crate::blocks::BLOCKED_STATUS;
+use crate::entity::{messages, users};
+use crate::messages::UNREAD_STATUS;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Body of `POST /conversations/:username/messages`.
+#[derive(Deserialize)]
+pub struct SendMessageRequest {
+    pub text: String,
+}
+
+/// Body of `POST /direct-messages`, which names the recipient in the body
+/// like `POST /scheduled-messages` does.
+#[derive(Deserialize)]
+pub struct DirectMessageRequest {
+    pub to: String,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageView {
+    pub id: i32,
+    pub from: String,
+    /// Sent by a bot account, so clients can render it differently
+    pub from_bot: bool,
+    pub to: String,
+    pub text: String,
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl MessageView {
+    /// The sender's view of a message they just sent. A message swallowed
+    /// by the recipient's block looks like any other.
+    pub fn sent(message: messages::Model, from: &users::Model, to: String) -> Self {
+        let status = if message.status == BLOCKED_STATUS {
+            UNREAD_STATUS.to_string()
+        } else {
+            message.status
+        };
+        Self {
+            id: message.id,
+            from: from.username.clone(),
+            from_bot: from.is_bot,
+            to,
+            text: message.message,
+            status,
+            created_at: message.created_at,
+            expires_at: message.expires_at,
+        }
+    }
+}
+
+/// Sent to the sender when messages reach the recipient's socket
+/// (`delivered`) or screen (`read`).
+#[derive(Serialize)]
+pub struct ReceiptFrame<'a> {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub status: &'a str,
+    /// The recipient
+    pub by: &'a str,
+    pub ids: Vec<i32>,
+}
+
+impl<'a> ReceiptFrame<'a> {
+    pub fn new(status: &'a str, by: &'a str, ids: Vec<i32>) -> Self {
+        Self {
+            kind: "receipt",
+            status,
+            by,
+            ids,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).expect("receipt frame serializes")
+    }
+}
@@ -0,0 +1,288 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{header::CONTENT_TYPE, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// Process-wide Prometheus metrics, served on `/metrics`.
+pub struct Metrics {
+    registry: Registry,
+    /// Sockets currently open on this instance
+    pub ws_connections: IntGauge,
+    pub ws_connections_total: IntCounter,
+    /// Closed sockets by `reason`: client, heartbeat_timeout, idle_timeout,
+    /// queue_overflow, kicked or shutdown
+    pub ws_disconnects: IntCounterVec,
+    /// By outcome: `sent`, `recipient_blocked` or `unknown_recipient`
+    pub messages_routed: IntCounterVec,
+    pub messages_persisted: IntCounter,
+    pub messages_failed: IntCounter,
+    pub message_route_seconds: Histogram,
+    /// Frames waiting in per-connection outbound queues, summed over sockets
+    pub ws_outbound_queued: IntGauge,
+    /// A connection's queue length after each enqueue
+    pub ws_outbound_queue_depth: Histogram,
+    /// Ephemeral frames dropped to keep a slow socket's queue bounded
+    pub ws_outbound_dropped: IntCounter,
+    /// Sockets closed because a reliable frame didn't fit their queue
+    pub ws_outbound_overflows: IntCounter,
+    /// By `operation` (select, insert, ...) and `table`
+    pub db_query_seconds: HistogramVec,
+    pub db_query_errors: IntCounterVec,
+    /// Firebase token checks by `transport` (http, ws, sse) and `outcome`
+    pub auth_verifications: IntCounterVec,
+    /// OTP round-trips by `step` (send, verify) and `outcome`
+    pub auth_otp: IntCounterVec,
+    /// Webhook delivery attempts by `outcome`: delivered, retrying or failed
+    pub webhook_deliveries: IntCounterVec,
+    pub http_requests: IntCounterVec,
+    pub http_request_seconds: HistogramVec,
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+fn counter(registry: &Registry, name: &str, help: &str) -> IntCounter {
+    let metric = IntCounter::new(name, help).expect("valid metric");
+    registry
+        .register(Box::new(metric.clone()))
+        .expect("metric registered once");
+    metric
+}
+
+fn counter_vec(registry: &Registry, name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    let metric = IntCounterVec::new(Opts::new(name, help), labels).expect("valid metric");
+    registry
+        .register(Box::new(metric.clone()))
+        .expect("metric registered once");
+    metric
+}
+
+fn histogram_vec(registry: &Registry, name: &str, help: &str, labels: &[&str]) -> HistogramVec {
+    let metric = HistogramVec::new(HistogramOpts::new(name, help), labels).expect("valid metric");
+    registry
+        .register(Box::new(metric.clone()))
+        .expect("metric registered once");
+    metric
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("whisper".to_string()), None).expect("valid registry prefix");
+
+        let ws_connections =
+            IntGauge::new("ws_connections", "Open WebSocket connections").expect("valid metric");
+        registry
+            .register(Box::new(ws_connections.clone()))
+            .expect("metric registered once");
+        let message_route_seconds = Histogram::with_opts(HistogramOpts::new(
+            "message_route_seconds",
+            "Time to route and store one text message",
+        ))
+        .expect("valid metric");
+        registry
+            .register(Box::new(message_route_seconds.clone()))
+            .expect("metric registered once");
+        let ws_outbound_queued = IntGauge::new(
+            "ws_outbound_queued",
+            "Frames waiting in WebSocket outbound queues",
+        )
+        .expect("valid metric");
+        registry
+            .register(Box::new(ws_outbound_queued.clone()))
+            .expect("metric registered once");
+        let ws_outbound_queue_depth = Histogram::with_opts(
+            HistogramOpts::new(
+                "ws_outbound_queue_depth",
+                "Outbound queue length of a socket after each enqueue",
+            )
+            .buckets(prometheus::exponential_buckets(1.0, 2.0, 11).expect("valid buckets")),
+        )
+        .expect("valid metric");
+        registry
+            .register(Box::new(ws_outbound_queue_depth.clone()))
+            .expect("metric registered once");
+
+        Self {
+            ws_connections,
+            ws_connections_total: counter(
+                &registry,
+                "ws_connections_total",
+                "WebSocket connections accepted",
+            ),
+            ws_disconnects: counter_vec(
+                &registry,
+                "ws_disconnects_total",
+                "WebSocket connections closed, by reason",
+                &["reason"],
+            ),
+            messages_routed: counter_vec(
+                &registry,
+                "messages_routed_total",
+                "Text messages routed, by outcome",
+                &["outcome"],
+            ),
+            messages_persisted: counter(
+                &registry,
+                "messages_persisted_total",
+                "Messages stored in the database",
+            ),
+            messages_failed: counter(
+                &registry,
+                "messages_failed_total",
+                "Messages that could not be routed or stored",
+            ),
+            message_route_seconds,
+            ws_outbound_queued,
+            ws_outbound_queue_depth,
+            ws_outbound_dropped: counter(
+                &registry,
+                "ws_outbound_dropped_total",
+                "Ephemeral frames dropped because a socket fell behind",
+            ),
+            ws_outbound_overflows: counter(
+                &registry,
+                "ws_outbound_overflows_total",
+                "Sockets closed for resync because their outbound queue overflowed",
+            ),
+            db_query_seconds: histogram_vec(
+                &registry,
+                "db_query_seconds",
+                "Database query latency",
+                &["operation", "table"],
+            ),
+            db_query_errors: counter_vec(
+                &registry,
+                "db_query_errors_total",
+                "Failed database queries",
+                &["operation", "table"],
+            ),
+            auth_verifications: counter_vec(
+                &registry,
+                "auth_verifications_total",
+                "Firebase ID token verifications",
+                &["transport", "outcome"],
+            ),
+            auth_otp: counter_vec(
+                &registry,
+                "auth_otp_total",
+                "OTP requests to Firebase",
+                &["step", "outcome"],
+            ),
+            webhook_deliveries: counter_vec(
+                &registry,
+                "webhook_deliveries_total",
+                "Webhook delivery attempts, by outcome",
+                &["outcome"],
+            ),
+            http_requests: counter_vec(
+                &registry,
+                "http_requests_total",
+                "HTTP requests, by matched route",
+                &["method", "route", "status"],
+            ),
+            http_request_seconds: histogram_vec(
+                &registry,
+                "http_request_duration_seconds",
+                "HTTP request latency, by matched route",
+                &["method", "route"],
+            ),
+            registry,
+        }
+    }
+
+    fn render(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+pub fn outcome(ok: bool) -> &'static str {
+    if ok {
+        "ok"
+    } else {
+        "error"
+    }
+}
+
+/// sea-orm metric callback. Labels by statement kind and first table so the
+/// series stay bounded no matter what the SQL looks like.
+pub fn observe_db_query(info: &sea_orm::metric::Info<'_>) {
+    let sql = info.statement.sql.as_str();
+    let operation = sql
+        .split_whitespace()
+        .next()
+        .map(|word| word.to_ascii_lowercase())
+        .filter(|word| matches!(word.as_str(), "select" | "insert" | "update" | "delete"))
+        .unwrap_or_else(|| "other".to_string());
+    let table = query_table(sql).unwrap_or("raw");
+
+    let m = metrics();
+    m.db_query_seconds
+        .with_label_values(&[&operation, table])
+        .observe(info.elapsed.as_secs_f64());
+    if info.failed {
+        m.db_query_errors
+            .with_label_values(&[&operation, table])
+            .inc();
+    }
+}
+
+/// First quoted table after `FROM`, `INTO` or `UPDATE`, as sea-query emits it.
+fn query_table(sql: &str) -> Option<&str> {
+    ["FROM \"", "INTO \"", "UPDATE \""]
+        .iter()
+        .filter_map(|keyword| sql.find(keyword).map(|i| i + keyword.len()))
+        .min()
+        .and_then(|start| {
+            let rest = &sql[start..];
+            rest.find('"').map(|end| &rest[..end])
+        })
+}
+
+/// Route layer recording count and latency per matched route.
+pub async fn track_http(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().to_string();
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    let m = metrics();
+    m.http_request_seconds
+        .with_label_values(&[&method, &route])
+        .observe(started.elapsed().as_secs_f64());
+    m.http_requests
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+    response
+}
+
+/// `GET /metrics` — Prometheus text exposition.
+pub async fn metrics_handler() -> Response {
+    match metrics().render() {
+        Ok(body) => ([(CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+pub fn metrics_routes() -> Router {
+    Router::new().route("/metrics", get(metrics_handler))
+}
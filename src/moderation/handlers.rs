@@ -0,0 +1,603 @@
+use crate::attachments::{delete_blobs, StorageBackend};
+use crate::audit::AuditEvent;
+use crate::auth::{check_sanction, CurrentUser, Moderator, Role};
+use crate::entity::{
+    attachments, messages, moderation_actions, reports, users, Attachments, Messages,
+    ModerationActions, Reports, Users,
+};
+use crate::error::AppError;
+use crate::moderation::types::{
+    CreateReportRequest, CreateReportResponse, ListReportsParams, MessageRemovedFrame,
+    ModerationActionView, NoteRequest, ReportView, ResolveReportRequest, SuspendRequest,
+    REPORT_REASONS, STATUS_DISMISSED, STATUS_OPEN, STATUS_RESOLVED,
+};
+use crate::moderation::REMOVED_STATUS;
+use crate::sync::{redact_messages, SyncEvent};
+use crate::webhooks::types::MessagesDeletedData;
+use crate::webhooks::{WebhookEvent, MESSAGE_DELETED};
+use crate::ws::{disconnect_user, SharedState};
+use axum::{
+    extract::{FromRef, Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+#[derive(Clone)]
+pub struct ModerationState {
+    pub db: DatabaseConnection,
+    pub online: SharedState,
+    pub storage: Arc<dyn StorageBackend>,
+}
+
+impl FromRef<ModerationState> for DatabaseConnection {
+    fn from_ref(state: &ModerationState) -> Self {
+        state.db.clone()
+    }
+}
+
+/// Longest suspension a moderator can give, about a century; indefinite
+/// suspensions last this long too.
+const MAX_SUSPENSION_HOURS: i64 = 24 * 365 * 100;
+
+/// Append an entry to the moderation log and the audit log.
+pub(crate) async fn record_action(
+    db: &DatabaseConnection,
+    moderator_id: i32,
+    action: &str,
+    report_id: Option<i32>,
+    target_user_id: Option<i32>,
+    target_message_id: Option<i32>,
+    note: Option<String>,
+) -> Result<(), AppError> {
+    moderation_actions::ActiveModel {
+        moderator_id: Set(moderator_id),
+        action: Set(action.to_string()),
+        report_id: Set(report_id),
+        target_user_id: Set(target_user_id),
+        target_message_id: Set(target_message_id),
+        note: Set(note.clone()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    let mut event = AuditEvent::new(format!("moderation.{}", action))
+        .actor(moderator_id)
+        .detail("report_id", report_id)
+        .detail("message_id", target_message_id)
+        .detail("note", note);
+    if let Some(user_id) = target_user_id {
+        event = event.target(user_id);
+    }
+    event.record(db).await?;
+    Ok(())
+}
+
+/// `POST /reports` — report a message you were part of, or a user.
+pub async fn create_report(
+    State(state): State<ModerationState>,
+    CurrentUser(reporter): CurrentUser,
+    Json(payload): Json<CreateReportRequest>,
+) -> Result<(StatusCode, Json<CreateReportResponse>), AppError> {
+    if !REPORT_REASONS.contains(&payload.reason.as_str()) {
+        return Err(AppError::unprocessable(format!(
+            "reason must be one of: {}",
+            REPORT_REASONS.join(", ")
+        )));
+    }
+
+    let (reported_user_id, message_id, snapshot) = match (payload.message_id, &payload.username) {
+        (Some(message_id), _) => {
+            let message = Messages::find_by_id(message_id)
+                .one(&state.db)
+                .await?
+                // Only the receiving side can report a message
+                .filter(|m| m.receiver_id == reporter.id && m.sender_id != reporter.id)
+                .ok_or_else(|| AppError::not_found("Message"))?;
+            (message.sender_id, Some(message.id), Some(message.message))
+        }
+        (None, Some(username)) => {
+            let user = Users::find()
+                .filter(users::Column::Username.eq(username.trim()))
+                .one(&state.db)
+                .await?
+                .ok_or_else(|| AppError::not_found("User"))?;
+            (user.id, None, None)
+        }
+        (None, None) => {
+            return Err(AppError::bad_request(
+                "Either message_id or username is required",
+            ))
+        }
+    };
+
+    if reported_user_id == reporter.id {
+        return Err(AppError::bad_request("You cannot report yourself"));
+    }
+
+    let report = reports::ActiveModel {
+        reporter_id: Set(reporter.id),
+        reported_user_id: Set(reported_user_id),
+        message_id: Set(message_id),
+        message_snapshot: Set(snapshot),
+        reason: Set(payload.reason),
+        details: Set(payload.details),
+        status: Set(STATUS_OPEN.to_string()),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await?;
+
+    info!("🚩 Report {} filed by {}", report.id, reporter.username);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateReportResponse {
+            id: report.id,
+            status: report.status,
+        }),
+    ))
+}
+
+async fn usernames(
+    db: &DatabaseConnection,
+    ids: Vec<i32>,
+) -> Result<HashMap<i32, String>, AppError> {
+    Ok(Users::find()
+        .filter(users::Column::Id.is_in(ids))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|u| (u.id, u.username))
+        .collect())
+}
+
+/// `GET /admin/reports?status=open` — moderation queue, oldest first.
+pub async fn list_reports(
+    State(state): State<ModerationState>,
+    Moderator(_): Moderator,
+    Query(params): Query<ListReportsParams>,
+) -> Result<Json<Vec<ReportView>>, AppError> {
+    let status = params.status.unwrap_or_else(|| STATUS_OPEN.to_string());
+    let found = Reports::find()
+        .filter(reports::Column::Status.eq(status))
+        .order_by_asc(reports::Column::CreatedAt)
+        .limit(params.limit.unwrap_or(50).min(200))
+        .offset(params.offset.unwrap_or(0))
+        .all(&state.db)
+        .await?;
+
+    let ids = found
+        .iter()
+        .flat_map(|r| [r.reporter_id, r.reported_user_id])
+        .collect();
+    let names = usernames(&state.db, ids).await?;
+
+    Ok(Json(
+        found
+            .into_iter()
+            .map(|r| {
+                let reporter = names.get(&r.reporter_id).cloned();
+                let reported = names.get(&r.reported_user_id).cloned();
+                ReportView::new(r, reporter, reported)
+            })
+            .collect(),
+    ))
+}
+
+/// `POST /admin/reports/:id/resolve` — close a report, optionally removing
+/// the reported message and suspending its author.
+pub async fn resolve_report(
+    State(state): State<ModerationState>,
+    Moderator(moderator): Moderator,
+    Path(id): Path<i32>,
+    Json(payload): Json<ResolveReportRequest>,
+) -> Result<Json<ReportView>, AppError> {
+    if payload.outcome != STATUS_RESOLVED && payload.outcome != STATUS_DISMISSED {
+        return Err(AppError::unprocessable(format!(
+            "outcome must be '{}' or '{}'",
+            STATUS_RESOLVED, STATUS_DISMISSED
+        )));
+    }
+
+    let report = Reports::find_by_id(id)
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AppError::not_found("Report"))?;
+    if report.status != STATUS_OPEN {
+        return Err(AppError::Conflict("Report is already closed".to_string()));
+    }
+
+    let remove_message_id = if payload.remove_message {
+        Some(
+            report
+                .message_id
+                .ok_or_else(|| AppError::bad_request("Report is not about a message"))?,
+        )
+    } else {
+        None
+    };
+    // Suspending can be refused, so it goes before anything is removed
+    if let Some(hours) = payload.suspend_hours {
+        suspend(
+            &state,
+            &moderator,
+            report.reported_user_id,
+            Some(hours),
+            Some(report.id),
+            None,
+        )
+        .await?;
+    }
+    if let Some(message_id) = remove_message_id {
+        remove_message_content(&state, &moderator, message_id, Some(report.id), None).await?;
+    }
+
+    let mut update: reports::ActiveModel = report.into();
+    update.status = Set(payload.outcome.clone());
+    update.resolution_note = Set(payload.note.clone());
+    update.resolved_by = Set(Some(moderator.id));
+    update.resolved_at = Set(Some(Utc::now()));
+    let report = update.update(&state.db).await?;
+
+    record_action(
+        &state.db,
+        moderator.id,
+        &format!("report_{}", payload.outcome),
+        Some(report.id),
+        Some(report.reported_user_id),
+        report.message_id,
+        payload.note,
+    )
+    .await?;
+
+    let names = usernames(&state.db, vec![report.reporter_id, report.reported_user_id]).await?;
+    let reporter = names.get(&report.reporter_id).cloned();
+    let reported = names.get(&report.reported_user_id).cloned();
+    Ok(Json(ReportView::new(report, reporter, reported)))
+}
+
+async fn suspend(
+    state: &ModerationState,
+    moderator: &users::Model,
+    user_id: i32,
+    hours: Option<i64>,
+    report_id: Option<i32>,
+    note: Option<String>,
+) -> Result<DateTime<Utc>, AppError> {
+    let db = &state.db;
+    let user = Users::find_by_id(user_id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::not_found("User"))?;
+    check_sanction(moderator, &user, "suspend")?;
+
+    let until = match hours {
+        Some(h) if (1..=MAX_SUSPENSION_HOURS).contains(&h) => Utc::now() + Duration::hours(h),
+        Some(_) => {
+            return Err(AppError::bad_request(format!(
+                "hours must be between 1 and {}",
+                MAX_SUSPENSION_HOURS
+            )))
+        }
+        // Open-ended removal is a ban in all but name, so it takes an admin
+        None if moderator.role() < Role::Admin => {
+            return Err(AppError::forbidden(
+                "Only admins can suspend indefinitely; give hours",
+            ))
+        }
+        // Far enough in the future to be permanent in practice
+        None => Utc::now() + Duration::hours(MAX_SUSPENSION_HOURS),
+    };
+
+    let mut update: users::ActiveModel = user.into();
+    update.suspended_until = Set(Some(until));
+    update.updated_at = Set(Some(Utc::now()));
+    let user = update.update(db).await?;
+
+    warn!("⛔ {} suspended until {}", user.username, until);
+    disconnect_user(
+        &state.online,
+        &user.username,
+        "Your account has been suspended",
+    )
+    .await;
+    record_action(
+        db,
+        moderator.id,
+        "suspend_user",
+        report_id,
+        Some(user.id),
+        None,
+        note,
+    )
+    .await?;
+    Ok(until)
+}
+
+/// `POST /admin/users/:username/suspend`
+pub async fn suspend_user(
+    State(state): State<ModerationState>,
+    Moderator(moderator): Moderator,
+    Path(username): Path<String>,
+    Json(payload): Json<SuspendRequest>,
+) -> Result<StatusCode, AppError> {
+    let user = Users::find()
+        .filter(users::Column::Username.eq(&username))
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AppError::not_found("User"))?;
+
+    suspend(
+        &state,
+        &moderator,
+        user.id,
+        payload.hours,
+        None,
+        payload.note,
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `POST /admin/users/:username/unsuspend`
+pub async fn unsuspend_user(
+    State(state): State<ModerationState>,
+    Moderator(moderator): Moderator,
+    Path(username): Path<String>,
+    payload: Option<Json<NoteRequest>>,
+) -> Result<StatusCode, AppError> {
+    let user = Users::find()
+        .filter(users::Column::Username.eq(&username))
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AppError::not_found("User"))?;
+
+    let mut update: users::ActiveModel = user.into();
+    update.suspended_until = Set(None);
+    update.updated_at = Set(Some(Utc::now()));
+    let user = update.update(&state.db).await?;
+
+    let Json(payload) = payload.unwrap_or_default();
+    record_action(
+        &state.db,
+        moderator.id,
+        "unsuspend_user",
+        None,
+        Some(user.id),
+        None,
+        payload.note,
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Blank a message, delete its attachments and tell online participants.
+async fn remove_message_content(
+    state: &ModerationState,
+    moderator: &users::Model,
+    message_id: i32,
+    report_id: Option<i32>,
+    note: Option<String>,
+) -> Result<(), AppError> {
+    let message = Messages::find_by_id(message_id)
+        .one(&state.db)
+        .await?
+        .ok_or_else(|| AppError::not_found("Message"))?;
+
+    for attachment in Attachments::find()
+        .filter(attachments::Column::MessageId.eq(message.id))
+        .all(&state.db)
+        .await?
+    {
+        delete_blobs(state.storage.as_ref(), &attachment).await;
+        Attachments::delete_by_id(attachment.id)
+            .exec(&state.db)
+            .await?;
+    }
+
+    let participants = vec![message.sender_id, message.receiver_id];
+    let mut update: messages::ActiveModel = message.into();
+    update.message = Set(String::new());
+    update.status = Set(REMOVED_STATUS.to_string());
+    update.update(&state.db).await?;
+    redact_messages(&state.db, &[message_id]).await?;
+    WebhookEvent::new(
+        MESSAGE_DELETED,
+        MessagesDeletedData {
+            ids: vec![message_id],
+            reason: "removed",
+        },
+    )
+    .emit(&state.db)
+    .await;
+
+    let frame = MessageRemovedFrame::new(message_id).to_text();
+    for (user_id, username) in usernames(&state.db, participants).await? {
+        SyncEvent::new(frame.clone())
+            .publish(&state.db, &state.online, user_id, &username)
+            .await;
+    }
+
+    record_action(
+        &state.db,
+        moderator.id,
+        "remove_message",
+        report_id,
+        None,
+        Some(message_id),
+        note,
+    )
+    .await
+}
+
+/// `DELETE /admin/messages/:id`
+pub async fn remove_message(
+    State(state): State<ModerationState>,
+    Moderator(moderator): Moderator,
+    Path(id): Path<i32>,
+    payload: Option<Json<NoteRequest>>,
+) -> Result<StatusCode, AppError> {
+    let Json(payload) = payload.unwrap_or_default();
+    remove_message_content(&state, &moderator, id, None, payload.note).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /admin/moderation-log` — most recent moderator actions first.
+pub async fn moderation_log(
+    State(state): State<ModerationState>,
+    Moderator(_): Moderator,
+    Query(params): Query<ListReportsParams>,
+) -> Result<Json<Vec<ModerationActionView>>, AppError> {
+    let actions = ModerationActions::find()
+        .order_by_desc(moderation_actions::Column::Id)
+        .limit(params.limit.unwrap_or(50).min(200))
+        .offset(params.offset.unwrap_or(0))
+        .all(&state.db)
+        .await?;
+
+    Ok(Json(actions.into_iter().map(Into::into).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::storage::FsStorage;
+    use crate::test_support::{create_message, create_user, test_db};
+
+    fn state(db: &DatabaseConnection) -> ModerationState {
+        ModerationState {
+            db: db.clone(),
+            online: SharedState::default(),
+            storage: Arc::new(FsStorage::new(std::env::temp_dir())),
+        }
+    }
+
+    async fn report(
+        db: &DatabaseConnection,
+        reporter: &users::Model,
+        message_id: Option<i32>,
+        username: Option<&str>,
+        reason: &str,
+    ) -> Result<reports::Model, AppError> {
+        let (status, Json(created)) = create_report(
+            State(state(db)),
+            CurrentUser(reporter.clone()),
+            Json(CreateReportRequest {
+                message_id,
+                username: username.map(str::to_string),
+                reason: reason.to_string(),
+                details: Some("details".to_string()),
+            }),
+        )
+        .await?;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created.status, STATUS_OPEN);
+        Ok(Reports::find_by_id(created.id)
+            .one(db)
+            .await
+            .unwrap()
+            .unwrap())
+    }
+
+    #[tokio::test]
+    async fn a_message_report_keeps_the_text_after_removal() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let alice = create_user(&db, "alice").await;
+        let bob = create_user(&db, "bob").await;
+        let moderator = create_user(&db, "moderator").await;
+        let message = create_message(&db, &bob, &alice, "something nasty").await;
+
+        let filed = report(&db, &alice, Some(message.id), None, "harassment")
+            .await
+            .unwrap();
+        assert_eq!(filed.reporter_id, alice.id);
+        assert_eq!(filed.reported_user_id, bob.id);
+        assert_eq!(filed.message_id, Some(message.id));
+        assert_eq!(filed.message_snapshot.as_deref(), Some("something nasty"));
+        assert_eq!(filed.reason, "harassment");
+        assert_eq!(filed.details.as_deref(), Some("details"));
+
+        // Taking the message down blanks it, but the report still shows what was said
+        remove_message(
+            State(state(&db)),
+            Moderator(moderator),
+            Path(message.id),
+            None,
+        )
+        .await
+        .unwrap();
+        let removed = Messages::find_by_id(message.id)
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(removed.message, "");
+        assert_eq!(removed.status, REMOVED_STATUS);
+        let kept = Reports::find_by_id(filed.id)
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(kept.message_snapshot.as_deref(), Some("something nasty"));
+    }
+
+    #[tokio::test]
+    async fn a_user_report_has_no_snapshot() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let alice = create_user(&db, "alice").await;
+        let bob = create_user(&db, "bob").await;
+
+        let filed = report(&db, &alice, None, Some(&bob.username), "impersonation")
+            .await
+            .unwrap();
+        assert_eq!(filed.reported_user_id, bob.id);
+        assert_eq!(filed.message_id, None);
+        assert_eq!(filed.message_snapshot, None);
+    }
+
+    #[tokio::test]
+    async fn only_the_recipient_can_report_a_message() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let alice = create_user(&db, "alice").await;
+        let bob = create_user(&db, "bob").await;
+        let carol = create_user(&db, "carol").await;
+        let message = create_message(&db, &bob, &alice, "hi").await;
+
+        let status = |result: Result<reports::Model, AppError>| result.unwrap_err().status();
+        // The sender and bystanders can't see it to report it
+        for reporter in [&bob, &carol] {
+            let result = report(&db, reporter, Some(message.id), None, "spam").await;
+            assert_eq!(status(result), StatusCode::NOT_FOUND);
+        }
+        let result = report(&db, &alice, Some(message.id), None, "boring").await;
+        assert_eq!(status(result), StatusCode::UNPROCESSABLE_ENTITY);
+        let result = report(&db, &alice, None, Some(&alice.username), "spam").await;
+        assert_eq!(status(result), StatusCode::BAD_REQUEST);
+        let result = report(&db, &alice, None, None, "spam").await;
+        assert_eq!(status(result), StatusCode::BAD_REQUEST);
+
+        let filed = Reports::find()
+            .filter(reports::Column::MessageId.eq(message.id))
+            .all(&db)
+            .await
+            .unwrap();
+        assert!(filed.is_empty());
+    }
+}
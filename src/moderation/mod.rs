@@ -0,0 +1,6 @@
+pub mod handlers;
+pub mod routes;
+pub mod types;
+
+/// Message status for content taken down by a moderator.
+pub const REMOVED_STATUS: &str = "removed";
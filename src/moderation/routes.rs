@@ -0,0 +1,21 @@
+use axum::{
+    routing::{delete, get, post},
+    Router,
+};
+
+use crate::moderation::handlers::{
+    create_report, list_reports, moderation_log, remove_message, resolve_report, suspend_user,
+    unsuspend_user, ModerationState,
+};
+
+pub fn configure_moderation_routes(state: ModerationState) -> Router {
+    Router::new()
+        .route("/reports", post(create_report))
+        .route("/admin/reports", get(list_reports))
+        .route("/admin/reports/:id/resolve", post(resolve_report))
+        .route("/admin/users/:username/suspend", post(suspend_user))
+        .route("/admin/users/:username/unsuspend", post(unsuspend_user))
+        .route("/admin/messages/:id", delete(remove_message))
+        .route("/admin/moderation-log", get(moderation_log))
+        .with_state(state)
+}
@@ -0,0 +1,143 @@
+use crate::entity::{moderation_actions, reports};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub const REPORT_REASONS: &[&str] = &[
+    "spam",
+    "harassment",
+    "hate",
+    "sexual_content",
+    "violence",
+    "impersonation",
+    "other",
+];
+
+pub const STATUS_OPEN: &str = "open";
+pub const STATUS_RESOLVED: &str = "resolved";
+pub const STATUS_DISMISSED: &str = "dismissed";
+
+/// Report a message (`message_id`) or an account (`username`).
+#[derive(Deserialize)]
+pub struct CreateReportRequest {
+    pub message_id: Option<i32>,
+    pub username: Option<String>,
+    pub reason: String,
+    pub details: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CreateReportResponse {
+    pub id: i32,
+    pub status: String,
+}
+
+#[derive(Deserialize)]
+pub struct ListReportsParams {
+    pub status: Option<String>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct ReportView {
+    pub id: i32,
+    pub reporter: Option<String>,
+    pub reported_user: Option<String>,
+    pub message_id: Option<i32>,
+    pub message_snapshot: Option<String>,
+    pub reason: String,
+    pub details: Option<String>,
+    pub status: String,
+    pub resolution_note: Option<String>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ReportView {
+    pub fn new(report: reports::Model, reporter: Option<String>, reported: Option<String>) -> Self {
+        Self {
+            id: report.id,
+            reporter,
+            reported_user: reported,
+            message_id: report.message_id,
+            message_snapshot: report.message_snapshot,
+            reason: report.reason,
+            details: report.details,
+            status: report.status,
+            resolution_note: report.resolution_note,
+            resolved_at: report.resolved_at,
+            created_at: report.created_at,
+        }
+    }
+}
+
+/// Close a report, optionally acting on it in the same call.
+#[derive(Deserialize)]
+pub struct ResolveReportRequest {
+    /// `resolved` or `dismissed`
+    pub outcome: String,
+    pub note: Option<String>,
+    #[serde(default)]
+    pub remove_message: bool,
+    pub suspend_hours: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct SuspendRequest {
+    /// Omit for an indefinite suspension
+    pub hours: Option<i64>,
+    pub note: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct NoteRequest {
+    pub note: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ModerationActionView {
+    pub id: i32,
+    pub moderator_id: i32,
+    pub action: String,
+    pub report_id: Option<i32>,
+    pub target_user_id: Option<i32>,
+    pub target_message_id: Option<i32>,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<moderation_actions::Model> for ModerationActionView {
+    fn from(action: moderation_actions::Model) -> Self {
+        Self {
+            id: action.id,
+            moderator_id: action.moderator_id,
+            action: action.action,
+            report_id: action.report_id,
+            target_user_id: action.target_user_id,
+            target_message_id: action.target_message_id,
+            note: action.note,
+            created_at: action.created_at,
+        }
+    }
+}
+
+/// Sent to online participants when a moderator takes a message down.
+#[derive(Serialize)]
+pub struct MessageRemovedFrame {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub id: i32,
+}
+
+impl MessageRemovedFrame {
+    pub fn new(id: i32) -> Self {
+        Self {
+            kind: "message_removed",
+            id,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).expect("removal frame serializes")
+    }
+}
@@ -0,0 +1,126 @@
+use crate::auth::CurrentUser;
+use crate::blocks::is_blocked;
+use crate::entity::{conversation_settings, users, Users};
+use crate::error::AppError;
+use crate::retention::types::{SetTimerRequest, TimerUpdatedFrame, TimerView};
+use crate::retention::{conversation_key, find_settings, RetentionPolicy, ALLOWED_TIMERS};
+use crate::sync::SyncEvent;
+use crate::ws::SharedState;
+use axum::{
+    extract::{FromRef, Path, State},
+    Json,
+};
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
+
+#[derive(Clone)]
+pub struct RetentionState {
+    pub db: DatabaseConnection,
+    pub online: SharedState,
+    pub policy: RetentionPolicy,
+}
+
+impl FromRef<RetentionState> for DatabaseConnection {
+    fn from_ref(state: &RetentionState) -> Self {
+        state.db.clone()
+    }
+}
+
+async fn find_user(db: &DatabaseConnection, username: &str) -> Result<users::Model, AppError> {
+    Users::find()
+        .filter(users::Column::Username.eq(username))
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::not_found(format!("User '{}'", username)))
+}
+
+/// `GET /conversations/:username/disappearing`
+pub async fn get_timer(
+    State(state): State<RetentionState>,
+    CurrentUser(user): CurrentUser,
+    Path(username): Path<String>,
+) -> Result<Json<TimerView>, AppError> {
+    let partner = find_user(&state.db, &username).await?;
+    let settings = find_settings(&state.db, user.id, partner.id).await?;
+
+    Ok(Json(TimerView {
+        with: partner.username,
+        seconds: settings.as_ref().and_then(|s| s.disappearing_seconds),
+        server_max_seconds: state.policy.max_age.map(|d| d.num_seconds()),
+        updated_at: settings.map(|s| s.updated_at),
+    }))
+}
+
+/// `PUT /conversations/:username/disappearing` — either participant may set
+/// or clear the timer. Applies to messages sent afterwards.
+pub async fn set_timer(
+    State(state): State<RetentionState>,
+    CurrentUser(user): CurrentUser,
+    Path(username): Path<String>,
+    Json(payload): Json<SetTimerRequest>,
+) -> Result<Json<TimerView>, AppError> {
+    if let Some(seconds) = payload.seconds {
+        if !ALLOWED_TIMERS.contains(&seconds) {
+            return Err(AppError::unprocessable(format!(
+                "seconds must be null or one of: {}",
+                ALLOWED_TIMERS
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+    }
+    let partner = find_user(&state.db, &username).await?;
+    if partner.id == user.id {
+        return Err(AppError::bad_request("Not a conversation"));
+    }
+
+    let now = Utc::now();
+    let settings = match find_settings(&state.db, user.id, partner.id).await? {
+        Some(existing) => {
+            let mut update: conversation_settings::ActiveModel = existing.into();
+            update.disappearing_seconds = Set(payload.seconds);
+            update.updated_by = Set(Some(user.id));
+            update.updated_at = Set(now);
+            update.update(&state.db).await
+        }
+        None => {
+            let (low, high) = conversation_key(user.id, partner.id);
+            conversation_settings::ActiveModel {
+                user_low_id: Set(low),
+                user_high_id: Set(high),
+                disappearing_seconds: Set(payload.seconds),
+                updated_by: Set(Some(user.id)),
+                updated_at: Set(now),
+                ..Default::default()
+            }
+            .insert(&state.db)
+            .await
+        }
+    }?;
+
+    let mut notify = vec![(&user, &partner)];
+    // Someone who blocked the caller doesn't hear from them, settings included
+    if !is_blocked(&state.db, partner.id, user.id).await? {
+        notify.push((&partner, &user));
+    }
+    for (recipient, with) in notify {
+        let frame = TimerUpdatedFrame::new(
+            with.username.clone(),
+            payload.seconds,
+            user.username.clone(),
+        )
+        .to_text();
+        SyncEvent::new(frame)
+            .publish(&state.db, &state.online, recipient.id, &recipient.username)
+            .await;
+    }
+
+    Ok(Json(TimerView {
+        with: partner.username,
+        seconds: settings.disappearing_seconds,
+        server_max_seconds: state.policy.max_age.map(|d| d.num_seconds()),
+        updated_at: Some(settings.updated_at),
+    }))
+}
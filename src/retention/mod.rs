@@ -0,0 +1,84 @@
+pub mod handlers;
+pub mod routes;
+pub mod sweeper;
+pub mod types;
+
+pub use sweeper::RetentionSweeper;
+
+use crate::entity::{conversation_settings, messages, ConversationSettings};
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::{ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter};
+
+/// Disappearing-message timers participants can choose from: 24 hours,
+/// 7 days and 90 days.
+pub const ALLOWED_TIMERS: &[i64] = &[86_400, 604_800, 7_776_000];
+
+/// Server-wide retention limit from `MESSAGE_RETENTION_DAYS` (see `config`);
+/// unset keeps messages without a timer forever.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetentionPolicy {
+    pub max_age: Option<Duration>,
+}
+
+impl RetentionPolicy {
+    /// `0` disables the limit, like leaving it unset.
+    pub fn from_days(days: Option<i64>) -> Self {
+        Self {
+            max_age: days.filter(|days| *days > 0).map(Duration::days),
+        }
+    }
+
+    /// Expiry for a message sent now between `sender_id` and `receiver_id`:
+    /// the shorter of the conversation timer and the server limit.
+    pub async fn expires_at(
+        &self,
+        db: &DatabaseConnection,
+        sender_id: i32,
+        receiver_id: i32,
+    ) -> Result<Option<DateTime<Utc>>, sea_orm::DbErr> {
+        let timer = conversation_timer(db, sender_id, receiver_id)
+            .await?
+            .map(Duration::seconds);
+        let lifetime = match (timer, self.max_age) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        Ok(lifetime.map(|d| Utc::now() + d))
+    }
+}
+
+/// Conversations are unordered pairs; settings rows store the lower id first.
+pub fn conversation_key(a: i32, b: i32) -> (i32, i32) {
+    (a.min(b), a.max(b))
+}
+
+pub async fn find_settings(
+    db: &DatabaseConnection,
+    a: i32,
+    b: i32,
+) -> Result<Option<conversation_settings::Model>, sea_orm::DbErr> {
+    let (low, high) = conversation_key(a, b);
+    ConversationSettings::find()
+        .filter(conversation_settings::Column::UserLowId.eq(low))
+        .filter(conversation_settings::Column::UserHighId.eq(high))
+        .one(db)
+        .await
+}
+
+pub async fn conversation_timer(
+    db: &DatabaseConnection,
+    a: i32,
+    b: i32,
+) -> Result<Option<i64>, sea_orm::DbErr> {
+    Ok(find_settings(db, a, b)
+        .await?
+        .and_then(|s| s.disappearing_seconds))
+}
+
+/// Messages that have not yet expired. The sweeper runs periodically, so
+/// reads filter out anything past its expiry in the meantime.
+pub fn not_expired() -> Condition {
+    Condition::any()
+        .add(messages::Column::ExpiresAt.is_null())
+        .add(messages::Column::ExpiresAt.gt(Utc::now()))
+}
@@ -0,0 +1,12 @@
+use axum::{routing::get, Router};
+
+use crate::retention::handlers::{get_timer, set_timer, RetentionState};
+
+pub fn configure_retention_routes(state: RetentionState) -> Router {
+    Router::new()
+        .route(
+            "/conversations/:username/disappearing",
+            get(get_timer).put(set_timer),
+        )
+        .with_state(state)
+}
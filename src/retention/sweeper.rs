@@ -0,0 +1,241 @@
+//! Background deletion of expired messages. Each pass removes at most
+//! `BATCH_SIZE` messages with their attachments, so a large backlog (say,
+//! after lowering `MESSAGE_RETENTION_DAYS`) never holds long locks. With
+//! several instances running, whichever holds the sweep lock does the work.
+
+use crate::attachments::{delete_blobs, StorageBackend};
+use crate::blocks::BLOCKED_STATUS;
+use crate::entity::{attachments, messages, users, Attachments, Messages, Users};
+use crate::retention::types::MessagesExpiredFrame;
+use crate::retention::RetentionPolicy;
+use crate::shutdown::Shutdown;
+use crate::sync::{redact_messages, SyncEvent};
+use crate::webhooks::types::MessagesDeletedData;
+use crate::webhooks::{WebhookEvent, MESSAGE_DELETED};
+use crate::ws::SharedState;
+use chrono::Utc;
+use sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DbBackend, EntityTrait,
+    QueryFilter, QueryOrder, QuerySelect, Statement, TransactionTrait,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+const BATCH_SIZE: u64 = 500;
+const SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+/// Advisory lock key held while a batch is swept, so only one instance
+/// deletes (and announces) a given batch.
+const SWEEP_LOCK_KEY: i64 = 0x7265_7465_6e74;
+
+#[derive(Clone)]
+pub struct RetentionSweeper {
+    db: DatabaseConnection,
+    storage: Arc<dyn StorageBackend>,
+    online: SharedState,
+    policy: RetentionPolicy,
+}
+
+impl RetentionSweeper {
+    pub fn new(
+        db: DatabaseConnection,
+        storage: Arc<dyn StorageBackend>,
+        online: SharedState,
+        policy: RetentionPolicy,
+    ) -> Self {
+        Self {
+            db,
+            storage,
+            online,
+            policy,
+        }
+    }
+
+    pub fn spawn(self, shutdown: &Shutdown) -> tokio::task::JoinHandle<()> {
+        let shutdown = shutdown.clone();
+        shutdown.clone().spawn(async move {
+            while !shutdown.is_draining() {
+                match self.sweep_batch().await {
+                    // A full batch suggests more has expired
+                    Ok(n) if n as u64 == BATCH_SIZE => continue,
+                    Ok(_) => {}
+                    Err(e) => warn!("Retention sweeper failed: {}", e),
+                }
+                tokio::select! {
+                    _ = shutdown.cancelled() => {}
+                    _ = tokio::time::sleep(SWEEP_INTERVAL) => {}
+                }
+            }
+        })
+    }
+
+    /// Delete one batch. Returns 0 without waiting when another instance is
+    /// mid-sweep; it will get to whatever this one would have.
+    async fn sweep_batch(&self) -> Result<usize, sea_orm::DbErr> {
+        let txn = self.db.begin().await?;
+        let locked = txn
+            .query_one(Statement::from_string(
+                DbBackend::Postgres,
+                format!(
+                    "SELECT pg_try_advisory_xact_lock({}) AS locked",
+                    SWEEP_LOCK_KEY
+                ),
+            ))
+            .await?
+            .map(|row| row.try_get::<bool>("", "locked"))
+            .transpose()?
+            .unwrap_or(false);
+        if !locked {
+            return Ok(0);
+        }
+
+        let now = Utc::now();
+        let mut due = Condition::any().add(messages::Column::ExpiresAt.lte(now));
+        // Also catches messages stored before the limit was configured
+        if let Some(max_age) = self.policy.max_age {
+            due = due.add(messages::Column::CreatedAt.lt(now - max_age));
+        }
+
+        let expired = Messages::find()
+            .filter(due)
+            .order_by_asc(messages::Column::Id)
+            .limit(BATCH_SIZE)
+            .all(&txn)
+            .await?;
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        let ids: Vec<i32> = expired.iter().map(|m| m.id).collect();
+        let files = Attachments::find()
+            .filter(attachments::Column::MessageId.is_in(ids.clone()))
+            .all(&txn)
+            .await?;
+
+        Attachments::delete_many()
+            .filter(attachments::Column::MessageId.is_in(ids.clone()))
+            .exec(&txn)
+            .await?;
+        Messages::delete_many()
+            .filter(messages::Column::Id.is_in(ids.clone()))
+            .exec(&txn)
+            .await?;
+        redact_messages(&txn, &ids).await?;
+        txn.commit().await?;
+
+        for attachment in &files {
+            delete_blobs(self.storage.as_ref(), attachment).await;
+        }
+        self.announce(&expired).await?;
+        WebhookEvent::new(
+            MESSAGE_DELETED,
+            MessagesDeletedData {
+                ids: expired.iter().map(|m| m.id).collect(),
+                reason: "expired",
+            },
+        )
+        .emit(&self.db)
+        .await;
+
+        info!(
+            "🧹 Deleted {} expired message(s) and {} attachment(s)",
+            expired.len(),
+            files.len()
+        );
+        Ok(expired.len())
+    }
+
+    /// Tell each participant which of their messages are gone; offline ones
+    /// get it from the event log when they resume.
+    async fn announce(&self, expired: &[messages::Model]) -> Result<(), sea_orm::DbErr> {
+        let mut by_user: HashMap<i32, Vec<i32>> = HashMap::new();
+        for message in expired {
+            by_user
+                .entry(message.sender_id)
+                .or_default()
+                .push(message.id);
+            // Hidden messages from a blocked sender never reached the receiver
+            if message.status != BLOCKED_STATUS {
+                by_user
+                    .entry(message.receiver_id)
+                    .or_default()
+                    .push(message.id);
+            }
+        }
+
+        let recipients = Users::find()
+            .filter(users::Column::Id.is_in(by_user.keys().copied()))
+            .all(&self.db)
+            .await?;
+        for user in recipients {
+            if let Some(ids) = by_user.remove(&user.id) {
+                let frame = MessagesExpiredFrame::new(ids).to_text();
+                SyncEvent::new(frame)
+                    .publish(&self.db, &self.online, user.id, &user.username)
+                    .await;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::storage::FsStorage;
+    use crate::messages::UNREAD_STATUS;
+    use crate::test_support::{create_user, test_db};
+    use chrono::Duration;
+    use sea_orm::{ActiveModelTrait, Set};
+
+    #[tokio::test]
+    async fn only_one_instance_sweeps_at_a_time() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let sender = create_user(&db, "sender").await;
+        let receiver = create_user(&db, "receiver").await;
+        let expired = messages::ActiveModel {
+            sender_id: Set(sender.id),
+            receiver_id: Set(receiver.id),
+            message: Set("gone soon".to_string()),
+            created_at: Set(Utc::now()),
+            status: Set(UNREAD_STATUS.to_string()),
+            expires_at: Set(Some(Utc::now() - Duration::minutes(1))),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        let sweeper = RetentionSweeper::new(
+            db.clone(),
+            Arc::new(FsStorage::new(std::env::temp_dir())),
+            SharedState::default(),
+            RetentionPolicy::from_days(None),
+        );
+
+        // Another instance is mid-sweep
+        let other = db.begin().await.unwrap();
+        other
+            .execute(Statement::from_string(
+                DbBackend::Postgres,
+                format!("SELECT pg_advisory_xact_lock({})", SWEEP_LOCK_KEY),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(sweeper.sweep_batch().await.unwrap(), 0);
+        assert!(Messages::find_by_id(expired.id)
+            .one(&db)
+            .await
+            .unwrap()
+            .is_some());
+
+        other.rollback().await.unwrap();
+        while sweeper.sweep_batch().await.unwrap() as u64 == BATCH_SIZE {}
+        assert!(Messages::find_by_id(expired.id)
+            .one(&db)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,65 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct SetTimerRequest {
+    /// One of `ALLOWED_TIMERS`, or `null` to turn disappearing messages off
+    pub seconds: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct TimerView {
+    pub with: String,
+    pub seconds: Option<i64>,
+    /// Server-wide retention limit, which applies even with no timer set
+    pub server_max_seconds: Option<i64>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Sent to both participants when the conversation timer changes. It applies
+/// to messages sent from then on.
+#[derive(Serialize)]
+pub struct TimerUpdatedFrame {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub with: String,
+    pub seconds: Option<i64>,
+    pub updated_by: String,
+}
+
+impl TimerUpdatedFrame {
+    pub fn new(with: String, seconds: Option<i64>, updated_by: String) -> Self {
+        Self {
+            kind: "disappearing_timer_updated",
+            with,
+            seconds,
+            updated_by,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).expect("timer frame serializes")
+    }
+}
+
+/// Sent to online participants when the sweeper deletes their messages, so
+/// clients purge local copies.
+#[derive(Serialize)]
+pub struct MessagesExpiredFrame {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub ids: Vec<i32>,
+}
+
+impl MessagesExpiredFrame {
+    pub fn new(ids: Vec<i32>) -> Self {
+        Self {
+            kind: "messages_expired",
+            ids,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).expect("expiry frame serializes")
+    }
+}
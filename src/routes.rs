@@ -1,10 +1,6 @@
-use crate::ws::ws_routes;
-use axum::routing::get;
+use crate::ws::{ws_routes, WsState};
 use axum::Router;
-use sea_orm::DatabaseConnection;
 
-pub fn get_routes(db: DatabaseConnection) -> Router {
-    Router::new()
-        .route("/health", get(|| async { "OK" }))
-        .merge(ws_routes(db))
+pub fn get_routes(ws_state: WsState) -> Router {
+    Router::new().merge(ws_routes(ws_state))
 }
@@ -0,0 +1,183 @@
+use crate::auth::CurrentUser;
+use crate::entity::{scheduled_messages, users, ScheduledMessages, Users};
+use crate::error::AppError;
+use crate::scheduled::service::{schedule_message, validate_send_at, ScheduleError};
+use crate::scheduled::types::{
+    ListScheduledParams, RescheduleRequest, ScheduleRequest, ScheduledView,
+};
+use crate::scheduled::{MessageScheduler, STATUS_CANCELLED, STATUS_PENDING};
+use axum::{
+    extract::{FromRef, Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::Utc;
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+};
+use std::collections::HashMap;
+
+#[derive(Clone)]
+pub struct ScheduledState {
+    pub db: DatabaseConnection,
+    pub scheduler: MessageScheduler,
+}
+
+impl FromRef<ScheduledState> for DatabaseConnection {
+    fn from_ref(state: &ScheduledState) -> Self {
+        state.db.clone()
+    }
+}
+
+async fn find_own(
+    db: &DatabaseConnection,
+    sender_id: i32,
+    id: i32,
+) -> Result<scheduled_messages::Model, ScheduleError> {
+    ScheduledMessages::find_by_id(id)
+        .filter(scheduled_messages::Column::SenderId.eq(sender_id))
+        .one(db)
+        .await?
+        .ok_or(ScheduleError::NotFound)
+}
+
+async fn view(
+    db: &DatabaseConnection,
+    scheduled: scheduled_messages::Model,
+) -> Result<ScheduledView, ScheduleError> {
+    let to = Users::find_by_id(scheduled.recipient_id)
+        .one(db)
+        .await?
+        .map(|u| u.username)
+        .unwrap_or_default();
+    Ok(ScheduledView::new(scheduled, to))
+}
+
+/// `POST /scheduled-messages`
+pub async fn create_scheduled(
+    State(state): State<ScheduledState>,
+    CurrentUser(user): CurrentUser,
+    Json(payload): Json<ScheduleRequest>,
+) -> Result<(StatusCode, Json<ScheduledView>), AppError> {
+    let (scheduled, to) = schedule_message(&state.db, &state.scheduler, &user, payload).await?;
+    Ok((StatusCode::CREATED, Json(ScheduledView::new(scheduled, to))))
+}
+
+/// `GET /scheduled-messages?status=` — the caller's scheduled messages,
+/// soonest first.
+pub async fn list_scheduled(
+    State(state): State<ScheduledState>,
+    CurrentUser(user): CurrentUser,
+    Query(params): Query<ListScheduledParams>,
+) -> Result<Json<Vec<ScheduledView>>, AppError> {
+    let mut query =
+        ScheduledMessages::find().filter(scheduled_messages::Column::SenderId.eq(user.id));
+    match params.status.as_deref() {
+        Some("all") => {}
+        status => {
+            query = query
+                .filter(scheduled_messages::Column::Status.eq(status.unwrap_or(STATUS_PENDING)))
+        }
+    }
+    let scheduled = query
+        .order_by_asc(scheduled_messages::Column::SendAt)
+        .all(&state.db)
+        .await
+        .map_err(ScheduleError::from)?;
+
+    let recipients: HashMap<i32, String> = Users::find()
+        .filter(users::Column::Id.is_in(scheduled.iter().map(|s| s.recipient_id)))
+        .all(&state.db)
+        .await
+        .map_err(ScheduleError::from)?
+        .into_iter()
+        .map(|u| (u.id, u.username))
+        .collect();
+
+    Ok(Json(
+        scheduled
+            .into_iter()
+            .map(|s| {
+                let to = recipients.get(&s.recipient_id).cloned().unwrap_or_default();
+                ScheduledView::new(s, to)
+            })
+            .collect(),
+    ))
+}
+
+/// `GET /scheduled-messages/:id`
+pub async fn get_scheduled(
+    State(state): State<ScheduledState>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i32>,
+) -> Result<Json<ScheduledView>, AppError> {
+    let scheduled = find_own(&state.db, user.id, id).await?;
+    Ok(Json(view(&state.db, scheduled).await?))
+}
+
+/// `PATCH /scheduled-messages/:id` — change the time or text while still pending.
+pub async fn reschedule(
+    State(state): State<ScheduledState>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i32>,
+    Json(payload): Json<RescheduleRequest>,
+) -> Result<Json<ScheduledView>, AppError> {
+    find_own(&state.db, user.id, id).await?;
+
+    let mut update = ScheduledMessages::update_many()
+        .col_expr(
+            scheduled_messages::Column::UpdatedAt,
+            Expr::value(Utc::now()),
+        )
+        .filter(scheduled_messages::Column::Id.eq(id))
+        .filter(scheduled_messages::Column::Status.eq(STATUS_PENDING));
+    if let Some(send_at) = payload.send_at {
+        validate_send_at(send_at)?;
+        update = update.col_expr(scheduled_messages::Column::SendAt, Expr::value(send_at));
+    }
+    if let Some(text) = payload.text {
+        let text = text.trim().to_string();
+        if text.is_empty() {
+            return Err(ScheduleError::EmptyMessage.into());
+        }
+        update = update.col_expr(scheduled_messages::Column::Message, Expr::value(text));
+    }
+
+    // Conditional on `pending`, so it cannot race with the scheduler's claim
+    let result = update.exec(&state.db).await.map_err(ScheduleError::from)?;
+    if result.rows_affected == 0 {
+        return Err(ScheduleError::NotPending.into());
+    }
+    state.scheduler.notify();
+
+    let scheduled = find_own(&state.db, user.id, id).await?;
+    Ok(Json(view(&state.db, scheduled).await?))
+}
+
+/// `DELETE /scheduled-messages/:id` — cancel a pending message.
+pub async fn cancel_scheduled(
+    State(state): State<ScheduledState>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i32>,
+) -> Result<StatusCode, AppError> {
+    find_own(&state.db, user.id, id).await?;
+
+    let result = ScheduledMessages::update_many()
+        .col_expr(
+            scheduled_messages::Column::Status,
+            Expr::value(STATUS_CANCELLED),
+        )
+        .col_expr(
+            scheduled_messages::Column::UpdatedAt,
+            Expr::value(Utc::now()),
+        )
+        .filter(scheduled_messages::Column::Id.eq(id))
+        .filter(scheduled_messages::Column::Status.eq(STATUS_PENDING))
+        .exec(&state.db)
+        .await
+        .map_err(ScheduleError::from)?;
+    if result.rows_affected == 0 {
+        return Err(ScheduleError::NotPending.into());
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
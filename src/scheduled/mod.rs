@@ -0,0 +1,13 @@
+pub mod handlers;
+pub mod routes;
+pub mod scheduler;
+pub mod service;
+pub mod types;
+
+pub use scheduler::MessageScheduler;
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_SENDING: &str = "sending";
+pub const STATUS_SENT: &str = "sent";
+pub const STATUS_CANCELLED: &str = "cancelled";
+pub const STATUS_FAILED: &str = "failed";
@@ -0,0 +1,20 @@
+use axum::{routing::get, Router};
+
+use crate::scheduled::handlers::{
+    cancel_scheduled, create_scheduled, get_scheduled, list_scheduled, reschedule, ScheduledState,
+};
+
+pub fn configure_scheduled_routes(state: ScheduledState) -> Router {
+    Router::new()
+        .route(
+            "/scheduled-messages",
+            get(list_scheduled).post(create_scheduled),
+        )
+        .route(
+            "/scheduled-messages/:id",
+            get(get_scheduled)
+                .patch(reschedule)
+                .delete(cancel_scheduled),
+        )
+        .with_state(state)
+}
@@ -0,0 +1,415 @@
+//! Durable dispatcher for scheduled messages. Rows wait in
+//! `scheduled_messages`; whichever server instance claims a due row sends it
+//! through the same routing path as a live socket.
+
+use crate::entity::{scheduled_messages, users, ScheduledMessages, Users};
+use crate::retention::RetentionPolicy;
+use crate::scheduled::types::{ScheduledFrame, ScheduledView};
+use crate::scheduled::{STATUS_FAILED, STATUS_PENDING, STATUS_SENDING, STATUS_SENT};
+use crate::shutdown::Shutdown;
+use crate::ws::{route_text_message, send_to_user, SendOutcome, SharedState};
+use chrono::{Duration, Utc};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, Set,
+};
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+const BATCH_SIZE: u64 = 50;
+/// Longest the scheduler sleeps without looking at the queue, so rows added
+/// by other instances are picked up.
+const MAX_IDLE: std::time::Duration = std::time::Duration::from_secs(15);
+/// A claim this old belongs to an instance that died mid-send.
+const STALE_AFTER: Duration = Duration::minutes(5);
+/// Pause after a full batch before fetching the next, so a batch that keeps
+/// coming back (say, claims failing) can't spin.
+const FULL_BATCH_PAUSE: std::time::Duration = std::time::Duration::from_millis(100);
+
+#[derive(Clone)]
+pub struct MessageScheduler {
+    db: DatabaseConnection,
+    online: SharedState,
+    retention: RetentionPolicy,
+    wake: Arc<Notify>,
+}
+
+impl MessageScheduler {
+    pub fn new(db: DatabaseConnection, online: SharedState, retention: RetentionPolicy) -> Self {
+        Self {
+            db,
+            online,
+            retention,
+            wake: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Re-check the queue after a message was scheduled or rescheduled, in
+    /// case it is due before the current sleep ends.
+    pub fn notify(&self) {
+        self.wake.notify_one();
+    }
+
+    pub fn spawn(self, shutdown: &Shutdown) -> tokio::task::JoinHandle<()> {
+        let shutdown = shutdown.clone();
+        shutdown.clone().spawn(async move {
+            while !shutdown.is_draining() {
+                if let Err(e) = self.fail_stale().await {
+                    warn!("Scheduler failed to check stale claims: {}", e);
+                }
+                let idle = match self.run_due().await {
+                    // A full batch suggests more is due
+                    Ok(n) if n as u64 == BATCH_SIZE => FULL_BATCH_PAUSE,
+                    Ok(_) => self.until_next_due().await.unwrap_or(MAX_IDLE),
+                    Err(e) => {
+                        warn!("Scheduler failed to poll queue: {}", e);
+                        self.until_next_due().await.unwrap_or(MAX_IDLE)
+                    }
+                };
+                tokio::select! {
+                    _ = shutdown.cancelled() => {}
+                    _ = self.wake.notified() => {}
+                    _ = tokio::time::sleep(idle) => {}
+                }
+            }
+        })
+    }
+
+    async fn until_next_due(&self) -> Result<std::time::Duration, sea_orm::DbErr> {
+        let next = ScheduledMessages::find()
+            .filter(scheduled_messages::Column::Status.eq(STATUS_PENDING))
+            .order_by_asc(scheduled_messages::Column::SendAt)
+            .one(&self.db)
+            .await?;
+        Ok(match next {
+            Some(next) => (next.send_at - Utc::now())
+                .to_std()
+                .unwrap_or_default()
+                .min(MAX_IDLE),
+            None => MAX_IDLE,
+        })
+    }
+
+    /// A row stuck in `sending` may or may not have gone out before its
+    /// instance died. Fail it rather than risk a duplicate.
+    async fn fail_stale(&self) -> Result<(), sea_orm::DbErr> {
+        let stale = ScheduledMessages::find()
+            .filter(scheduled_messages::Column::Status.eq(STATUS_SENDING))
+            .filter(scheduled_messages::Column::ClaimedAt.lt(Utc::now() - STALE_AFTER))
+            .all(&self.db)
+            .await?;
+        for job in stale {
+            warn!("Scheduled message {} was interrupted while sending", job.id);
+            self.finish(
+                job,
+                STATUS_FAILED,
+                None,
+                Some("Sending was interrupted".to_string()),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn run_due(&self) -> Result<usize, sea_orm::DbErr> {
+        let due = ScheduledMessages::find()
+            .filter(scheduled_messages::Column::Status.eq(STATUS_PENDING))
+            .filter(scheduled_messages::Column::SendAt.lte(Utc::now()))
+            .order_by_asc(scheduled_messages::Column::SendAt)
+            .limit(BATCH_SIZE)
+            .all(&self.db)
+            .await?;
+
+        let count = due.len();
+        for job in due {
+            match self.claim(job.id).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    warn!("Scheduler failed to claim message {}: {}", job.id, e);
+                    continue;
+                }
+            }
+            let id = job.id;
+            if let Err(e) = self.dispatch(job.clone()).await {
+                // It may have gone out before the error; fail it rather than
+                // risk a duplicate, like an interrupted send
+                warn!("Scheduled message {} failed to send: {}", id, e);
+                if let Err(e) = self
+                    .finish(job, STATUS_FAILED, None, Some("Sending failed".to_string()))
+                    .await
+                {
+                    warn!("Failed to mark scheduled message {} failed: {}", id, e);
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// Atomically move a row from pending to sending so that only one
+    /// instance ever sends it, and a concurrent cancel either wins or loses
+    /// cleanly.
+    async fn claim(&self, id: i32) -> Result<bool, sea_orm::DbErr> {
+        let result = ScheduledMessages::update_many()
+            .col_expr(
+                scheduled_messages::Column::Status,
+                Expr::value(STATUS_SENDING),
+            )
+            .col_expr(
+                scheduled_messages::Column::ClaimedAt,
+                Expr::value(Utc::now()),
+            )
+            .filter(scheduled_messages::Column::Id.eq(id))
+            .filter(scheduled_messages::Column::Status.eq(STATUS_PENDING))
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected == 1)
+    }
+
+    async fn dispatch(&self, job: scheduled_messages::Model) -> Result<(), sea_orm::DbErr> {
+        let sender = Users::find_by_id(job.sender_id).one(&self.db).await?;
+        let recipient = Users::find_by_id(job.recipient_id).one(&self.db).await?;
+
+        let (sender, recipient) = match (sender, recipient) {
+            (Some(sender), _) if sender.is_deleted() || sender.is_banned() => {
+                return self
+                    .finish(
+                        job,
+                        STATUS_FAILED,
+                        None,
+                        Some("Account unavailable".to_string()),
+                    )
+                    .await
+            }
+            (Some(sender), _) if sender.is_suspended() => {
+                return self
+                    .finish(
+                        job,
+                        STATUS_FAILED,
+                        None,
+                        Some("Account suspended".to_string()),
+                    )
+                    .await
+            }
+            (Some(sender), Some(recipient)) => (sender, recipient),
+            _ => {
+                return self
+                    .finish(
+                        job,
+                        STATUS_FAILED,
+                        None,
+                        Some("User no longer exists".to_string()),
+                    )
+                    .await
+            }
+        };
+
+        let outcome = route_text_message(
+            &self.db,
+            &self.online,
+            self.retention,
+            &sender,
+            &recipient.username,
+            job.message.clone(),
+        )
+        .await?;
+        match outcome {
+            SendOutcome::Sent(message) => {
+                info!("⏰ Sent scheduled message {}", job.id);
+                self.finish(job, STATUS_SENT, Some(message.id), None).await
+            }
+            SendOutcome::RecipientBlocked => {
+                let error = format!("You have blocked user '{}'", recipient.username);
+                self.finish(job, STATUS_FAILED, None, Some(error)).await
+            }
+            SendOutcome::UnknownRecipient => {
+                let error = format!("User '{}' does not exist", recipient.username);
+                self.finish(job, STATUS_FAILED, None, Some(error)).await
+            }
+        }
+    }
+
+    /// Record the outcome and tell the sender if they are online.
+    async fn finish(
+        &self,
+        job: scheduled_messages::Model,
+        status: &str,
+        message_id: Option<i32>,
+        error: Option<String>,
+    ) -> Result<(), sea_orm::DbErr> {
+        let mut update: scheduled_messages::ActiveModel = job.into();
+        update.status = Set(status.to_string());
+        update.message_id = Set(message_id);
+        update.error = Set(error);
+        update.updated_at = Set(Utc::now());
+        let job = update.update(&self.db).await?;
+
+        let names = Users::find()
+            .filter(users::Column::Id.is_in([job.sender_id, job.recipient_id]))
+            .all(&self.db)
+            .await?;
+        let name = |id: i32| {
+            names
+                .iter()
+                .find(|u| u.id == id)
+                .map(|u| u.username.clone())
+        };
+        if let (Some(sender), Some(to)) = (name(job.sender_id), name(job.recipient_id)) {
+            let frame = ScheduledFrame::new(ScheduledView::new(job, to)).to_text();
+            send_to_user(&self.online, &sender, frame).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::CurrentUser;
+    use crate::scheduled::handlers::{cancel_scheduled, ScheduledState};
+    use crate::scheduled::STATUS_CANCELLED;
+    use crate::test_support::{create_user, test_db};
+    use axum::extract::{Path, State};
+    use axum::http::StatusCode;
+    use sea_orm::ConnectionTrait;
+
+    /// Messages with this prefix are rejected by a trigger, to make a
+    /// dispatch fail inside the database.
+    const FAILING_PREFIX: &str = "scheduler-test-fail";
+
+    async fn scheduled(
+        db: &DatabaseConnection,
+        sender: &users::Model,
+        recipient: &users::Model,
+        text: &str,
+        send_at: chrono::DateTime<Utc>,
+    ) -> scheduled_messages::Model {
+        let now = Utc::now();
+        scheduled_messages::ActiveModel {
+            sender_id: Set(sender.id),
+            recipient_id: Set(recipient.id),
+            message: Set(text.to_string()),
+            send_at: Set(send_at),
+            status: Set(STATUS_PENDING.to_string()),
+            created_at: Set(now),
+            updated_at: Set(now),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+        .unwrap()
+    }
+
+    async fn reload(db: &DatabaseConnection, id: i32) -> scheduled_messages::Model {
+        ScheduledMessages::find_by_id(id)
+            .one(db)
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    fn scheduler(db: &DatabaseConnection) -> MessageScheduler {
+        MessageScheduler::new(
+            db.clone(),
+            SharedState::default(),
+            RetentionPolicy::from_days(None),
+        )
+    }
+
+    // Claim tests schedule far ahead so `run_due` in another test never
+    // picks their rows up
+
+    #[tokio::test]
+    async fn only_one_claim_wins() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let alice = create_user(&db, "alice").await;
+        let bob = create_user(&db, "bob").await;
+        let job = scheduled(&db, &alice, &bob, "hi", Utc::now() + Duration::days(1)).await;
+        let (a, b) = (scheduler(&db), scheduler(&db));
+
+        let (first, second) = tokio::join!(a.claim(job.id), b.claim(job.id));
+        assert!(first.unwrap() ^ second.unwrap());
+        assert_eq!(reload(&db, job.id).await.status, STATUS_SENDING);
+    }
+
+    #[tokio::test]
+    async fn cancel_and_claim_exclude_each_other() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let alice = create_user(&db, "alice").await;
+        let bob = create_user(&db, "bob").await;
+        let scheduler = scheduler(&db);
+        let state = || ScheduledState {
+            db: db.clone(),
+            scheduler: scheduler.clone(),
+        };
+        let later = Utc::now() + Duration::days(1);
+
+        // Cancelled first: the scheduler must not take it
+        let cancelled = scheduled(&db, &alice, &bob, "never", later).await;
+        cancel_scheduled(
+            State(state()),
+            CurrentUser(alice.clone()),
+            Path(cancelled.id),
+        )
+        .await
+        .unwrap();
+        assert!(!scheduler.claim(cancelled.id).await.unwrap());
+        assert_eq!(reload(&db, cancelled.id).await.status, STATUS_CANCELLED);
+
+        // Claimed first: too late to cancel
+        let claimed = scheduled(&db, &alice, &bob, "sending", later).await;
+        assert!(scheduler.claim(claimed.id).await.unwrap());
+        let err = cancel_scheduled(State(state()), CurrentUser(alice.clone()), Path(claimed.id))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::CONFLICT);
+        assert_eq!(reload(&db, claimed.id).await.status, STATUS_SENDING);
+    }
+
+    #[tokio::test]
+    async fn a_failed_dispatch_does_not_stop_the_batch() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        db.execute_unprepared(&format!(
+            "CREATE OR REPLACE FUNCTION scheduler_test_fail() RETURNS trigger AS $$ \
+             BEGIN \
+             IF NEW.message LIKE '{}%' THEN RAISE EXCEPTION 'rejected by test'; END IF; \
+             RETURN NEW; \
+             END $$ LANGUAGE plpgsql; \
+             DROP TRIGGER IF EXISTS scheduler_test_fail ON messages; \
+             CREATE TRIGGER scheduler_test_fail BEFORE INSERT ON messages \
+             FOR EACH ROW EXECUTE FUNCTION scheduler_test_fail();",
+            FAILING_PREFIX
+        ))
+        .await
+        .unwrap();
+
+        let alice = create_user(&db, "alice").await;
+        let bob = create_user(&db, "bob").await;
+        let due = Utc::now() - Duration::seconds(1);
+        let failing = scheduled(
+            &db,
+            &alice,
+            &bob,
+            &format!("{} boom", FAILING_PREFIX),
+            due - Duration::seconds(1),
+        )
+        .await;
+        let fine = scheduled(&db, &alice, &bob, "still sent", due).await;
+
+        scheduler(&db).run_due().await.unwrap();
+
+        let failing = reload(&db, failing.id).await;
+        assert_eq!(failing.status, STATUS_FAILED);
+        assert_eq!(failing.error.as_deref(), Some("Sending failed"));
+        let fine = reload(&db, fine.id).await;
+        assert_eq!(fine.status, STATUS_SENT);
+        assert!(fine.message_id.is_some());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::entity::{scheduled_messages, users, ScheduledMessages, Users};
+use crate::error::AppError;
+use crate::scheduled::types::ScheduleRequest;
+use crate::scheduled::{MessageScheduler, STATUS_PENDING};
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter,
+    Set,
+};
+
+/// Pending scheduled messages one user may have at a time.
+pub const MAX_PENDING_PER_USER: u64 = 100;
+const MAX_SCHEDULE_AHEAD: Duration = Duration::days(365);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScheduleError {
+    #[error("Message must not be empty")]
+    EmptyMessage,
+    #[error("send_at must be in the future")]
+    InPast,
+    #[error("send_at must be within a year")]
+    TooFarAhead,
+    #[error("User '{0}' does not exist")]
+    UnknownRecipient(String),
+    #[error("At most {} scheduled messages can be pending", MAX_PENDING_PER_USER)]
+    TooManyPending,
+    #[error("Scheduled message not found")]
+    NotFound,
+    #[error("Scheduled message was already sent or cancelled")]
+    NotPending,
+    #[error(transparent)]
+    Db(#[from] sea_orm::DbErr),
+}
+
+impl From<ScheduleError> for AppError {
+    fn from(e: ScheduleError) -> Self {
+        match e {
+            ScheduleError::EmptyMessage | ScheduleError::InPast | ScheduleError::TooFarAhead => {
+                AppError::Unprocessable(e.to_string())
+            }
+            ScheduleError::UnknownRecipient(name) => {
+                AppError::not_found(format!("User '{}'", name))
+            }
+            ScheduleError::NotFound => AppError::not_found("Scheduled message"),
+            ScheduleError::TooManyPending | ScheduleError::NotPending => {
+                AppError::Conflict(e.to_string())
+            }
+            ScheduleError::Db(e) => AppError::Database(e),
+        }
+    }
+}
+
+pub fn validate_send_at(send_at: DateTime<Utc>) -> Result<(), ScheduleError> {
+    let now = Utc::now();
+    if send_at <= now {
+        return Err(ScheduleError::InPast);
+    }
+    if send_at > now + MAX_SCHEDULE_AHEAD {
+        return Err(ScheduleError::TooFarAhead);
+    }
+    Ok(())
+}
+
+/// Queue a message from `sender`. Shared by the REST endpoint and the
+/// WebSocket `schedule` frame. Returns the row and the recipient's username.
+pub async fn schedule_message(
+    db: &DatabaseConnection,
+    scheduler: &MessageScheduler,
+    sender: &users::Model,
+    request: ScheduleRequest,
+) -> Result<(scheduled_messages::Model, String), ScheduleError> {
+    let text = request.text.trim().to_string();
+    if text.is_empty() {
+        return Err(ScheduleError::EmptyMessage);
+    }
+    validate_send_at(request.send_at)?;
+
+    let to = request.to.trim();
+    let recipient = Users::find()
+        .filter(users::Column::Username.eq(to))
+        .filter(users::Column::DeletedAt.is_null())
+        .one(db)
+        .await?
+        .ok_or_else(|| ScheduleError::UnknownRecipient(to.to_string()))?;
+
+    let pending = ScheduledMessages::find()
+        .filter(scheduled_messages::Column::SenderId.eq(sender.id))
+        .filter(scheduled_messages::Column::Status.eq(STATUS_PENDING))
+        .count(db)
+        .await?;
+    if pending >= MAX_PENDING_PER_USER {
+        return Err(ScheduleError::TooManyPending);
+    }
+
+    let now = Utc::now();
+    let scheduled = scheduled_messages::ActiveModel {
+        sender_id: Set(sender.id),
+        recipient_id: Set(recipient.id),
+        message: Set(text),
+        send_at: Set(request.send_at),
+        status: Set(STATUS_PENDING.to_string()),
+        created_at: Set(now),
+        updated_at: Set(now),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+
+    scheduler.notify();
+    Ok((scheduled, recipient.username))
+}
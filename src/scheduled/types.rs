@@ -0,0 +1,75 @@
+use crate::entity::scheduled_messages;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Body of `POST /scheduled-messages`, also accepted over the WebSocket as
+/// `{"type":"schedule", ...}`.
+#[derive(Deserialize)]
+pub struct ScheduleRequest {
+    pub to: String,
+    pub text: String,
+    pub send_at: DateTime<Utc>,
+}
+
+/// Change the time and/or text of a pending message.
+#[derive(Deserialize)]
+pub struct RescheduleRequest {
+    pub send_at: Option<DateTime<Utc>>,
+    pub text: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ListScheduledParams {
+    /// Defaults to `pending`; `all` lists every status
+    pub status: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ScheduledView {
+    pub id: i32,
+    pub to: String,
+    pub text: String,
+    pub send_at: DateTime<Utc>,
+    pub status: String,
+    pub message_id: Option<i32>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ScheduledView {
+    pub fn new(scheduled: scheduled_messages::Model, to: String) -> Self {
+        Self {
+            id: scheduled.id,
+            to,
+            text: scheduled.message,
+            send_at: scheduled.send_at,
+            status: scheduled.status,
+            message_id: scheduled.message_id,
+            error: scheduled.error,
+            created_at: scheduled.created_at,
+        }
+    }
+}
+
+/// Sent to the sender when a scheduled message is accepted over the
+/// WebSocket, and again when it is sent or fails.
+#[derive(Serialize)]
+pub struct ScheduledFrame {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    #[serde(flatten)]
+    pub scheduled: ScheduledView,
+}
+
+impl ScheduledFrame {
+    pub fn new(scheduled: ScheduledView) -> Self {
+        Self {
+            kind: "scheduled_message",
+            scheduled,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).expect("scheduled frame serializes")
+    }
+}
@@ -0,0 +1,234 @@
+use crate::auth::CurrentUser;
+use crate::blocks::BLOCKED_STATUS;
+use crate::entity::{users, Users};
+use crate::error::AppError;
+use crate::moderation::REMOVED_STATUS;
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbBackend, EntityTrait, FromQueryResult, QueryFilter,
+    Statement, Value,
+};
+use serde::{Deserialize, Serialize};
+
+/// Text search configuration used for both the index and queries. `simple`
+/// avoids language-specific stemming since chats mix languages freely. The
+/// GIN index in the `migration` crate is built with the same configuration.
+pub const SEARCH_CONFIG: &str = "simple";
+
+const DEFAULT_LIMIT: u64 = 20;
+const MAX_LIMIT: u64 = 100;
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    q: String,
+    /// Restrict to the conversation with this username
+    with: Option<String>,
+    /// Restrict to messages sent by this username
+    from: Option<String>,
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+    limit: Option<u64>,
+    offset: Option<u64>,
+}
+
+#[derive(Debug, Serialize, FromQueryResult)]
+pub struct SearchHit {
+    id: i32,
+    sender: String,
+    receiver: String,
+    created_at: DateTime<Utc>,
+    rank: f32,
+    /// Matching excerpt with terms wrapped in `<mark>`; the message text
+    /// itself is not HTML-escaped.
+    snippet: String,
+}
+
+#[derive(Serialize)]
+pub struct SearchResponse {
+    results: Vec<SearchHit>,
+}
+
+async fn user_id_by_name(db: &DatabaseConnection, username: &str) -> Result<i32, AppError> {
+    Users::find()
+        .filter(users::Column::Username.eq(username))
+        .one(db)
+        .await?
+        .map(|u| u.id)
+        .ok_or_else(|| AppError::not_found(format!("User '{}'", username)))
+}
+
+/// `GET /search?q=` — ranked full-text search over the caller's own conversations.
+pub async fn search_messages(
+    State(db): State<DatabaseConnection>,
+    CurrentUser(user): CurrentUser,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<SearchResponse>, AppError> {
+    let q = params.q.trim();
+    if q.is_empty() {
+        return Err(AppError::bad_request("Query must not be empty"));
+    }
+
+    let mut values: Vec<Value> = vec![q.into(), user.id.into()];
+    // Every result must involve the caller, whatever other filters are set
+    let mut conditions = vec![
+        "(m.sender_id = $2 OR m.receiver_id = $2)".to_string(),
+        format!(
+            "NOT (m.receiver_id = $2 AND m.status = '{}')",
+            BLOCKED_STATUS
+        ),
+        format!("m.status <> '{}'", REMOVED_STATUS),
+        "(m.expires_at IS NULL OR m.expires_at > now())".to_string(),
+    ];
+
+    if let Some(with) = params.with.as_deref() {
+        values.push(user_id_by_name(&db, with).await?.into());
+        let p = values.len();
+        conditions.push(format!(
+            "((m.sender_id = $2 AND m.receiver_id = ${p}) OR (m.sender_id = ${p} AND m.receiver_id = $2))"
+        ));
+    }
+    if let Some(from) = params.from.as_deref() {
+        values.push(user_id_by_name(&db, from).await?.into());
+        conditions.push(format!("m.sender_id = ${}", values.len()));
+    }
+    if let Some(after) = params.after {
+        values.push(after.into());
+        conditions.push(format!("m.created_at >= ${}", values.len()));
+    }
+    if let Some(before) = params.before {
+        values.push(before.into());
+        conditions.push(format!("m.created_at < ${}", values.len()));
+    }
+
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+
+    let sql = format!(
+        "SELECT m.id, s.username AS sender, r.username AS receiver, m.created_at, \
+                ts_rank(to_tsvector('{cfg}', m.message), query) AS rank, \
+                ts_headline('{cfg}', m.message, query, \
+                    'StartSel=<mark>, StopSel=</mark>, MaxWords=24, MinWords=8') AS snippet \
+         FROM messages m \
+         JOIN users s ON s.id = m.sender_id \
+         JOIN users r ON r.id = m.receiver_id, \
+         websearch_to_tsquery('{cfg}', $1) query \
+         WHERE to_tsvector('{cfg}', m.message) @@ query AND {conditions} \
+         ORDER BY rank DESC, m.created_at DESC \
+         LIMIT {limit} OFFSET {offset}",
+        cfg = SEARCH_CONFIG,
+        conditions = conditions.join(" AND "),
+    );
+
+    let results = SearchHit::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        sql,
+        values,
+    ))
+    .all(&db)
+    .await?;
+
+    Ok(Json(SearchResponse { results }))
+}
+
+pub fn search_routes(db: DatabaseConnection) -> Router {
+    Router::new()
+        .route("/search", get(search_messages))
+        .with_state(db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::messages;
+    use crate::test_support::{create_message, create_user, test_db};
+    use chrono::Duration;
+    use sea_orm::{ActiveModelTrait, Set};
+    use uuid::Uuid;
+
+    /// A word no other test's messages contain, so results only come from
+    /// this test's rows.
+    fn unique_word() -> String {
+        format!("kw{}", &Uuid::new_v4().simple().to_string()[..12])
+    }
+
+    async fn search(
+        db: &DatabaseConnection,
+        user: &users::Model,
+        q: &str,
+        with: Option<&str>,
+    ) -> Vec<i32> {
+        let Json(response) = search_messages(
+            State(db.clone()),
+            CurrentUser(user.clone()),
+            Query(SearchParams {
+                q: q.to_string(),
+                with: with.map(str::to_string),
+                from: None,
+                after: None,
+                before: None,
+                limit: None,
+                offset: None,
+            }),
+        )
+        .await
+        .unwrap();
+        let mut ids: Vec<i32> = response.results.into_iter().map(|hit| hit.id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn only_the_callers_conversations_are_searched() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let word = unique_word();
+        let alice = create_user(&db, "alice").await;
+        let bob = create_user(&db, "bob").await;
+        let carol = create_user(&db, "carol").await;
+        let dave = create_user(&db, "dave").await;
+        let sent = create_message(&db, &alice, &bob, &format!("hello {}", word)).await;
+        let received = create_message(&db, &bob, &alice, &format!("{} back", word)).await;
+        let other = create_message(&db, &carol, &dave, &format!("hello {}", word)).await;
+
+        assert_eq!(
+            search(&db, &alice, &word, None).await,
+            vec![sent.id, received.id]
+        );
+        assert_eq!(search(&db, &carol, &word, None).await, vec![other.id]);
+        // Naming someone else's partner doesn't open their conversation
+        assert!(search(&db, &alice, &word, Some(&dave.username))
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn removed_and_expired_messages_are_excluded() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let word = unique_word();
+        let alice = create_user(&db, "alice").await;
+        let bob = create_user(&db, "bob").await;
+        let visible = create_message(&db, &alice, &bob, &word).await;
+
+        let removed = create_message(&db, &alice, &bob, &word).await;
+        let mut removed: messages::ActiveModel = removed.into();
+        removed.status = Set(REMOVED_STATUS.to_string());
+        removed.update(&db).await.unwrap();
+
+        // Past its expiry but not swept yet
+        let expired = create_message(&db, &bob, &alice, &word).await;
+        let mut expired: messages::ActiveModel = expired.into();
+        expired.expires_at = Set(Some(Utc::now() - Duration::minutes(1)));
+        expired.update(&db).await.unwrap();
+
+        assert_eq!(search(&db, &alice, &word, None).await, vec![visible.id]);
+        assert_eq!(search(&db, &bob, &word, None).await, vec![visible.id]);
+    }
+}
@@ -0,0 +1,152 @@
+use std::future::Future;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio_util::sync::{CancellationToken, WaitForCancellationFuture};
+use tokio_util::task::TaskTracker;
+use tracing::info;
+
+/// Shared shutdown coordinator. On SIGTERM or Ctrl-C it first stops
+/// advertising readiness, then after a pre-stop delay starts draining:
+/// sockets and background workers watch it, and register with its tracker
+/// so `run()` can wait for their in-flight database writes before exiting.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    stopping: CancellationToken,
+    token: CancellationToken,
+    tasks: TaskTracker,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start draining: refuse new sockets and tell everything else to wind down.
+    pub fn trigger(&self) {
+        self.stopping.cancel();
+        self.token.cancel();
+    }
+
+    /// True from the first signal on, including the pre-stop delay while
+    /// everything still works; readiness fails from here.
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.is_cancelled()
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    pub fn cancelled(&self) -> WaitForCancellationFuture<'_> {
+        self.token.cancelled()
+    }
+
+    /// Spawn a task that shutdown waits for.
+    pub fn spawn<F>(&self, task: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.tasks.spawn(task)
+    }
+
+    /// Wrap a future spawned elsewhere (e.g. by the WebSocket upgrade) so
+    /// shutdown waits for it.
+    pub fn track<F: Future>(&self, task: F) -> impl Future<Output = F::Output> {
+        self.tasks.track_future(task)
+    }
+
+    /// Number of tracked tasks still running.
+    pub fn pending(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Wait for every tracked task to finish. Callers bound this with a deadline.
+    pub async fn drain(&self) {
+        self.tasks.close();
+        self.tasks.wait().await;
+    }
+
+    /// Resolve on the first termination signal, once `pre_stop` has passed
+    /// and draining has started. Meant for
+    /// `axum::serve(..).with_graceful_shutdown`.
+    pub async fn on_signal(self, pre_stop: Duration) {
+        termination_signal().await;
+        info!(
+            "🛑 Shutdown signal received, draining connections in {:?}",
+            pre_stop
+        );
+        // A second signal skips the wait
+        self.stop_after(pre_stop, termination_signal()).await;
+    }
+
+    /// Fail readiness now and start draining after `pre_stop` (or once
+    /// `skip` resolves), so load balancers see `/readyz` fail while
+    /// connections are still accepted.
+    async fn stop_after(&self, pre_stop: Duration, skip: impl Future<Output = ()>) {
+        self.stopping.cancel();
+        tokio::select! {
+            _ = tokio::time::sleep(pre_stop) => {}
+            _ = skip => {}
+        }
+        info!("🛑 Draining connections");
+        self.trigger();
+    }
+}
+
+async fn termination_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl-C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn readiness_fails_before_draining_starts() {
+        let shutdown = Shutdown::new();
+        let stopping = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move {
+                shutdown
+                    .stop_after(Duration::from_millis(200), std::future::pending())
+                    .await
+            }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(shutdown.is_stopping());
+        assert!(!shutdown.is_draining());
+
+        stopping.await.unwrap();
+        assert!(shutdown.is_draining());
+    }
+
+    #[tokio::test]
+    async fn a_second_signal_skips_the_delay() {
+        let shutdown = Shutdown::new();
+        shutdown
+            .stop_after(Duration::from_secs(3600), std::future::ready(()))
+            .await;
+        assert!(shutdown.is_draining());
+    }
+}
@@ -0,0 +1,13 @@
+//! Per-user event log behind session resume. Every frame that reflects a
+//! stored change (new messages, receipts, removals, expiries, attachment and
+//! timer updates, deleted accounts) is appended with a gap-free sequence
+//! number before it is pushed. A client reconnecting with `?since=<seq>` gets
+//! the events it missed instead of the full history. Logged copies of a
+//! message are redacted when the message itself goes.
+
+pub mod pruner;
+pub mod service;
+pub mod types;
+
+pub use pruner::EventLogPruner;
+pub use service::{current_seq, events_since, redact_messages, Resume, SyncEvent, REDACTED_KIND};
@@ -0,0 +1,50 @@
+//! Background deletion of sync events older than `EVENT_LOG_RETENTION_HOURS`.
+//! Clients whose cursor falls in the pruned range get a full resync.
+
+use crate::entity::{user_events, UserEvents};
+use crate::shutdown::Shutdown;
+use chrono::{Duration, Utc};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use tracing::{info, warn};
+
+const PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(600);
+
+#[derive(Clone)]
+pub struct EventLogPruner {
+    db: DatabaseConnection,
+    retention: Duration,
+}
+
+impl EventLogPruner {
+    pub fn new(db: DatabaseConnection, retention: Duration) -> Self {
+        Self { db, retention }
+    }
+
+    pub fn spawn(self, shutdown: &Shutdown) -> tokio::task::JoinHandle<()> {
+        let shutdown = shutdown.clone();
+        shutdown.clone().spawn(async move {
+            while !shutdown.is_draining() {
+                if let Err(e) = self.prune().await {
+                    warn!("Event log pruning failed: {}", e);
+                }
+                tokio::select! {
+                    _ = shutdown.cancelled() => {}
+                    _ = tokio::time::sleep(PRUNE_INTERVAL) => {}
+                }
+            }
+        })
+    }
+
+    async fn prune(&self) -> Result<u64, sea_orm::DbErr> {
+        let cutoff = Utc::now() - self.retention;
+        let deleted = UserEvents::delete_many()
+            .filter(user_events::Column::CreatedAt.lt(cutoff))
+            .exec(&self.db)
+            .await?
+            .rows_affected;
+        if deleted > 0 {
+            info!("🧹 Pruned {} sync event(s)", deleted);
+        }
+        Ok(deleted)
+    }
+}
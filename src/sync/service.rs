@@ -0,0 +1,215 @@
+use crate::entity::{user_events, users, UserEvents, Users};
+use crate::sync::types::FrameKind;
+use crate::ws::{send_outbound, Outbound, SharedState};
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, DbErr,
+    EntityTrait, QueryFilter, QueryOrder, Statement,
+};
+use tracing::error;
+use uuid::Uuid;
+
+/// Kind, and frame `type`, of a logged message that has since been removed.
+pub const REDACTED_KIND: &str = "message_redacted";
+
+/// A client further behind than this gets a full resync instead of a replay.
+pub const MAX_REPLAY: i64 = 1000;
+
+/// A frame on its way into a user's event log and out to their socket.
+pub struct SyncEvent {
+    kind: String,
+    frame: String,
+    correlation_id: Option<Uuid>,
+    message_id: Option<i32>,
+}
+
+impl SyncEvent {
+    /// The kind is the frame's `type`, or `message` for a plain text message.
+    pub fn new(frame: String) -> Self {
+        let kind = serde_json::from_str::<FrameKind>(&frame)
+            .map(|k| k.kind)
+            .unwrap_or_else(|_| "message".to_string());
+        Self {
+            kind,
+            frame,
+            correlation_id: None,
+            message_id: None,
+        }
+    }
+
+    pub fn correlation_id(mut self, correlation_id: Uuid) -> Self {
+        self.correlation_id = Some(correlation_id);
+        self
+    }
+
+    /// The stored message this frame carries, so the logged copy can be
+    /// redacted along with it.
+    pub fn message_id(mut self, message_id: i32) -> Self {
+        self.message_id = Some(message_id);
+        self
+    }
+
+    /// Append to the user's log, returning the sequence number it got.
+    pub async fn record(&self, db: &DatabaseConnection, user_id: i32) -> Result<i64, DbErr> {
+        // One statement, so the counter and the row can't disagree
+        let row = db
+            .query_one(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                r#"
+                WITH next AS (
+                    UPDATE users SET event_seq = event_seq + 1 WHERE id = $1
+                    RETURNING event_seq
+                )
+                INSERT INTO user_events (user_id, seq, kind, frame, message_id)
+                SELECT $1, event_seq, $2, $3, $4 FROM next
+                RETURNING seq
+                "#,
+                [
+                    user_id.into(),
+                    self.kind.clone().into(),
+                    self.frame.clone().into(),
+                    self.message_id.into(),
+                ],
+            ))
+            .await?
+            .ok_or_else(|| DbErr::RecordNotFound(format!("user {}", user_id)))?;
+        row.try_get("", "seq")
+    }
+
+    /// Log the event for `user_id` and push it to their socket if they are
+    /// connected. A failed append is logged and the frame still sent, just
+    /// without a sequence number. Returns whether it was queued for a socket.
+    pub async fn publish(
+        self,
+        db: &DatabaseConnection,
+        online: &SharedState,
+        user_id: i32,
+        username: &str,
+    ) -> bool {
+        let seq = match self.record(db, user_id).await {
+            Ok(seq) => Some(seq),
+            Err(e) => {
+                error!(
+                    "❌ Failed to log {} event for user {}: {}",
+                    self.kind, user_id, e
+                );
+                None
+            }
+        };
+        let outbound = Outbound {
+            correlation_id: self.correlation_id,
+            seq,
+            ..self.frame.into()
+        };
+        send_outbound(online, username, outbound).await
+    }
+}
+
+/// Replace every logged copy of these messages, in anyone's log, with a
+/// `message_redacted` frame. Rows are rewritten rather than deleted so
+/// sequence numbers stay gap-free and replays still line up.
+pub async fn redact_messages<C: ConnectionTrait>(
+    db: &C,
+    message_ids: &[i32],
+) -> Result<u64, DbErr> {
+    let mut redacted = 0;
+    for chunk in message_ids.chunks(1000) {
+        redacted += UserEvents::update_many()
+            .col_expr(user_events::Column::Kind, Expr::value(REDACTED_KIND))
+            .col_expr(
+                user_events::Column::Frame,
+                Expr::cust_with_values(
+                    "json_build_object('type', $1::text, 'id', message_id)::text",
+                    [REDACTED_KIND],
+                ),
+            )
+            .filter(user_events::Column::MessageId.is_in(chunk.to_vec()))
+            .filter(user_events::Column::Kind.ne(REDACTED_KIND))
+            .exec(db)
+            .await?
+            .rows_affected;
+    }
+    Ok(redacted)
+}
+
+/// Latest sequence number in the user's log; `0` before the first event.
+pub async fn current_seq(db: &DatabaseConnection, user_id: i32) -> Result<i64, DbErr> {
+    Ok(Users::find_by_id(user_id)
+        .one(db)
+        .await?
+        .map(|user: users::Model| user.event_seq)
+        .unwrap_or_default())
+}
+
+/// What a reconnecting client with cursor `since` should get.
+pub enum Resume {
+    /// Everything after the cursor, oldest first
+    Replay(Vec<user_events::Model>),
+    /// Events were pruned, the gap is too large, or the cursor is from the
+    /// future (e.g. after a database restore)
+    Expired,
+}
+
+/// Events after `since` up to and including `through`.
+pub async fn events_since(
+    db: &DatabaseConnection,
+    user_id: i32,
+    since: i64,
+    through: i64,
+) -> Result<Resume, DbErr> {
+    if since > through || through - since > MAX_REPLAY {
+        return Ok(Resume::Expired);
+    }
+    let events = UserEvents::find()
+        .filter(user_events::Column::UserId.eq(user_id))
+        .filter(user_events::Column::Seq.gt(since))
+        .filter(user_events::Column::Seq.lte(through))
+        .order_by_asc(user_events::Column::Seq)
+        .all(db)
+        .await?;
+    // Sequence numbers have no gaps, so a short read means pruning
+    if events.len() as i64 != through - since {
+        return Ok(Resume::Expired);
+    }
+    Ok(Resume::Replay(events))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{create_user, test_db};
+
+    #[tokio::test]
+    async fn redacting_keeps_sequence_numbers_gap_free() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let alice = create_user(&db, "alice").await;
+        let bob = create_user(&db, "bob").await;
+        // Ids no real message has, so other tests' rows are left alone
+        let message_id = -alice.id;
+        for user in [&alice, &bob] {
+            SyncEvent::new(format!("{}: secret", alice.username))
+                .message_id(message_id)
+                .record(&db, user.id)
+                .await
+                .unwrap();
+            SyncEvent::new(r#"{"type":"heartbeat_ack"}"#.to_string())
+                .record(&db, user.id)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(redact_messages(&db, &[message_id]).await.unwrap(), 2);
+        // Already redacted rows aren't rewritten again
+        assert_eq!(redact_messages(&db, &[message_id]).await.unwrap(), 0);
+
+        let Resume::Replay(events) = events_since(&db, bob.id, 0, 2).await.unwrap() else {
+            panic!("replay expired");
+        };
+        assert_eq!(events[0].kind, REDACTED_KIND);
+        let frame: serde_json::Value = serde_json::from_str(&events[0].frame).unwrap();
+        assert_eq!(frame["type"], REDACTED_KIND);
+        assert_eq!(frame["id"], message_id);
+        assert_eq!(events[1].kind, "heartbeat_ack");
+    }
+}
@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+
+/// A logged frame as sent to a client in resume mode, live or replayed.
+/// `frame` is exactly what a legacy client would have received.
+#[derive(Serialize)]
+pub struct EventFrame<'a> {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub seq: i64,
+    pub frame: &'a str,
+}
+
+impl<'a> EventFrame<'a> {
+    pub fn new(seq: i64, frame: &'a str) -> Self {
+        Self {
+            kind: "event",
+            seq,
+            frame,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).expect("event frame serializes")
+    }
+}
+
+/// Ends the catch-up on connect: the client is current through `cursor`.
+#[derive(Serialize)]
+pub struct SyncFrame {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub cursor: i64,
+    /// Events replayed since the client's cursor; `0` after a full history
+    pub replayed: usize,
+}
+
+impl SyncFrame {
+    pub fn new(cursor: i64, replayed: usize) -> Self {
+        Self {
+            kind: "sync",
+            cursor,
+            replayed,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).expect("sync frame serializes")
+    }
+}
+
+/// The client's view can't be patched up with events; it should drop its
+/// local state and rebuild it from the history that follows, or from a
+/// fresh connection.
+#[derive(Serialize)]
+pub struct ResyncFrame {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// `cursor_expired` or `outbound_queue_overflow`
+    pub reason: &'static str,
+}
+
+impl ResyncFrame {
+    pub fn new(reason: &'static str) -> Self {
+        Self {
+            kind: "resync_required",
+            reason,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).expect("resync frame serializes")
+    }
+}
+
+/// Just the `type` of a JSON frame.
+#[derive(Deserialize)]
+pub(crate) struct FrameKind {
+    #[serde(rename = "type")]
+    pub kind: String,
+}
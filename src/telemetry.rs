@@ -0,0 +1,184 @@
+use crate::config::{LogFormat, TelemetryConfig};
+use axum::http::Request;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use tower_http::request_id::RequestId;
+use tracing::{info_span, warn, Span};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+/// Header carrying the per-request correlation id, generated when the
+/// client doesn't send one and echoed on the response.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Keeps the OTLP exporter alive; `shutdown` flushes spans still buffered.
+pub struct Telemetry {
+    provider: Option<TracerProvider>,
+}
+
+impl Telemetry {
+    pub fn shutdown(self) {
+        if let Some(provider) = self.provider {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to flush traces: {}", e);
+            }
+        }
+    }
+}
+
+/// Install the global subscriber: text or JSON logs filtered by `RUST_LOG`
+/// (default `info`), plus span export when an OTLP endpoint is configured.
+pub fn init(config: &TelemetryConfig) -> Telemetry {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let logs = match config.log_format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    };
+
+    let (provider, export_error) = match &config.otlp_endpoint {
+        Some(endpoint) => match otlp_provider(endpoint.as_str(), &config.service_name) {
+            Ok(provider) => (Some(provider), None),
+            Err(e) => (None, Some(e)),
+        },
+        None => (None, None),
+    };
+    let traces = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(config.service_name.clone()))
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(logs)
+        .with(traces)
+        .init();
+
+    // Logging works now, so report a broken exporter instead of dying on it
+    if let Some(e) = export_error {
+        warn!("⚠️ OTLP export disabled: {}", e);
+    }
+    Telemetry { provider }
+}
+
+fn otlp_provider(endpoint: &str, service_name: &str) -> Result<TracerProvider, String> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| e.to_string())?;
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            service_name.to_string(),
+        )]))
+        .build())
+}
+
+/// Span for one HTTP request. `user_id` is filled in by the auth extractor
+/// once the caller is known.
+pub fn http_span<B>(request: &Request<B>) -> Span {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .unwrap_or_default();
+    info_span!(
+        "http_request",
+        method = %request.method(),
+        path = %request.uri().path(),
+        request_id,
+        user_id = tracing::field::Empty,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id};
+    use tracing_subscriber::layer::Context;
+    use tracing_subscriber::Registry;
+
+    /// Records the fields every new span is created with.
+    #[derive(Clone, Default)]
+    struct Fields(Arc<Mutex<Vec<(String, String)>>>);
+
+    impl Visit for Fields {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((field.name().to_string(), value.to_string()));
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+
+    impl<S: tracing::Subscriber> Layer<S> for Fields {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+            attrs.record(&mut self.clone());
+        }
+    }
+
+    fn span_fields(request: &Request<()>) -> Vec<(String, String)> {
+        let fields = Fields::default();
+        let subscriber = Registry::default().with(fields.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = http_span(request);
+        });
+        let recorded = fields.0.lock().unwrap().clone();
+        recorded
+    }
+
+    fn field<'a>(fields: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn http_span_carries_the_request_id() {
+        let mut request = Request::get("/messages/alice?limit=5").body(()).unwrap();
+        request
+            .extensions_mut()
+            .insert(RequestId::new(HeaderValue::from_static("req-123")));
+
+        let fields = span_fields(&request);
+        assert_eq!(field(&fields, "request_id"), Some("req-123"));
+        assert_eq!(field(&fields, "method"), Some("GET"));
+        assert_eq!(field(&fields, "path"), Some("/messages/alice"));
+        // Filled in later by the auth extractor
+        assert_eq!(field(&fields, "user_id"), None);
+    }
+
+    #[test]
+    fn http_span_without_a_request_id_is_blank() {
+        let request = Request::post("/auth/me").body(()).unwrap();
+        let fields = span_fields(&request);
+        assert_eq!(field(&fields, "request_id"), Some(""));
+    }
+
+    // The batch exporter flushes on shutdown from its own task, which a
+    // current-thread runtime would never get to run
+    #[tokio::test(flavor = "multi_thread")]
+    async fn otlp_provider_builds_without_a_collector() {
+        // The exporter connects lazily, so a missing collector must not
+        // keep the server from starting
+        let provider = otlp_provider("http://127.0.0.1:4317", "whisper-test").unwrap();
+        drop(provider.tracer("whisper-test"));
+        let _ = provider.shutdown();
+    }
+}
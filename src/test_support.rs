@@ -0,0 +1,63 @@
+//! Setup shared by tests that need Postgres. They run against the database
+//! in `TEST_DATABASE_URL` and pass trivially when it isn't set.
+
+use crate::entity::{messages, users};
+use crate::messages::UNREAD_STATUS;
+use chrono::Utc;
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{ActiveModelTrait, Database, DatabaseConnection, Set};
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+/// A connection to the migrated test database, or `None` to skip.
+pub async fn test_db() -> Option<DatabaseConnection> {
+    static MIGRATED: OnceCell<()> = OnceCell::const_new();
+    let url = std::env::var("TEST_DATABASE_URL").ok()?;
+    let db = Database::connect(url)
+        .await
+        .expect("Failed to connect to TEST_DATABASE_URL");
+    MIGRATED
+        .get_or_init(|| async {
+            Migrator::up(&db, None)
+                .await
+                .expect("Failed to migrate test database");
+        })
+        .await;
+    Some(db)
+}
+
+/// A fresh account; names are unique so tests can share a database.
+pub async fn create_user(db: &DatabaseConnection, name: &str) -> users::Model {
+    let id = Uuid::new_v4().simple().to_string();
+    users::ActiveModel {
+        username: Set(format!("{}-{}", name, &id[..12])),
+        phone_number: Set(format!("test-{}", id)),
+        role: Set("user".to_string()),
+        event_seq: Set(0),
+        is_bot: Set(false),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .expect("Failed to create test user")
+}
+
+/// A stored, unread message, as routing leaves it when no block applies.
+pub async fn create_message(
+    db: &DatabaseConnection,
+    sender: &users::Model,
+    receiver: &users::Model,
+    text: &str,
+) -> messages::Model {
+    messages::ActiveModel {
+        sender_id: Set(sender.id),
+        receiver_id: Set(receiver.id),
+        message: Set(text.to_string()),
+        created_at: Set(Utc::now()),
+        status: Set(UNREAD_STATUS.to_string()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+    .expect("Failed to create test message")
+}
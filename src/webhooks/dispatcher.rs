@@ -0,0 +1,351 @@
+//! Durable sender for webhook deliveries. Rows wait in `webhook_deliveries`;
+//! whichever server instance claims a due row POSTs it, and a failed attempt
+//! is rescheduled with exponential backoff.
+
+use crate::config::WebhookConfig;
+use crate::entity::{webhook_deliveries, webhooks, WebhookDeliveries, Webhooks};
+use crate::metrics::metrics;
+use crate::shutdown::Shutdown;
+use crate::webhooks::service::{sign, wake};
+use crate::webhooks::{
+    DELIVERY_HEADER, EVENT_HEADER, SIGNATURE_HEADER, STATUS_DELIVERED, STATUS_FAILED,
+    STATUS_PENDING, STATUS_SENDING, TIMESTAMP_HEADER,
+};
+use chrono::{Duration, Utc};
+use futures::StreamExt;
+use reqwest::header::CONTENT_TYPE;
+use sea_orm::{
+    sea_query::Expr, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+const BATCH_SIZE: u64 = 50;
+/// Attempts in flight at once, so one slow receiver doesn't hold up the rest.
+const CONCURRENCY: usize = 8;
+/// Longest the dispatcher sleeps without looking at the queue, so rows added
+/// by other instances are picked up.
+const MAX_IDLE: std::time::Duration = std::time::Duration::from_secs(15);
+/// A claim this old belongs to an instance that died mid-request.
+const STALE_AFTER: Duration = Duration::minutes(5);
+/// Wait after the first failed attempt; doubled after each further one.
+const FIRST_RETRY: Duration = Duration::seconds(30);
+const MAX_RETRY: Duration = Duration::hours(1);
+const PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(600);
+/// Bytes of a failed response body kept in `last_error`.
+const MAX_ERROR_LEN: usize = 500;
+
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    db: DatabaseConnection,
+    client: reqwest::Client,
+    max_attempts: i32,
+    retention: Duration,
+}
+
+impl WebhookDispatcher {
+    pub fn new(db: DatabaseConnection, config: &WebhookConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .user_agent("whisper-webhooks")
+            // A redirect would re-send a signed body somewhere unreviewed
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Failed to build webhook HTTP client");
+        Self {
+            db,
+            client,
+            max_attempts: config.max_attempts,
+            retention: config.delivery_retention,
+        }
+    }
+
+    pub fn spawn(self, shutdown: &Shutdown) -> tokio::task::JoinHandle<()> {
+        let shutdown = shutdown.clone();
+        shutdown.clone().spawn(async move {
+            let mut next_prune = std::time::Instant::now();
+            while !shutdown.is_draining() {
+                if std::time::Instant::now() >= next_prune {
+                    if let Err(e) = self.prune().await {
+                        warn!("Webhook delivery log pruning failed: {}", e);
+                    }
+                    next_prune = std::time::Instant::now() + PRUNE_INTERVAL;
+                }
+                if let Err(e) = self.release_stale().await {
+                    warn!("Webhook dispatcher failed to check stale claims: {}", e);
+                }
+                match self.run_due().await {
+                    // A full batch suggests more is due
+                    Ok(n) if n as u64 == BATCH_SIZE => continue,
+                    Ok(_) => {}
+                    Err(e) => warn!("Webhook dispatcher failed to poll queue: {}", e),
+                }
+                let idle = self.until_next_due().await.unwrap_or(MAX_IDLE);
+                tokio::select! {
+                    _ = shutdown.cancelled() => {}
+                    _ = wake().notified() => {}
+                    _ = tokio::time::sleep(idle) => {}
+                }
+            }
+        })
+    }
+
+    /// Deliveries to paused subscriptions stay queued until they resume.
+    fn due() -> sea_orm::Select<WebhookDeliveries> {
+        WebhookDeliveries::find()
+            .inner_join(Webhooks)
+            .filter(webhooks::Column::Active.eq(true))
+            .filter(webhook_deliveries::Column::Status.eq(STATUS_PENDING))
+            .order_by_asc(webhook_deliveries::Column::NextAttemptAt)
+    }
+
+    async fn until_next_due(&self) -> Result<std::time::Duration, sea_orm::DbErr> {
+        let next = Self::due().one(&self.db).await?;
+        Ok(match next {
+            Some(next) => (next.next_attempt_at - Utc::now())
+                .to_std()
+                .unwrap_or_default()
+                .min(MAX_IDLE),
+            None => MAX_IDLE,
+        })
+    }
+
+    /// A row stuck in `sending` may or may not have reached the receiver
+    /// before its instance died. Deliveries are at-least-once, so send it
+    /// again; receivers deduplicate by event id.
+    async fn release_stale(&self) -> Result<(), sea_orm::DbErr> {
+        let released = WebhookDeliveries::update_many()
+            .col_expr(
+                webhook_deliveries::Column::Status,
+                Expr::value(STATUS_PENDING),
+            )
+            .col_expr(
+                webhook_deliveries::Column::ClaimedAt,
+                Expr::value(Option::<chrono::DateTime<Utc>>::None),
+            )
+            .filter(webhook_deliveries::Column::Status.eq(STATUS_SENDING))
+            .filter(webhook_deliveries::Column::ClaimedAt.lt(Utc::now() - STALE_AFTER))
+            .exec(&self.db)
+            .await?
+            .rows_affected;
+        if released > 0 {
+            warn!("Requeued {} interrupted webhook delivery(ies)", released);
+        }
+        Ok(())
+    }
+
+    async fn run_due(&self) -> Result<usize, sea_orm::DbErr> {
+        let due = Self::due()
+            .filter(webhook_deliveries::Column::NextAttemptAt.lte(Utc::now()))
+            .limit(BATCH_SIZE)
+            .all(&self.db)
+            .await?;
+        let count = due.len();
+        if due.is_empty() {
+            return Ok(0);
+        }
+
+        let webhook_ids: Vec<i32> = due.iter().map(|d| d.webhook_id).collect();
+        let webhooks: HashMap<i32, webhooks::Model> = Webhooks::find()
+            .filter(webhooks::Column::Id.is_in(webhook_ids))
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|webhook| (webhook.id, webhook))
+            .collect();
+
+        let mut claimed = Vec::with_capacity(count);
+        for delivery in due {
+            let Some(webhook) = webhooks.get(&delivery.webhook_id) else {
+                // Deleted since the poll; the cascade took the row with it
+                continue;
+            };
+            if self.claim(delivery.id).await? {
+                claimed.push((delivery, webhook));
+            }
+        }
+        futures::stream::iter(claimed)
+            .for_each_concurrent(CONCURRENCY, |(delivery, webhook)| async move {
+                let id = delivery.id;
+                if let Err(e) = self.attempt(delivery, webhook).await {
+                    warn!("Failed to record webhook delivery {}: {}", id, e);
+                }
+            })
+            .await;
+        Ok(count)
+    }
+
+    /// Atomically move a row from pending to sending so that only one
+    /// instance ever sends a given attempt.
+    async fn claim(&self, id: i64) -> Result<bool, sea_orm::DbErr> {
+        let result = WebhookDeliveries::update_many()
+            .col_expr(
+                webhook_deliveries::Column::Status,
+                Expr::value(STATUS_SENDING),
+            )
+            .col_expr(
+                webhook_deliveries::Column::ClaimedAt,
+                Expr::value(Utc::now()),
+            )
+            .filter(webhook_deliveries::Column::Id.eq(id))
+            .filter(webhook_deliveries::Column::Status.eq(STATUS_PENDING))
+            .exec(&self.db)
+            .await?;
+        Ok(result.rows_affected == 1)
+    }
+
+    async fn attempt(
+        &self,
+        delivery: webhook_deliveries::Model,
+        webhook: &webhooks::Model,
+    ) -> Result<(), sea_orm::DbErr> {
+        let timestamp = Utc::now().timestamp();
+        let signature = sign(&webhook.secret, timestamp, &delivery.payload);
+        let result = self
+            .client
+            .post(&webhook.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, &delivery.event_type)
+            .header(DELIVERY_HEADER, delivery.id.to_string())
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, format!("sha256={}", signature))
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+
+        let (response_status, error) = match result {
+            Ok(response) if response.status().is_success() => {
+                (Some(response.status().as_u16() as i32), None)
+            }
+            Ok(response) => {
+                let status = response.status();
+                let body = response_excerpt(response).await;
+                let error = if body.is_empty() {
+                    format!("HTTP {}", status)
+                } else {
+                    format!("HTTP {}: {}", status, body)
+                };
+                (Some(status.as_u16() as i32), Some(error))
+            }
+            Err(e) => (None, Some(e.to_string())),
+        };
+        self.record(delivery, webhook, response_status, error).await
+    }
+
+    async fn record(
+        &self,
+        delivery: webhook_deliveries::Model,
+        webhook: &webhooks::Model,
+        response_status: Option<i32>,
+        error: Option<String>,
+    ) -> Result<(), sea_orm::DbErr> {
+        let now = Utc::now();
+        let attempts = delivery.attempts + 1;
+        let mut update = webhook_deliveries::ActiveModel {
+            attempts: Set(attempts),
+            claimed_at: Set(None),
+            last_attempt_at: Set(Some(now)),
+            response_status: Set(response_status),
+            last_error: Set(error.clone()),
+            ..Default::default()
+        };
+        let outcome = match &error {
+            None => {
+                update.status = Set(STATUS_DELIVERED.to_string());
+                update.delivered_at = Set(Some(now));
+                info!(
+                    "📬 Delivered {} webhook {} to {}",
+                    delivery.event_type, delivery.id, webhook.url
+                );
+                "delivered"
+            }
+            Some(error) if attempts >= self.max_attempts => {
+                update.status = Set(STATUS_FAILED.to_string());
+                warn!(
+                    "❌ Webhook delivery {} to {} failed after {} attempt(s): {}",
+                    delivery.id, webhook.url, attempts, error
+                );
+                "failed"
+            }
+            Some(error) => {
+                let delay = retry_delay(attempts);
+                update.status = Set(STATUS_PENDING.to_string());
+                update.next_attempt_at = Set(now + delay);
+                warn!(
+                    "⚠️ Webhook delivery {} to {} failed, retrying in {}s: {}",
+                    delivery.id,
+                    webhook.url,
+                    delay.num_seconds(),
+                    error
+                );
+                "retrying"
+            }
+        };
+        metrics()
+            .webhook_deliveries
+            .with_label_values(&[outcome])
+            .inc();
+
+        // Only while still ours; a redelivery or delete may have raced us
+        WebhookDeliveries::update_many()
+            .set(update)
+            .filter(webhook_deliveries::Column::Id.eq(delivery.id))
+            .filter(webhook_deliveries::Column::Status.eq(STATUS_SENDING))
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Drop finished deliveries older than `WEBHOOK_DELIVERY_RETENTION_HOURS`.
+    async fn prune(&self) -> Result<u64, sea_orm::DbErr> {
+        let cutoff = Utc::now() - self.retention;
+        let deleted = WebhookDeliveries::delete_many()
+            .filter(webhook_deliveries::Column::Status.is_in([STATUS_DELIVERED, STATUS_FAILED]))
+            .filter(webhook_deliveries::Column::CreatedAt.lt(cutoff))
+            .exec(&self.db)
+            .await?
+            .rows_affected;
+        if deleted > 0 {
+            info!("🧹 Pruned {} webhook delivery(ies)", deleted);
+        }
+        Ok(deleted)
+    }
+}
+
+/// `FIRST_RETRY` after the first failure, doubling up to `MAX_RETRY`.
+fn retry_delay(attempts: i32) -> Duration {
+    let doublings = (attempts - 1).clamp(0, 16) as u32;
+    (FIRST_RETRY * 2_i32.pow(doublings)).min(MAX_RETRY)
+}
+
+/// The start of a failed response's body, for the delivery log.
+async fn response_excerpt(mut response: reqwest::Response) -> String {
+    let mut body = Vec::new();
+    while body.len() < MAX_ERROR_LEN {
+        match response.chunk().await {
+            Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+            _ => break,
+        }
+    }
+    body.truncate(MAX_ERROR_LEN);
+    String::from_utf8_lossy(&body).trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_back_off_exponentially_up_to_the_cap() {
+        assert_eq!(retry_delay(1), FIRST_RETRY);
+        assert_eq!(retry_delay(2), FIRST_RETRY * 2);
+        assert_eq!(retry_delay(3), FIRST_RETRY * 4);
+        assert_eq!(retry_delay(7), Duration::minutes(32));
+        assert_eq!(retry_delay(8), MAX_RETRY);
+        assert_eq!(retry_delay(1000), MAX_RETRY);
+        // Never shorter than the first retry, whatever is stored
+        assert_eq!(retry_delay(0), FIRST_RETRY);
+        assert_eq!(retry_delay(-5), FIRST_RETRY);
+    }
+}
@@ -0,0 +1,344 @@
+use crate::audit::{AuditEvent, ClientIp, WEBHOOK_CREATED, WEBHOOK_DELETED, WEBHOOK_UPDATED};
+use crate::auth::Admin;
+use crate::entity::{webhook_deliveries, webhooks, WebhookDeliveries, Webhooks};
+use crate::error::AppError;
+use crate::webhooks::service::{generate_secret, wake};
+use crate::webhooks::types::{
+    CreateWebhookRequest, DeliveryLogParams, DeliveryView, NewWebhook, UpdateWebhookRequest,
+    WebhookView,
+};
+use crate::webhooks::{
+    WebhookEvent, ALL_EVENTS, EVENT_TYPES, PING, STATUS_DELIVERED, STATUS_FAILED, STATUS_PENDING,
+    STATUS_SENDING,
+};
+use axum::{
+    extract::{FromRef, Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::Utc;
+use reqwest::Url;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+};
+use serde_json::json;
+use tracing::info;
+
+/// Most subscriptions the server holds at once.
+const MAX_WEBHOOKS: u64 = 50;
+const DEFAULT_LOG_LIMIT: u64 = 50;
+const MAX_LOG_LIMIT: u64 = 200;
+
+#[derive(Clone)]
+pub struct WebhooksState {
+    pub db: DatabaseConnection,
+}
+
+impl FromRef<WebhooksState> for DatabaseConnection {
+    fn from_ref(state: &WebhooksState) -> Self {
+        state.db.clone()
+    }
+}
+
+fn validate_url(raw: &str) -> Result<String, AppError> {
+    let raw = raw.trim();
+    match Url::parse(raw) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => {
+            Ok(raw.to_string())
+        }
+        _ => Err(AppError::bad_request(format!(
+            "'{}' is not an http(s) URL",
+            raw
+        ))),
+    }
+}
+
+/// Checked against `EVENT_TYPES` and stored space-separated.
+fn validate_events(events: Vec<String>) -> Result<String, AppError> {
+    let mut events: Vec<String> = events.into_iter().map(|e| e.trim().to_string()).collect();
+    events.sort();
+    events.dedup();
+    if events.is_empty() {
+        return Err(AppError::bad_request("Subscribe to at least one event"));
+    }
+    if let Some(unknown) = events
+        .iter()
+        .find(|e| *e != ALL_EVENTS && !EVENT_TYPES.contains(&e.as_str()))
+    {
+        return Err(AppError::bad_request(format!(
+            "Unknown event '{}', expected one of {} or '{}'",
+            unknown,
+            EVENT_TYPES.join(", "),
+            ALL_EVENTS
+        )));
+    }
+    if events.iter().any(|e| e == ALL_EVENTS) {
+        return Ok(ALL_EVENTS.to_string());
+    }
+    Ok(events.join(" "))
+}
+
+async fn find_webhook(db: &DatabaseConnection, id: i32) -> Result<webhooks::Model, AppError> {
+    Webhooks::find_by_id(id)
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::not_found("Webhook"))
+}
+
+async fn find_delivery(
+    db: &DatabaseConnection,
+    webhook_id: i32,
+    id: i64,
+) -> Result<webhook_deliveries::Model, AppError> {
+    WebhookDeliveries::find_by_id(id)
+        .filter(webhook_deliveries::Column::WebhookId.eq(webhook_id))
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::not_found("Delivery"))
+}
+
+/// `GET /admin/webhooks`
+pub async fn list_webhooks(
+    State(state): State<WebhooksState>,
+    Admin(_): Admin,
+) -> Result<Json<Vec<WebhookView>>, AppError> {
+    let webhooks = Webhooks::find()
+        .order_by_asc(webhooks::Column::Id)
+        .all(&state.db)
+        .await?;
+    Ok(Json(webhooks.into_iter().map(Into::into).collect()))
+}
+
+/// `POST /admin/webhooks` — subscribe a URL. The signing secret is only
+/// returned here.
+pub async fn create_webhook(
+    State(state): State<WebhooksState>,
+    Admin(admin): Admin,
+    ClientIp(ip): ClientIp,
+    Json(payload): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<NewWebhook>), AppError> {
+    let url = validate_url(&payload.url)?;
+    let events = validate_events(payload.events)?;
+    if Webhooks::find().count(&state.db).await? >= MAX_WEBHOOKS {
+        return Err(AppError::Conflict(format!(
+            "At most {} webhooks",
+            MAX_WEBHOOKS
+        )));
+    }
+
+    let secret = generate_secret();
+    let now = Utc::now();
+    let webhook = webhooks::ActiveModel {
+        url: Set(url),
+        secret: Set(secret.clone()),
+        events: Set(events),
+        description: Set(payload.description.filter(|d| !d.trim().is_empty())),
+        active: Set(true),
+        created_by: Set(Some(admin.id)),
+        created_at: Set(now),
+        updated_at: Set(now),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await?;
+
+    AuditEvent::new(WEBHOOK_CREATED)
+        .actor(admin.id)
+        .ip(ip)
+        .detail("webhook_id", webhook.id)
+        .detail("url", webhook.url.clone())
+        .detail("events", webhook.events.clone())
+        .log(&state.db)
+        .await;
+    info!(
+        "🪝 {} subscribed {} to webhooks",
+        admin.username, webhook.url
+    );
+    Ok((
+        StatusCode::CREATED,
+        Json(NewWebhook {
+            view: webhook.into(),
+            secret,
+        }),
+    ))
+}
+
+/// `GET /admin/webhooks/:id`
+pub async fn get_webhook(
+    State(state): State<WebhooksState>,
+    Admin(_): Admin,
+    Path(id): Path<i32>,
+) -> Result<Json<WebhookView>, AppError> {
+    Ok(Json(find_webhook(&state.db, id).await?.into()))
+}
+
+/// `PATCH /admin/webhooks/:id` — change the URL or filter, or pause
+/// deliveries with `"active": false`. Paused deliveries stay queued.
+pub async fn update_webhook(
+    State(state): State<WebhooksState>,
+    Admin(admin): Admin,
+    ClientIp(ip): ClientIp,
+    Path(id): Path<i32>,
+    Json(payload): Json<UpdateWebhookRequest>,
+) -> Result<Json<WebhookView>, AppError> {
+    let webhook = find_webhook(&state.db, id).await?;
+    let mut update: webhooks::ActiveModel = webhook.into();
+    if let Some(url) = payload.url {
+        update.url = Set(validate_url(&url)?);
+    }
+    if let Some(events) = payload.events {
+        update.events = Set(validate_events(events)?);
+    }
+    if let Some(description) = payload.description {
+        update.description = Set(Some(description).filter(|d| !d.trim().is_empty()));
+    }
+    if let Some(active) = payload.active {
+        update.active = Set(active);
+    }
+    update.updated_at = Set(Utc::now());
+    let webhook = update.update(&state.db).await?;
+    if webhook.active {
+        // Anything queued while paused is due now
+        wake().notify_one();
+    }
+
+    AuditEvent::new(WEBHOOK_UPDATED)
+        .actor(admin.id)
+        .ip(ip)
+        .detail("webhook_id", webhook.id)
+        .detail("url", webhook.url.clone())
+        .detail("events", webhook.events.clone())
+        .detail("active", webhook.active)
+        .log(&state.db)
+        .await;
+    Ok(Json(webhook.into()))
+}
+
+/// `DELETE /admin/webhooks/:id` — unsubscribe, dropping the delivery log
+/// and anything still queued.
+pub async fn delete_webhook(
+    State(state): State<WebhooksState>,
+    Admin(admin): Admin,
+    ClientIp(ip): ClientIp,
+    Path(id): Path<i32>,
+) -> Result<StatusCode, AppError> {
+    let webhook = find_webhook(&state.db, id).await?;
+    Webhooks::delete_by_id(webhook.id).exec(&state.db).await?;
+
+    AuditEvent::new(WEBHOOK_DELETED)
+        .actor(admin.id)
+        .ip(ip)
+        .detail("webhook_id", webhook.id)
+        .detail("url", webhook.url.clone())
+        .log(&state.db)
+        .await;
+    info!("🪝 {} removed webhook {}", admin.username, webhook.url);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `POST /admin/webhooks/:id/ping` — queue a `ping` event to this
+/// subscription alone, to check the receiver and its signature handling.
+pub async fn ping_webhook(
+    State(state): State<WebhooksState>,
+    Admin(admin): Admin,
+    Path(id): Path<i32>,
+) -> Result<(StatusCode, Json<DeliveryView>), AppError> {
+    let webhook = find_webhook(&state.db, id).await?;
+    let delivery = WebhookEvent::new(
+        PING,
+        json!({ "webhook_id": webhook.id, "sent_by": admin.username }),
+    )
+    .enqueue_to(&state.db, webhook.id)
+    .await?;
+    Ok((StatusCode::ACCEPTED, Json(delivery.into())))
+}
+
+/// `GET /admin/webhooks/:id/deliveries` — the delivery log, newest first.
+pub async fn list_deliveries(
+    State(state): State<WebhooksState>,
+    Admin(_): Admin,
+    Path(id): Path<i32>,
+    Query(params): Query<DeliveryLogParams>,
+) -> Result<Json<Vec<DeliveryView>>, AppError> {
+    let webhook = find_webhook(&state.db, id).await?;
+    let mut query =
+        WebhookDeliveries::find().filter(webhook_deliveries::Column::WebhookId.eq(webhook.id));
+    if let Some(status) = params.status {
+        if ![
+            STATUS_PENDING,
+            STATUS_SENDING,
+            STATUS_DELIVERED,
+            STATUS_FAILED,
+        ]
+        .contains(&status.as_str())
+        {
+            return Err(AppError::bad_request(format!(
+                "Unknown status '{}'",
+                status
+            )));
+        }
+        query = query.filter(webhook_deliveries::Column::Status.eq(status));
+    }
+    if let Some(event_type) = params.event_type {
+        query = query.filter(webhook_deliveries::Column::EventType.eq(event_type));
+    }
+    if let Some(before) = params.before {
+        query = query.filter(webhook_deliveries::Column::Id.lt(before));
+    }
+    let deliveries = query
+        .order_by_desc(webhook_deliveries::Column::Id)
+        .limit(
+            params
+                .limit
+                .unwrap_or(DEFAULT_LOG_LIMIT)
+                .clamp(1, MAX_LOG_LIMIT),
+        )
+        .all(&state.db)
+        .await?;
+    Ok(Json(deliveries.into_iter().map(Into::into).collect()))
+}
+
+/// `GET /admin/webhooks/:id/deliveries/:delivery_id` — one delivery,
+/// including the body that was sent.
+pub async fn get_delivery(
+    State(state): State<WebhooksState>,
+    Admin(_): Admin,
+    Path((id, delivery_id)): Path<(i32, i64)>,
+) -> Result<Json<DeliveryView>, AppError> {
+    let delivery = find_delivery(&state.db, id, delivery_id).await?;
+    Ok(Json(DeliveryView::with_payload(delivery)))
+}
+
+/// `POST /admin/webhooks/:id/deliveries/:delivery_id/redeliver` — send the
+/// same event again now, with a fresh set of attempts.
+pub async fn redeliver(
+    State(state): State<WebhooksState>,
+    Admin(_): Admin,
+    Path((id, delivery_id)): Path<(i32, i64)>,
+) -> Result<(StatusCode, Json<DeliveryView>), AppError> {
+    let delivery = find_delivery(&state.db, id, delivery_id).await?;
+    let result = WebhookDeliveries::update_many()
+        .col_expr(
+            webhook_deliveries::Column::Status,
+            Expr::value(STATUS_PENDING),
+        )
+        .col_expr(webhook_deliveries::Column::Attempts, Expr::value(0))
+        .col_expr(
+            webhook_deliveries::Column::NextAttemptAt,
+            Expr::value(Utc::now()),
+        )
+        .filter(webhook_deliveries::Column::Id.eq(delivery.id))
+        .filter(webhook_deliveries::Column::Status.ne(STATUS_SENDING))
+        .exec(&state.db)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(AppError::Conflict(
+            "Delivery is being sent right now".to_string(),
+        ));
+    }
+    wake().notify_one();
+
+    let delivery = find_delivery(&state.db, id, delivery_id).await?;
+    Ok((StatusCode::ACCEPTED, Json(delivery.into())))
+}
@@ -0,0 +1,47 @@
+//! Outbound webhooks. Admins subscribe a URL to event types; every matching
+//! event is queued in `webhook_deliveries`, one row per subscription, and the
+//! dispatcher POSTs it with an HMAC signature, retrying with exponential
+//! backoff until the receiver answers `2xx` or the attempts run out.
+
+pub mod dispatcher;
+pub mod handlers;
+pub mod routes;
+pub mod service;
+pub mod types;
+
+pub use dispatcher::WebhookDispatcher;
+pub use service::WebhookEvent;
+
+// Event types a subscription can filter on.
+pub const MESSAGE_CREATED: &str = "message.created";
+/// Reserved: messages can't be edited yet, so nothing emits it
+pub const MESSAGE_EDITED: &str = "message.edited";
+pub const MESSAGE_DELETED: &str = "message.deleted";
+pub const USER_JOINED: &str = "user.joined";
+pub const RECEIPT_DELIVERED: &str = "receipt.delivered";
+pub const RECEIPT_READ: &str = "receipt.read";
+
+pub const EVENT_TYPES: &[&str] = &[
+    MESSAGE_CREATED,
+    MESSAGE_EDITED,
+    MESSAGE_DELETED,
+    USER_JOINED,
+    RECEIPT_DELIVERED,
+    RECEIPT_READ,
+];
+
+/// Subscribes to every event type, including ones added later.
+pub const ALL_EVENTS: &str = "*";
+
+/// Sent by `POST /admin/webhooks/:id/ping` only; not subscribable.
+pub const PING: &str = "ping";
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_SENDING: &str = "sending";
+pub const STATUS_DELIVERED: &str = "delivered";
+pub const STATUS_FAILED: &str = "failed";
+
+pub const EVENT_HEADER: &str = "x-whisper-event";
+pub const DELIVERY_HEADER: &str = "x-whisper-delivery";
+pub const TIMESTAMP_HEADER: &str = "x-whisper-timestamp";
+pub const SIGNATURE_HEADER: &str = "x-whisper-signature";
@@ -0,0 +1,31 @@
+use axum::{
+    routing::{get, post},
+    Router,
+};
+
+use crate::webhooks::handlers::{
+    create_webhook, delete_webhook, get_delivery, get_webhook, list_deliveries, list_webhooks,
+    ping_webhook, redeliver, update_webhook, WebhooksState,
+};
+
+pub fn configure_webhook_routes(state: WebhooksState) -> Router {
+    Router::new()
+        .route("/admin/webhooks", get(list_webhooks).post(create_webhook))
+        .route(
+            "/admin/webhooks/:id",
+            get(get_webhook)
+                .patch(update_webhook)
+                .delete(delete_webhook),
+        )
+        .route("/admin/webhooks/:id/ping", post(ping_webhook))
+        .route("/admin/webhooks/:id/deliveries", get(list_deliveries))
+        .route(
+            "/admin/webhooks/:id/deliveries/:delivery_id",
+            get(get_delivery),
+        )
+        .route(
+            "/admin/webhooks/:id/deliveries/:delivery_id/redeliver",
+            post(redeliver),
+        )
+        .with_state(state)
+}
@@ -0,0 +1,168 @@
+use crate::entity::{webhook_deliveries, webhooks, WebhookDeliveries, Webhooks};
+use crate::webhooks::{ALL_EVENTS, STATUS_PENDING};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, Set};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::sync::OnceLock;
+use tokio::sync::Notify;
+use tracing::error;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Wakes this instance's dispatcher when a delivery is queued. Other
+/// instances find it on their next poll.
+pub fn wake() -> &'static Notify {
+    static WAKE: OnceLock<Notify> = OnceLock::new();
+    WAKE.get_or_init(Notify::new)
+}
+
+/// Hex HMAC-SHA256 of `<timestamp>.<body>`, sent as `sha256=<hex>`.
+/// Covering the timestamp lets receivers reject replayed deliveries.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// A fresh signing secret.
+pub fn generate_secret() -> String {
+    format!(
+        "whsec_{}{}",
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+/// Parse the stored, space-separated filter.
+pub fn event_filter(stored: &str) -> Vec<String> {
+    stored.split_whitespace().map(str::to_string).collect()
+}
+
+pub fn subscribes_to(webhook: &webhooks::Model, event_type: &str) -> bool {
+    webhook
+        .events
+        .split_whitespace()
+        .any(|event| event == ALL_EVENTS || event == event_type)
+}
+
+/// One event on its way to every subscription that wants it.
+pub struct WebhookEvent {
+    id: Uuid,
+    event_type: &'static str,
+    data: Value,
+}
+
+impl WebhookEvent {
+    pub fn new(event_type: &'static str, data: impl Serialize) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            event_type,
+            data: serde_json::to_value(data).expect("webhook payload serializes"),
+        }
+    }
+
+    /// The body POSTed for this event.
+    fn payload(&self) -> String {
+        json!({
+            "id": self.id.to_string(),
+            "type": self.event_type,
+            "created_at": Utc::now(),
+            "data": self.data,
+        })
+        .to_string()
+    }
+
+    fn delivery(&self, webhook_id: i32, payload: &str) -> webhook_deliveries::ActiveModel {
+        let now = Utc::now();
+        webhook_deliveries::ActiveModel {
+            webhook_id: Set(webhook_id),
+            event_id: Set(self.id.to_string()),
+            event_type: Set(self.event_type.to_string()),
+            payload: Set(payload.to_string()),
+            status: Set(STATUS_PENDING.to_string()),
+            attempts: Set(0),
+            next_attempt_at: Set(now),
+            created_at: Set(now),
+            ..Default::default()
+        }
+    }
+
+    /// Queue a delivery for every active subscription to this event type.
+    pub async fn enqueue(self, db: &DatabaseConnection) -> Result<usize, DbErr> {
+        let subscribers: Vec<webhooks::Model> = Webhooks::find()
+            .filter(webhooks::Column::Active.eq(true))
+            .all(db)
+            .await?
+            .into_iter()
+            .filter(|webhook| subscribes_to(webhook, self.event_type))
+            .collect();
+        if subscribers.is_empty() {
+            return Ok(0);
+        }
+
+        let payload = self.payload();
+        WebhookDeliveries::insert_many(
+            subscribers
+                .iter()
+                .map(|webhook| self.delivery(webhook.id, &payload)),
+        )
+        .exec(db)
+        .await?;
+        wake().notify_one();
+        Ok(subscribers.len())
+    }
+
+    /// Queue a delivery to one subscription, whatever its filter says.
+    pub async fn enqueue_to(
+        self,
+        db: &DatabaseConnection,
+        webhook_id: i32,
+    ) -> Result<webhook_deliveries::Model, DbErr> {
+        let delivery = WebhookDeliveries::insert(self.delivery(webhook_id, &self.payload()))
+            .exec_with_returning(db)
+            .await?;
+        wake().notify_one();
+        Ok(delivery)
+    }
+
+    /// Like `enqueue`, but failures are logged rather than returned: a
+    /// webhook must never fail the action that caused it.
+    pub async fn emit(self, db: &DatabaseConnection) {
+        let event_type = self.event_type;
+        if let Err(e) = self.enqueue(db).await {
+            error!("❌ Failed to queue {} webhook: {}", event_type, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_covers_timestamp_and_body() {
+        let signature = sign("whsec_test", 1_700_000_000, r#"{"type":"ping"}"#);
+        // echo -n '1700000000.{"type":"ping"}' | openssl dgst -sha256 -hmac whsec_test
+        assert_eq!(
+            signature,
+            "bc08c591847b765241711bcbe7067e3869a219e424d3fdd9d00b3b6f915baf97"
+        );
+        assert_ne!(
+            signature,
+            sign("whsec_test", 1_700_000_001, r#"{"type":"ping"}"#)
+        );
+        assert_ne!(
+            signature,
+            sign("whsec_test", 1_700_000_000, r#"{"type":"pong"}"#)
+        );
+        assert_ne!(
+            signature,
+            sign("whsec_other", 1_700_000_000, r#"{"type":"ping"}"#)
+        );
+    }
+}
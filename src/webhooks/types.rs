@@ -0,0 +1,171 @@
+use crate::entity::{messages, webhook_deliveries, webhooks};
+use crate::webhooks::service::event_filter;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    /// Event types, or `["*"]` for all of them
+    pub events: Vec<String>,
+    pub description: Option<String>,
+}
+
+/// Fields left out are unchanged.
+#[derive(Deserialize)]
+pub struct UpdateWebhookRequest {
+    pub url: Option<String>,
+    pub events: Option<Vec<String>>,
+    pub description: Option<String>,
+    pub active: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct WebhookView {
+    pub id: i32,
+    pub url: String,
+    pub events: Vec<String>,
+    pub description: Option<String>,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<webhooks::Model> for WebhookView {
+    fn from(webhook: webhooks::Model) -> Self {
+        Self {
+            events: event_filter(&webhook.events),
+            id: webhook.id,
+            url: webhook.url,
+            description: webhook.description,
+            active: webhook.active,
+            created_at: webhook.created_at,
+            updated_at: webhook.updated_at,
+        }
+    }
+}
+
+/// Returned once, on creation; the secret can't be read back later.
+#[derive(Serialize)]
+pub struct NewWebhook {
+    #[serde(flatten)]
+    pub view: WebhookView,
+    pub secret: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeliveryLogParams {
+    /// `pending`, `sending`, `delivered` or `failed`
+    pub status: Option<String>,
+    pub event_type: Option<String>,
+    /// Only deliveries with a smaller id, for paging back
+    pub before: Option<i64>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct DeliveryView {
+    pub id: i64,
+    pub event_id: String,
+    pub event_type: String,
+    pub status: String,
+    pub attempts: i32,
+    /// When the next attempt is due, while the delivery is pending
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    /// The body as sent; only on single-delivery lookups
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+}
+
+impl DeliveryView {
+    pub fn with_payload(delivery: webhook_deliveries::Model) -> Self {
+        let payload = serde_json::from_str(&delivery.payload).ok();
+        Self {
+            payload,
+            ..delivery.into()
+        }
+    }
+}
+
+impl From<webhook_deliveries::Model> for DeliveryView {
+    fn from(delivery: webhook_deliveries::Model) -> Self {
+        let pending = delivery.status == crate::webhooks::STATUS_PENDING;
+        Self {
+            id: delivery.id,
+            event_id: delivery.event_id,
+            event_type: delivery.event_type,
+            status: delivery.status,
+            attempts: delivery.attempts,
+            next_attempt_at: pending.then_some(delivery.next_attempt_at),
+            last_attempt_at: delivery.last_attempt_at,
+            response_status: delivery.response_status,
+            last_error: delivery.last_error,
+            created_at: delivery.created_at,
+            delivered_at: delivery.delivered_at,
+            payload: None,
+        }
+    }
+}
+
+// Event payloads, sent as the `data` of each delivery.
+
+/// `message.created`
+#[derive(Serialize)]
+pub struct MessageData<'a> {
+    pub id: i32,
+    pub from: &'a str,
+    pub to: &'a str,
+    pub text: &'a str,
+    /// Files sent with the message, empty for plain text
+    pub attachment_ids: Vec<i32>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl<'a> MessageData<'a> {
+    pub fn new(message: &'a messages::Model, from: &'a str, to: &'a str) -> Self {
+        Self {
+            id: message.id,
+            from,
+            to,
+            text: &message.message,
+            attachment_ids: Vec::new(),
+            created_at: message.created_at,
+            expires_at: message.expires_at,
+        }
+    }
+}
+
+/// `message.deleted`
+#[derive(Serialize)]
+pub struct MessagesDeletedData {
+    pub ids: Vec<i32>,
+    /// `removed` by a moderator, `expired` under a retention rule, or
+    /// `account_deleted` with the sender's account
+    pub reason: &'static str,
+}
+
+/// `user.joined`
+#[derive(Serialize)]
+pub struct UserJoinedData<'a> {
+    pub username: &'a str,
+    pub is_bot: bool,
+    /// Who created the bot, for bots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bot_owner: Option<&'a str>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// `receipt.delivered` and `receipt.read`: `count` messages from `from` to
+/// `to` changed status.
+#[derive(Serialize)]
+pub struct ReceiptData<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub count: u64,
+}
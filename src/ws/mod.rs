@@ -0,0 +1,1347 @@
+use crate::attachments::types::MessageFrame;
+use crate::audit::{AuditEvent, ClientIp, WS_AUTH_FAILED, WS_CONNECTED, WS_DISCONNECTED};
+use crate::auth::api_keys::{authenticate_api_key, is_api_key};
+use crate::auth::{verify_firebase_token, Scope, Scopes};
+use crate::blocks::{blocked_either_way, is_blocked, BLOCKED_STATUS};
+use crate::config::{FirebaseConfig, WebSocketConfig};
+use crate::entity::{attachments, messages, users, Attachments, Messages, Users};
+use crate::error::AppError;
+use crate::messages::types::ReceiptFrame;
+use crate::messages::{DELIVERED_STATUS, READ_STATUS, UNREAD_STATUS};
+use crate::metrics::{metrics, outcome};
+use crate::moderation::REMOVED_STATUS;
+use crate::retention::{not_expired, RetentionPolicy};
+use crate::scheduled::service::schedule_message;
+use crate::scheduled::types::{ScheduleRequest, ScheduledFrame, ScheduledView};
+use crate::scheduled::MessageScheduler;
+use crate::shutdown::Shutdown;
+use crate::sync::types::{EventFrame, ResyncFrame, SyncFrame};
+use crate::sync::{current_seq, events_since, Resume, SyncEvent};
+use crate::webhooks::types::{MessageData, ReceiptData};
+use crate::webhooks::{WebhookEvent, MESSAGE_CREATED, RECEIPT_DELIVERED, RECEIPT_READ};
+use axum::{
+    extract::ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+    extract::{FromRef, Query, State},
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use futures::{Sink, SinkExt, StreamExt};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Set,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::{Mutex, Notify};
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, field, info, info_span, warn, Instrument, Span};
+use uuid::Uuid;
+
+mod queue;
+mod sse;
+
+use queue::Next;
+pub use queue::{Delivery, Outbound, OutboundQueue};
+
+/// A user's live socket on this instance.
+#[derive(Clone)]
+pub struct Connection {
+    pub id: Uuid,
+    pub queue: OutboundQueue,
+    /// Cancelled to force the socket closed, e.g. when the account is banned
+    pub cancel: CancellationToken,
+}
+
+pub type SharedState = Arc<Mutex<HashMap<String, Connection>>>;
+
+/// Everything a socket needs beyond the connection itself.
+#[derive(Clone)]
+pub struct WsState {
+    pub db: DatabaseConnection,
+    pub online: SharedState,
+    pub retention: RetentionPolicy,
+    /// `None` when scheduled messages are switched off
+    pub scheduler: Option<MessageScheduler>,
+    pub firebase: FirebaseConfig,
+    pub shutdown: Shutdown,
+    /// Heartbeat and idle settings
+    pub config: WebSocketConfig,
+}
+
+impl FromRef<WsState> for DatabaseConnection {
+    fn from_ref(state: &WsState) -> Self {
+        state.db.clone()
+    }
+}
+
+/// Sent just before the close frame when the server drains for a restart.
+#[derive(Serialize)]
+struct RestartingFrame {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    retry_after_secs: u64,
+}
+
+/// Reconnect delay for a socket closed by a restart, spread over 2..12s by
+/// connection id so clients don't all come back at once.
+fn reconnect_after_secs(connection_id: Uuid) -> u64 {
+    2 + (connection_id.as_u128() % 10) as u64
+}
+
+/// JSON frames a client may send instead of the plain `recipient: text` form.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientFrame {
+    Schedule(ScheduleRequest),
+    /// Keepalive for clients behind proxies that drop ping/pong control
+    /// frames; answered with a `heartbeat_ack`
+    Heartbeat,
+}
+
+const HEARTBEAT_ACK: &str = r#"{"type":"heartbeat_ack"}"#;
+
+/// Why the receive loop of a socket ended.
+#[derive(Clone, Copy)]
+enum CloseReason {
+    /// The client closed the socket or the stream ended
+    Client,
+    /// Too many pings went unanswered
+    HeartbeatTimeout,
+    IdleTimeout,
+    /// Fell so far behind that a reliable frame didn't fit its queue
+    QueueOverflow,
+    /// Closed through `disconnect_user`, e.g. on a ban
+    Kicked,
+    Shutdown,
+}
+
+impl CloseReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Client => "client",
+            Self::HeartbeatTimeout => "heartbeat_timeout",
+            Self::IdleTimeout => "idle_timeout",
+            Self::QueueOverflow => "queue_overflow",
+            Self::Kicked => "kicked",
+            Self::Shutdown => "shutdown",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WsParams {
+    token: String,
+    /// Resume from this event sequence number; `0` for a fresh session.
+    /// Leaving it out keeps the legacy protocol: full history, no cursors.
+    since: Option<i64>,
+}
+
+pub async fn web_socket_handler(
+    ws: WebSocketUpgrade,
+    ClientIp(ip): ClientIp,
+    Query(WsParams { token, since }): Query<WsParams>,
+    State(ws_state): State<WsState>,
+) -> impl IntoResponse {
+    // Draining for a restart; the client should try another instance
+    if ws_state.shutdown.is_draining() {
+        return AppError::ShuttingDown.into_response();
+    }
+    match authenticate(&ws_state, &token, "ws").await {
+        Ok((uid, scopes)) => {
+            let shutdown = ws_state.shutdown.clone();
+            let connection_id = Uuid::new_v4();
+            let span = info_span!(
+                "ws_connection",
+                %connection_id,
+                username = %uid,
+                user_id = field::Empty,
+            );
+
+            // Tracked so shutdown waits for the socket's cleanup writes
+            ws.on_upgrade(move |socket| {
+                shutdown.track(
+                    handle_socket(socket, ws_state, uid, scopes, ip, connection_id, since)
+                        .instrument(span),
+                )
+            })
+        }
+        Err(e) => {
+            if matches!(e, AppError::Unauthorized(_)) {
+                AuditEvent::new(WS_AUTH_FAILED)
+                    .ip(ip)
+                    .log(&ws_state.db)
+                    .await;
+            }
+            e.into_response()
+        }
+    }
+}
+
+/// Check the token a socket or event stream connects with: a Firebase ID
+/// token, or a bot's API key with `messages:read`. Returns the username to
+/// connect as and what the connection may do.
+async fn authenticate(
+    ws_state: &WsState,
+    token: &str,
+    transport: &'static str,
+) -> Result<(String, Scopes), AppError> {
+    if is_api_key(token) {
+        let (bot, scopes) = authenticate_api_key(&ws_state.db, token).await?;
+        if !scopes.contains(Scope::MessagesRead) {
+            return Err(AppError::Forbidden(
+                "API key lacks the messages:read scope".to_string(),
+            ));
+        }
+        return Ok((bot.username, scopes));
+    }
+    let verified = verify_firebase_token(token, &ws_state.firebase.project_id).await;
+    metrics()
+        .auth_verifications
+        .with_label_values(&[transport, outcome(verified.is_ok())])
+        .inc();
+    verified
+        .map(|claims| (claims.sub, Scopes::all()))
+        .map_err(|_| AppError::unauthorized("Invalid or expired token"))
+}
+
+pub async fn handle_socket(
+    socket: WebSocket,
+    ws_state: WsState,
+    uid: String,
+    scopes: Scopes,
+    ip: Option<String>,
+    connection_id: Uuid,
+    since: Option<i64>,
+) {
+    info!("WebSocket connection");
+
+    // Split the socket into a sender and receiver
+    let (mut sender, mut receiver) = socket.split();
+
+    let user = match load_user(&ws_state.db, &uid).await {
+        Ok(user) => user,
+        Err(e) => {
+            let _ = sender.send(Message::Text(e.to_frame())).await;
+            return;
+        }
+    };
+    Span::current().record("user_id", user.id);
+    let connection = register(&ws_state, &user, ip.clone(), connection_id, "ws").await;
+    let WsState {
+        db,
+        online: state,
+        retention,
+        scheduler,
+        shutdown,
+        config,
+        ..
+    } = ws_state.clone();
+    let username = user.username.clone();
+    let queue = connection.queue.clone();
+    let cancel = connection.cancel.clone();
+
+    // Catch the client up. Registered first, so anything sent meanwhile
+    // waits in the queue; in resume mode the sender task then skips what
+    // the catch-up already covered.
+    let resumed_through = match since {
+        None => {
+            if let Err(e) = send_message_history(&mut sender, &user, &db, &state).await {
+                error!("❌ Failed to send message history to {}: {}", username, e);
+                let _ = sender.send(Message::Text(e.to_frame())).await;
+            }
+            None
+        }
+        Some(since) => match resume_session(&mut sender, &user, &db, &state, since).await {
+            Ok(cursor) => Some(cursor),
+            Err(e) => {
+                error!("❌ Failed to resume session for {}: {}", username, e);
+                let _ = sender.send(Message::Text(e.to_frame())).await;
+                Some(0)
+            }
+        },
+    };
+
+    // Spawn a task to drain the outbound queue into the WebSocket
+    let sender_queue = queue.clone();
+    let user_clone = username.clone();
+    let sender_cancel = cancel.clone();
+    let sender_shutdown = shutdown.clone();
+    let retry_after_secs = reconnect_after_secs(connection.id);
+    // The receive loop decides when to ping; the sender task owns the sink
+    let ping = Arc::new(Notify::new());
+    let sender_ping = ping.clone();
+    let mut sender_handle = tokio::spawn(
+        async move {
+            loop {
+                tokio::select! {
+                    // Flush queued frames (e.g. the disconnect reason) before closing
+                    biased;
+                    next = sender_queue.next() => {
+                        let msg = match next {
+                            Next::Frame(msg) => msg,
+                            // Too far behind to catch up live; have the client
+                            // reconnect and pick up what it missed from history
+                            Next::Overflowed => {
+                                warn!("⚠️ {} fell too far behind, closing for resync", user_clone);
+                                let frame = ResyncFrame::new("outbound_queue_overflow");
+                                let _ = sender.send(Message::Text(frame.to_text())).await;
+                                let _ = sender
+                                    .send(Message::Close(Some(CloseFrame {
+                                        code: close_code::AGAIN,
+                                        reason: "Too far behind, reconnect to resync".into(),
+                                    })))
+                                    .await;
+                                sender_cancel.cancel();
+                                break;
+                            }
+                        };
+                        info!(
+                            correlation_id = msg.correlation_id.map(field::display),
+                            "🔔 Delivering message to {}: {}",
+                            user_clone,
+                            msg.frame
+                        );
+                        let text = match (resumed_through, msg.seq) {
+                            // Already replayed during the catch-up
+                            (Some(through), Some(seq)) if seq <= through => continue,
+                            (Some(_), Some(seq)) => EventFrame::new(seq, &msg.frame).to_text(),
+                            _ => msg.frame,
+                        };
+                        if let Err(e) = sender.send(Message::Text(text)).await {
+                            info!("❌ Error sending message to {}: {}", user_clone, e);
+                            break;
+                        }
+                    }
+                    _ = sender_shutdown.cancelled() => {
+                        let frame = RestartingFrame {
+                            kind: "server_restarting",
+                            retry_after_secs,
+                        };
+                        let _ = sender
+                            .send(Message::Text(
+                                serde_json::to_string(&frame).expect("restart frame serializes"),
+                            ))
+                            .await;
+                        let _ = sender
+                            .send(Message::Close(Some(CloseFrame {
+                                code: close_code::AWAY,
+                                reason: format!(
+                                    "Server restarting, reconnect in {}s",
+                                    retry_after_secs
+                                )
+                                .into(),
+                            })))
+                            .await;
+                        break;
+                    }
+                    _ = sender_cancel.cancelled() => {
+                        let _ = sender
+                            .send(Message::Close(Some(CloseFrame {
+                                code: close_code::POLICY,
+                                reason: "Disconnected by server".into(),
+                            })))
+                            .await;
+                        break;
+                    }
+                    _ = sender_ping.notified() => {
+                        if sender.send(Message::Ping(Vec::new())).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        .in_current_span(),
+    );
+
+    // Handle incoming messages from the user until they leave, are kicked,
+    // stop answering pings or go idle
+    let mut ping_interval =
+        interval_at(Instant::now() + config.ping_interval, config.ping_interval);
+    ping_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut missed_pongs = 0;
+    let mut last_active = Instant::now();
+    let mut reason = CloseReason::Client;
+    loop {
+        let msg = tokio::select! {
+            msg = receiver.next() => msg,
+            _ = cancel.cancelled() => {
+                reason = if queue.is_overflowed() {
+                    CloseReason::QueueOverflow
+                } else {
+                    CloseReason::Kicked
+                };
+                break;
+            }
+            _ = shutdown.cancelled() => {
+                reason = CloseReason::Shutdown;
+                break;
+            }
+            _ = ping_interval.tick() => {
+                if missed_pongs >= config.max_missed_pongs {
+                    info!("💔 {} missed {} ping(s), dropping connection", username, missed_pongs);
+                    reason = CloseReason::HeartbeatTimeout;
+                    break;
+                }
+                if let Some(idle) = config.idle_timeout.filter(|idle| last_active.elapsed() >= *idle) {
+                    queue.push(
+                        format!("System: Disconnected after {}s without activity", idle.as_secs())
+                            .into(),
+                    );
+                    reason = CloseReason::IdleTimeout;
+                    break;
+                }
+                missed_pongs += 1;
+                ping.notify_one();
+                continue;
+            }
+        };
+        let Some(Ok(msg)) = msg else {
+            break;
+        };
+        // Any frame, a pong included, shows the peer is still there
+        missed_pongs = 0;
+        let Message::Text(text) = msg else {
+            continue;
+        };
+        // Heartbeats keep the connection alive but don't count as activity
+        if is_heartbeat(&text) {
+            queue.push(Outbound::ephemeral(HEARTBEAT_ACK.to_string()));
+            continue;
+        }
+        last_active = Instant::now();
+
+        let result = if scopes.contains(Scope::MessagesWrite) {
+            dispatch(&db, &state, retention, scheduler.as_ref(), &username, &text).await
+        } else {
+            Err(AppError::Forbidden(
+                "API key lacks the messages:write scope".to_string(),
+            ))
+        };
+        if let Err(e) = result {
+            report_error(&state, &username, &e).await;
+        }
+    }
+
+    // Clean up when user disconnects
+    // Give the sender task a moment to deliver a close frame, then abort it
+    cancel.cancel();
+    if tokio::time::timeout(std::time::Duration::from_secs(1), &mut sender_handle)
+        .await
+        .is_err()
+    {
+        sender_handle.abort();
+    }
+
+    unregister(&ws_state, &user, ip, &connection, reason).await;
+}
+
+/// Look up a connecting user by Firebase UID (stored as username), refusing
+/// banned and suspended accounts.
+async fn load_user(db: &DatabaseConnection, uid: &str) -> Result<users::Model, AppError> {
+    match Users::find()
+        .filter(users::Column::Username.eq(uid))
+        .one(db)
+        .await
+    {
+        Ok(Some(user)) if user.is_banned() || user.is_suspended() => {
+            info!(
+                "⛔ Suspended or banned user {} tried to connect, closing connection",
+                uid
+            );
+            Err(AppError::Forbidden(
+                "Account is suspended or banned".to_string(),
+            ))
+        }
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => {
+            info!("❌ No user found with UID {}, closing connection", uid);
+            Err(AppError::not_found("User"))
+        }
+        Err(e) => {
+            let e = AppError::from(e);
+            error!("❌ Failed to load user {} for WebSocket: {}", uid, e);
+            Err(e)
+        }
+    }
+}
+
+/// Put a new connection for `user` online with a bounded outbound queue,
+/// replacing any older one. `transport` is `ws` or `sse`.
+async fn register(
+    ws_state: &WsState,
+    user: &users::Model,
+    ip: Option<String>,
+    connection_id: Uuid,
+    transport: &'static str,
+) -> Connection {
+    let connection = Connection {
+        id: connection_id,
+        queue: OutboundQueue::new(ws_state.config.outbound_queue_size),
+        cancel: CancellationToken::new(),
+    };
+    AuditEvent::new(WS_CONNECTED)
+        .actor(user.id)
+        .ip(ip)
+        .detail("connection_id", connection.id.to_string())
+        .detail("transport", transport)
+        .log(&ws_state.db)
+        .await;
+    metrics().ws_connections_total.inc();
+    metrics().ws_connections.inc();
+    let mut state_guard = ws_state.online.lock().await;
+    state_guard.insert(user.username.clone(), connection.clone());
+
+    info!(
+        "📡 Current online users: {:?}",
+        state_guard.keys().collect::<Vec<_>>()
+    );
+    connection
+}
+
+/// Take a closed connection offline: tell the user's contacts, drop it from
+/// the online map unless a newer one replaced it, and record why it ended.
+async fn unregister(
+    ws_state: &WsState,
+    user: &users::Model,
+    ip: Option<String>,
+    connection: &Connection,
+    reason: CloseReason,
+) {
+    fn broadcast_status_update(
+        state: &HashMap<String, Connection>,
+        username: &str,
+        is_online: bool,
+        hidden_from: &HashSet<String>,
+    ) {
+        let status_message = if is_online {
+            format!("System: User '{}' is online", username)
+        } else {
+            format!("System: User '{}' went offline", username)
+        };
+
+        // Send status update to all connected users
+        for (user, connection) in state {
+            // Don't send the notification to the user who triggered it, or
+            // to anyone on either side of a block with them
+            if user != username && !hidden_from.contains(user) {
+                connection
+                    .queue
+                    .push(Outbound::ephemeral(status_message.clone()));
+            }
+        }
+    }
+    let WsState {
+        db,
+        online: state,
+        shutdown,
+        ..
+    } = ws_state;
+    let username = &user.username;
+
+    // Broadcast that user went offline before removing from state. If the
+    // block list can't be loaded, stay quiet rather than leak presence. On
+    // shutdown everyone is leaving, so skip the noise.
+    if !shutdown.is_draining() {
+        match hidden_usernames(db, user.id).await {
+            Ok(hidden_from) => {
+                let state_guard = state.lock().await;
+                broadcast_status_update(&state_guard, username, false, &hidden_from);
+            }
+            Err(e) => warn!(
+                "⚠️ Skipping offline notice for {}, block list unavailable: {}",
+                username, e
+            ),
+        }
+    }
+
+    {
+        // A newer connection from the same user may have replaced this one
+        let mut state_guard = state.lock().await;
+        if state_guard
+            .get(username)
+            .is_some_and(|c| c.id == connection.id)
+        {
+            state_guard.remove(username);
+        }
+    }
+    metrics().ws_connections.dec();
+    metrics()
+        .ws_disconnects
+        .with_label_values(&[reason.as_str()])
+        .inc();
+    AuditEvent::new(WS_DISCONNECTED)
+        .actor(user.id)
+        .ip(ip)
+        .detail("connection_id", connection.id.to_string())
+        .log(db)
+        .await;
+    info!("User {} disconnected ({})", username, reason.as_str());
+}
+
+fn is_heartbeat(text: &str) -> bool {
+    text.trim_start().starts_with('{')
+        && matches!(
+            serde_json::from_str::<ClientFrame>(text),
+            Ok(ClientFrame::Heartbeat)
+        )
+}
+
+/// Log a failed client request and answer it with an error frame. The
+/// connection stays open.
+async fn report_error(state: &SharedState, username: &str, e: &AppError) {
+    if e.is_server_error() {
+        error!("❌ WebSocket request from {} failed: {}", username, e);
+    } else {
+        info!("⚠️ Rejected WebSocket request from {}: {}", username, e);
+    }
+    send_to_user(state, username, e.to_frame()).await;
+}
+
+/// Usernames on either side of a block with `user_id`.
+async fn hidden_usernames(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> Result<HashSet<String>, AppError> {
+    let blocked_ids = blocked_either_way(db, user_id).await?;
+    Ok(Users::find()
+        .filter(users::Column::Id.is_in(blocked_ids))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|u| u.username)
+        .collect())
+}
+
+/// Fresh copy of the connected user's row, so bans and deletions made while
+/// the socket is open are seen.
+async fn current_user(db: &DatabaseConnection, username: &str) -> Result<users::Model, AppError> {
+    Users::find()
+        .filter(users::Column::Username.eq(username))
+        .one(db)
+        .await?
+        .ok_or_else(|| AppError::not_found("User"))
+}
+
+/// Handle one text frame from `username`, whichever transport it came in on.
+async fn dispatch(
+    db: &DatabaseConnection,
+    state: &SharedState,
+    retention: RetentionPolicy,
+    scheduler: Option<&MessageScheduler>,
+    username: &str,
+    text: &str,
+) -> Result<(), AppError> {
+    if text.trim_start().starts_with('{') {
+        handle_client_frame(db, state, scheduler, username, text).await
+    } else {
+        handle_text_message(db, state, retention, username, text).await
+    }
+}
+
+/// Handle a plain `recipient: text` message from `username`.
+async fn handle_text_message(
+    db: &DatabaseConnection,
+    state: &SharedState,
+    retention: RetentionPolicy,
+    username: &str,
+    text: &str,
+) -> Result<(), AppError> {
+    let Some((recipient, message_content)) = text.split_once(':') else {
+        return Ok(());
+    };
+    let recipient = recipient.trim();
+    let sender_user = current_user(db, username).await?;
+
+    let outcome = route_text_message(
+        db,
+        state,
+        retention,
+        &sender_user,
+        recipient,
+        message_content.trim().to_string(),
+    )
+    .await?;
+    match outcome {
+        SendOutcome::Sent(_) => {}
+        SendOutcome::RecipientBlocked => {
+            send_to_user(
+                state,
+                username,
+                format!("System: You have blocked user '{}'", recipient),
+            )
+            .await;
+        }
+        SendOutcome::UnknownRecipient => {
+            // Notify sender that recipient doesn't exist
+            send_to_user(
+                state,
+                username,
+                format!("System: User '{}' does not exist", recipient),
+            )
+            .await;
+        }
+    }
+    Ok(())
+}
+
+/// Handle a JSON frame from `username`, answering on their own socket.
+async fn handle_client_frame(
+    db: &DatabaseConnection,
+    state: &SharedState,
+    scheduler: Option<&MessageScheduler>,
+    username: &str,
+    text: &str,
+) -> Result<(), AppError> {
+    let frame = match serde_json::from_str::<ClientFrame>(text) {
+        Ok(frame) => frame,
+        Err(e) => {
+            send_to_user(state, username, format!("System: Invalid frame: {}", e)).await;
+            return Ok(());
+        }
+    };
+
+    match frame {
+        ClientFrame::Schedule(request) => {
+            let Some(scheduler) = scheduler else {
+                return Err(AppError::Forbidden(
+                    "Scheduled messages are disabled on this server".to_string(),
+                ));
+            };
+            let sender = current_user(db, username).await?;
+            let reply = match schedule_message(db, scheduler, &sender, request).await {
+                Ok((scheduled, to)) => {
+                    ScheduledFrame::new(ScheduledView::new(scheduled, to)).to_text()
+                }
+                Err(e) => format!("System: {}", e),
+            };
+            send_to_user(state, username, reply).await;
+        }
+        // Normally answered in the receive loop before getting here
+        ClientFrame::Heartbeat => {
+            send_ephemeral(state, username, HEARTBEAT_ACK.to_string()).await;
+        }
+    }
+    Ok(())
+}
+
+/// Result of routing one text message.
+pub enum SendOutcome {
+    Sent(messages::Model),
+    /// The sender has blocked the recipient; nothing was stored
+    RecipientBlocked,
+    UnknownRecipient,
+}
+
+/// Route a text message the way a live socket does: push it to the recipient
+/// (unless they blocked the sender) and echo it to the sender, then store it
+/// so an offline recipient gets it with their history.
+pub async fn route_text_message(
+    db: &DatabaseConnection,
+    state: &SharedState,
+    retention: RetentionPolicy,
+    sender_user: &users::Model,
+    recipient: &str,
+    message_content: String,
+) -> Result<SendOutcome, sea_orm::DbErr> {
+    let m = metrics();
+    let correlation_id = Uuid::new_v4();
+    let span = info_span!(
+        "route_message",
+        %correlation_id,
+        sender_id = sender_user.id,
+        recipient,
+    );
+    let timer = m.message_route_seconds.start_timer();
+    let result = deliver_and_store(
+        db,
+        state,
+        retention,
+        sender_user,
+        recipient,
+        message_content,
+        correlation_id,
+    )
+    .instrument(span)
+    .await;
+    timer.observe_duration();
+
+    match &result {
+        Ok(SendOutcome::Sent(_)) => {
+            m.messages_routed.with_label_values(&["sent"]).inc();
+            m.messages_persisted.inc();
+        }
+        Ok(SendOutcome::RecipientBlocked) => {
+            m.messages_routed
+                .with_label_values(&["recipient_blocked"])
+                .inc();
+        }
+        Ok(SendOutcome::UnknownRecipient) => {
+            m.messages_routed
+                .with_label_values(&["unknown_recipient"])
+                .inc();
+        }
+        Err(_) => m.messages_failed.inc(),
+    }
+    result
+}
+
+async fn deliver_and_store(
+    db: &DatabaseConnection,
+    state: &SharedState,
+    retention: RetentionPolicy,
+    sender_user: &users::Model,
+    recipient: &str,
+    message_content: String,
+    correlation_id: Uuid,
+) -> Result<SendOutcome, sea_orm::DbErr> {
+    let username = &sender_user.username;
+    let full_message = format!("{}: {}", username, message_content);
+
+    let Some(recipient_user) = Users::find()
+        .filter(users::Column::Username.eq(recipient))
+        .filter(users::Column::DeletedAt.is_null())
+        .one(db)
+        .await?
+    else {
+        return Ok(SendOutcome::UnknownRecipient);
+    };
+    if is_blocked(db, sender_user.id, recipient_user.id).await? {
+        return Ok(SendOutcome::RecipientBlocked);
+    }
+
+    // A block by the recipient swallows the message without telling the sender
+    let hidden = is_blocked(db, recipient_user.id, sender_user.id).await?;
+
+    // Store message in the database
+    let expires_at = retention
+        .expires_at(db, sender_user.id, recipient_user.id)
+        .await?;
+    let message = messages::ActiveModel {
+        sender_id: Set(sender_user.id),
+        receiver_id: Set(recipient_user.id),
+        message: Set(message_content),
+        status: Set(if hidden {
+            BLOCKED_STATUS.to_string()
+        } else {
+            UNREAD_STATUS.to_string()
+        }),
+        expires_at: Set(expires_at),
+        ..Default::default()
+    };
+
+    let message = message.insert(db).await?;
+    info!(message_id = message.id, "💾 Stored message");
+    // A hidden message doesn't exist as far as the recipient can tell
+    if !hidden {
+        WebhookEvent::new(
+            MESSAGE_CREATED,
+            MessageData::new(&message, username, &recipient_user.username),
+        )
+        .emit(db)
+        .await;
+    }
+
+    // Stored first, so a logged event never points at a message that isn't
+    // there. Send to the recipient, online or not, unless hidden by a block
+    let pushed = !hidden
+        && SyncEvent::new(full_message.clone())
+            .correlation_id(correlation_id)
+            .message_id(message.id)
+            .publish(db, state, recipient_user.id, recipient)
+            .await;
+    // Also send to sender so they see their own messages
+    if recipient != username {
+        // Only if not sending to self
+        SyncEvent::new(full_message)
+            .correlation_id(correlation_id)
+            .message_id(message.id)
+            .publish(db, state, sender_user.id, username)
+            .await;
+    }
+    // Delivered once it's on its way to an open socket; after the echo, so
+    // the sender sees the message before its receipt
+    if pushed {
+        mark_as_delivered(db, state, sender_user, &recipient_user).await?;
+    }
+
+    Ok(SendOutcome::Sent(message))
+}
+
+/// Catch up a client that connected with `?since=`: replay the events after
+/// its cursor, or send a resync hint and the full history when that isn't
+/// possible. Ends with a sync frame and returns the cursor it carries.
+async fn resume_session<S>(
+    sender: &mut S,
+    user: &users::Model,
+    db: &DatabaseConnection,
+    online: &SharedState,
+    since: i64,
+) -> Result<i64, AppError>
+where
+    S: Sink<Message> + Unpin,
+{
+    let cursor = current_seq(db, user.id).await?;
+    if since > 0 {
+        match events_since(db, user.id, since, cursor).await? {
+            Resume::Replay(events) => {
+                for event in &events {
+                    let frame = EventFrame::new(event.seq, &event.frame).to_text();
+                    let _ = sender.send(Message::Text(frame)).await;
+                }
+                let frame = SyncFrame::new(cursor, events.len()).to_text();
+                let _ = sender.send(Message::Text(frame)).await;
+                info!(
+                    "⏩ Resumed {} from {} with {} event(s)",
+                    user.username,
+                    since,
+                    events.len()
+                );
+                return Ok(cursor);
+            }
+            Resume::Expired => {
+                info!(
+                    "🔄 Cursor {} of {} expired, resyncing",
+                    since, user.username
+                );
+                let frame = ResyncFrame::new("cursor_expired").to_text();
+                let _ = sender.send(Message::Text(frame)).await;
+            }
+        }
+    }
+    send_message_history(sender, user, db, online).await?;
+    let _ = sender
+        .send(Message::Text(SyncFrame::new(cursor, 0).to_text()))
+        .await;
+    Ok(cursor)
+}
+
+// Function to retrieve and send message history
+pub(crate) async fn send_message_history<S>(
+    sender: &mut S,
+    user: &users::Model,
+    db: &DatabaseConnection,
+    online: &SharedState,
+) -> Result<(), AppError>
+where
+    S: Sink<Message> + Unpin,
+{
+    info!("Retrieving message history for {}", user.username);
+
+    // Get distinct conversation partners
+    // Query for users where the current user is the sender
+    let sent_partners: Vec<i32> = Messages::find()
+        .filter(messages::Column::SenderId.eq(user.id))
+        .select_only()
+        .column(messages::Column::ReceiverId)
+        .group_by(messages::Column::ReceiverId)
+        .into_tuple::<i32>()
+        .all(db)
+        .await?;
+
+    // Query for users where the current user is the receiver
+    let received_partners: Vec<i32> = Messages::find()
+        .filter(messages::Column::ReceiverId.eq(user.id))
+        .filter(messages::Column::Status.ne(BLOCKED_STATUS))
+        .select_only()
+        .column(messages::Column::SenderId)
+        .group_by(messages::Column::SenderId)
+        .into_tuple::<i32>()
+        .all(db)
+        .await?;
+
+    // Merge and remove duplicates
+    let mut conversation_partners: Vec<i32> = sent_partners;
+    conversation_partners.extend(received_partners);
+    conversation_partners.sort();
+    conversation_partners.dedup();
+
+    if conversation_partners.is_empty() {
+        return Ok(());
+    }
+
+    // Send a history marker to indicate start of history
+    let _ = sender
+        .send(Message::Text("--- Message History ---".to_string()))
+        .await;
+
+    // Process each conversation partner
+    for partner_id in conversation_partners {
+        // Get partner username; a partner row removed under us is skipped
+        let Some(partner) = Users::find_by_id(partner_id).one(db).await? else {
+            warn!(
+                "⚠️ Conversation partner {} of {} no longer exists",
+                partner_id, user.username
+            );
+            continue;
+        };
+
+        // Send conversation header
+        let _ = sender
+            .send(Message::Text(format!(
+                "--- Conversation with {} ---",
+                partner.username
+            )))
+            .await;
+
+        // Get messages with this partner (limited to 50 most recent)
+        let messages = Messages::find()
+            .filter(
+                sea_orm::Condition::any()
+                    .add(
+                        sea_orm::Condition::all()
+                            .add(messages::Column::SenderId.eq(user.id))
+                            .add(messages::Column::ReceiverId.eq(partner_id)),
+                    )
+                    .add(
+                        sea_orm::Condition::all()
+                            .add(messages::Column::SenderId.eq(partner_id))
+                            .add(messages::Column::ReceiverId.eq(user.id))
+                            // Messages sent while the partner was blocked stay hidden
+                            .add(messages::Column::Status.ne(BLOCKED_STATUS)),
+                    ),
+            )
+            .filter(messages::Column::Status.ne(REMOVED_STATUS))
+            .filter(not_expired())
+            .order_by(messages::Column::CreatedAt, sea_orm::Order::Asc)
+            .limit(50)
+            .all(db)
+            .await?;
+
+        // Everything the partner sent is on screen now
+        mark_as_read(db, online, &partner, user).await?;
+
+        // Load attachments for this page of messages in one query
+        let message_ids: Vec<i32> = messages.iter().map(|m| m.id).collect();
+        let mut attachments_by_message: HashMap<i32, Vec<attachments::Model>> = HashMap::new();
+        for attachment in Attachments::find()
+            .filter(attachments::Column::MessageId.is_in(message_ids))
+            .all(db)
+            .await?
+        {
+            if let Some(message_id) = attachment.message_id {
+                attachments_by_message
+                    .entry(message_id)
+                    .or_default()
+                    .push(attachment);
+            }
+        }
+
+        // Process and send each message
+        for msg in messages {
+            // Only two people are in the conversation
+            let sender_name = if msg.sender_id == user.id {
+                &user.username
+            } else {
+                &partner.username
+            };
+
+            // Format and send message
+            let formatted_msg = match attachments_by_message.get(&msg.id) {
+                Some(files) => MessageFrame::new(sender_name, &msg, files).to_text(),
+                None => format!("{}: {}", sender_name, msg.message),
+            };
+            let _ = sender.send(Message::Text(formatted_msg)).await;
+        }
+    }
+
+    // Send end-of-history marker
+    let _ = sender
+        .send(Message::Text("--- End of History ---".to_string()))
+        .await;
+    Ok(())
+}
+
+/// Move messages from `sender` to `receiver` that were waiting for the
+/// recipient to `delivered`.
+pub async fn mark_as_delivered(
+    db: &DatabaseConnection,
+    online: &SharedState,
+    sender: &users::Model,
+    receiver: &users::Model,
+) -> Result<(), sea_orm::DbErr> {
+    // Build an ActiveModel with the updated value
+    let active_model = messages::ActiveModel {
+        status: Set(DELIVERED_STATUS.to_string()), // Set the status field
+        ..Default::default()                       // Make sure the other fields are left as they are
+    };
+
+    // Update the matching records
+    let delivered = messages::Entity::update_many()
+        .set(active_model) // Use the ActiveModel for the update
+        .filter(messages::Column::SenderId.eq(sender.id))
+        .filter(messages::Column::ReceiverId.eq(receiver.id))
+        .filter(messages::Column::Status.eq(UNREAD_STATUS))
+        .exec_with_returning(db)
+        .await?;
+    send_receipt(db, online, RECEIPT_DELIVERED, sender, receiver, delivered).await;
+
+    Ok(())
+}
+
+/// Move messages from `sender` to `receiver` to `read`, whether or not they
+/// were pushed live first.
+async fn mark_as_read(
+    db: &DatabaseConnection,
+    online: &SharedState,
+    sender: &users::Model,
+    receiver: &users::Model,
+) -> Result<(), sea_orm::DbErr> {
+    // Build an ActiveModel with the updated value
+    let active_model = messages::ActiveModel {
+        status: Set(READ_STATUS.to_string()), // Set the status field
+        ..Default::default()                  // Make sure the other fields are left as they are
+    };
+
+    // Update the matching records
+    let read = messages::Entity::update_many()
+        .set(active_model) // Use the ActiveModel for the update
+        .filter(messages::Column::SenderId.eq(sender.id))
+        .filter(messages::Column::ReceiverId.eq(receiver.id))
+        .filter(messages::Column::Status.is_in([UNREAD_STATUS, DELIVERED_STATUS]))
+        .exec_with_returning(db)
+        .await?;
+    send_receipt(db, online, RECEIPT_READ, sender, receiver, read).await;
+
+    Ok(())
+}
+
+/// Tell the sender, through their event log, and webhook subscribers that
+/// these messages changed status.
+async fn send_receipt(
+    db: &DatabaseConnection,
+    online: &SharedState,
+    event_type: &'static str,
+    sender: &users::Model,
+    receiver: &users::Model,
+    changed: Vec<messages::Model>,
+) {
+    let Some(status) = changed.first().map(|m| m.status.clone()) else {
+        return;
+    };
+    let ids: Vec<i32> = changed.iter().map(|m| m.id).collect();
+    let frame = ReceiptFrame::new(&status, &receiver.username, ids).to_text();
+    SyncEvent::new(frame)
+        .publish(db, online, sender.id, &sender.username)
+        .await;
+
+    let data = ReceiptData {
+        from: &sender.username,
+        to: &receiver.username,
+        count: changed.len() as u64,
+    };
+    WebhookEvent::new(event_type, data).emit(db).await;
+}
+
+/// Push a frame to a user's socket if they are connected to this instance.
+pub async fn send_to_user(state: &SharedState, username: &str, frame: String) -> bool {
+    send_outbound(state, username, frame.into()).await
+}
+
+/// `send_to_user` for a frame that may be dropped if the client is behind,
+/// like presence or acks.
+pub async fn send_ephemeral(state: &SharedState, username: &str, frame: String) -> bool {
+    send_outbound(state, username, Outbound::ephemeral(frame)).await
+}
+
+/// Queue a prepared frame on a user's socket if they are connected here.
+pub async fn send_outbound(state: &SharedState, username: &str, outbound: Outbound) -> bool {
+    match state.lock().await.get(username) {
+        Some(connection) => connection.queue.push(outbound),
+        None => false,
+    }
+}
+
+/// Close a user's socket, telling them why first. Returns whether they were connected.
+pub async fn disconnect_user(state: &SharedState, username: &str, reason: &str) -> bool {
+    match state.lock().await.get(username) {
+        Some(connection) => {
+            connection.queue.push(format!("System: {}", reason).into());
+            connection.cancel.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn ws_routes(state: WsState) -> Router {
+    Router::new()
+        .route("/ws", get(web_socket_handler))
+        .route("/events", get(sse::event_stream_handler))
+        .route("/messages", post(sse::post_message))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{webhook_deliveries, webhooks, WebhookDeliveries, Webhooks};
+    use crate::test_support::{create_user, test_db};
+    use std::time::Duration;
+    use tokio_tungstenite::tungstenite::Message as ClientMessage;
+
+    #[tokio::test]
+    async fn delivered_then_read_message_fires_both_receipts() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let alice = create_user(&db, "alice").await;
+        let bob = create_user(&db, "bob").await;
+        let webhook = webhooks::ActiveModel {
+            url: Set("http://127.0.0.1:9/receipts".to_string()),
+            secret: Set("whsec_test".to_string()),
+            events: Set(format!("{} {}", RECEIPT_DELIVERED, RECEIPT_READ)),
+            active: Set(true),
+            created_at: Set(chrono::Utc::now()),
+            updated_at: Set(chrono::Utc::now()),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+
+        // Bob is online, so the message is pushed and marked delivered
+        let online = SharedState::default();
+        online.lock().await.insert(
+            bob.username.clone(),
+            Connection {
+                id: Uuid::new_v4(),
+                queue: OutboundQueue::new(16),
+                cancel: CancellationToken::new(),
+            },
+        );
+        let outcome = deliver_and_store(
+            &db,
+            &online,
+            RetentionPolicy::from_days(None),
+            &alice,
+            &bob.username,
+            "hello".to_string(),
+            Uuid::new_v4(),
+        )
+        .await
+        .unwrap();
+        let SendOutcome::Sent(message) = outcome else {
+            panic!("message was not sent");
+        };
+        let stored = Messages::find_by_id(message.id).one(&db).await.unwrap();
+        assert_eq!(stored.unwrap().status, DELIVERED_STATUS);
+
+        // Loading the history reads it
+        let mut frames: Vec<Message> = Vec::new();
+        send_message_history(&mut frames, &bob, &db, &online)
+            .await
+            .unwrap();
+        let stored = Messages::find_by_id(message.id).one(&db).await.unwrap();
+        assert_eq!(stored.unwrap().status, READ_STATUS);
+
+        let events: Vec<String> = WebhookDeliveries::find()
+            .filter(webhook_deliveries::Column::WebhookId.eq(webhook.id))
+            .order_by_asc(webhook_deliveries::Column::Id)
+            .all(&db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|delivery| delivery.event_type)
+            .collect();
+        assert_eq!(events, [RECEIPT_DELIVERED, RECEIPT_READ]);
+
+        // Alice's log has her message echo and both receipts
+        let Resume::Replay(logged) = events_since(&db, alice.id, 0, 3).await.unwrap() else {
+            panic!("replay expired");
+        };
+        let kinds: Vec<&str> = logged.iter().map(|e| e.kind.as_str()).collect();
+        assert_eq!(kinds, ["message", "receipt", "receipt"]);
+        assert_eq!(logged[0].message_id, Some(message.id));
+
+        Webhooks::delete_by_id(webhook.id).exec(&db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn an_idle_socket_is_closed() {
+        let Some(db) = test_db().await else {
+            return;
+        };
+        let alice = create_user(&db, "alice").await;
+        let ws_state = WsState {
+            db,
+            online: SharedState::default(),
+            retention: RetentionPolicy::from_days(None),
+            scheduler: None,
+            firebase: FirebaseConfig {
+                api_key: String::new(),
+                project_id: String::new(),
+            },
+            shutdown: Shutdown::new(),
+            config: WebSocketConfig {
+                ping_interval: Duration::from_millis(50),
+                max_missed_pongs: 3,
+                idle_timeout: Some(Duration::from_millis(300)),
+                outbound_queue_size: 16,
+            },
+        };
+        let uid = alice.username.clone();
+        let app = Router::new()
+            .route(
+                "/ws",
+                get(
+                    |ws: WebSocketUpgrade, State(ws_state): State<WsState>| async move {
+                        ws.on_upgrade(move |socket| {
+                            handle_socket(
+                                socket,
+                                ws_state,
+                                uid,
+                                Scopes::all(),
+                                None,
+                                Uuid::new_v4(),
+                                Some(0),
+                            )
+                        })
+                    },
+                ),
+            )
+            .with_state(ws_state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let started = Instant::now();
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+            .await
+            .unwrap();
+        // Heartbeats are acknowledged but don't keep the socket open
+        socket
+            .send(ClientMessage::Text(r#"{"type":"heartbeat"}"#.to_string()))
+            .await
+            .unwrap();
+
+        // Reading answers the server's pings, so only idleness can close it
+        let mut texts = Vec::new();
+        let close = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some(msg) = socket.next().await {
+                match msg.unwrap() {
+                    ClientMessage::Text(text) => texts.push(text),
+                    ClientMessage::Close(frame) => return frame,
+                    _ => {}
+                }
+            }
+            None
+        })
+        .await
+        .expect("idle socket was not closed");
+
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert!(texts.contains(&HEARTBEAT_ACK.to_string()));
+        assert_eq!(
+            texts.last().map(String::as_str),
+            Some("System: Disconnected after 0s without activity")
+        );
+        assert_eq!(close.unwrap().code, close_code::POLICY.into());
+        // Give the handler a moment to finish its cleanup
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!ws_state.online.lock().await.contains_key(&alice.username));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::metrics::metrics;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+/// How hard the queue tries to deliver a frame.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Never dropped. If it can't be queued the socket is closed with a
+    /// resync hint, and the client recovers it from history on reconnect.
+    Reliable,
+    /// Presence updates, heartbeat acks and the like; dropped first when
+    /// the client falls behind
+    Ephemeral,
+}
+
+/// A frame queued for a socket. Routed messages carry the correlation id
+/// of the send that produced them so delivery can be traced back to it.
+#[derive(Clone)]
+pub struct Outbound {
+    pub frame: String,
+    pub correlation_id: Option<Uuid>,
+    /// Position in the user's event log, for frames recorded by `sync`
+    pub seq: Option<i64>,
+    pub delivery: Delivery,
+}
+
+impl Outbound {
+    pub fn ephemeral(frame: String) -> Self {
+        Self {
+            delivery: Delivery::Ephemeral,
+            ..frame.into()
+        }
+    }
+}
+
+impl From<String> for Outbound {
+    fn from(frame: String) -> Self {
+        Self {
+            frame,
+            correlation_id: None,
+            seq: None,
+            delivery: Delivery::Reliable,
+        }
+    }
+}
+
+/// What the socket's sender task should do next.
+pub enum Next {
+    Frame(Outbound),
+    /// A reliable frame didn't fit; tell the client to resync and close
+    Overflowed,
+}
+
+/// Bounded outbound queue for one connection. When full, queued ephemeral
+/// frames make room for new ones; a reliable frame that still doesn't fit
+/// marks the queue overflowed instead of being dropped.
+#[derive(Clone)]
+pub struct OutboundQueue {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    capacity: usize,
+    state: Mutex<QueueState>,
+    ready: Notify,
+}
+
+#[derive(Default)]
+struct QueueState {
+    frames: VecDeque<Outbound>,
+    overflowed: bool,
+}
+
+impl OutboundQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                capacity,
+                state: Mutex::default(),
+                ready: Notify::new(),
+            }),
+        }
+    }
+
+    /// Queue a frame. Returns `false` if it was dropped or the queue has
+    /// overflowed.
+    pub fn push(&self, outbound: Outbound) -> bool {
+        let m = metrics();
+        let mut state = self.inner.state.lock().expect("outbound queue poisoned");
+        if state.overflowed {
+            return false;
+        }
+        if state.frames.len() >= self.inner.capacity {
+            match state
+                .frames
+                .iter()
+                .position(|queued| queued.delivery == Delivery::Ephemeral)
+            {
+                // Make room by dropping the oldest ephemeral frame
+                Some(index) => {
+                    state.frames.remove(index);
+                    m.ws_outbound_queued.dec();
+                    m.ws_outbound_dropped.inc();
+                }
+                None if outbound.delivery == Delivery::Ephemeral => {
+                    m.ws_outbound_dropped.inc();
+                    return false;
+                }
+                None => {
+                    m.ws_outbound_queued.sub(state.frames.len() as i64);
+                    state.frames.clear();
+                    state.overflowed = true;
+                    m.ws_outbound_overflows.inc();
+                    drop(state);
+                    self.inner.ready.notify_one();
+                    return false;
+                }
+            }
+        }
+        state.frames.push_back(outbound);
+        m.ws_outbound_queued.inc();
+        m.ws_outbound_queue_depth.observe(state.frames.len() as f64);
+        drop(state);
+        self.inner.ready.notify_one();
+        true
+    }
+
+    /// Wait for the next frame to send.
+    pub async fn next(&self) -> Next {
+        loop {
+            {
+                let mut state = self.inner.state.lock().expect("outbound queue poisoned");
+                if state.overflowed {
+                    return Next::Overflowed;
+                }
+                if let Some(outbound) = state.frames.pop_front() {
+                    metrics().ws_outbound_queued.dec();
+                    return Next::Frame(outbound);
+                }
+            }
+            self.inner.ready.notified().await;
+        }
+    }
+
+    pub fn is_overflowed(&self) -> bool {
+        self.inner
+            .state
+            .lock()
+            .expect("outbound queue poisoned")
+            .overflowed
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // Frames never sent because the socket went away
+        let state = self.state.get_mut().expect("outbound queue poisoned");
+        metrics().ws_outbound_queued.sub(state.frames.len() as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reliable(text: &str) -> Outbound {
+        text.to_string().into()
+    }
+
+    fn ephemeral(text: &str) -> Outbound {
+        Outbound::ephemeral(text.to_string())
+    }
+
+    async fn drain(queue: &OutboundQueue) -> Vec<String> {
+        let mut frames = Vec::new();
+        while queue.inner.state.lock().unwrap().frames.front().is_some() {
+            match queue.next().await {
+                Next::Frame(outbound) => frames.push(outbound.frame),
+                Next::Overflowed => break,
+            }
+        }
+        frames
+    }
+
+    #[tokio::test]
+    async fn full_queue_drops_oldest_ephemeral_first() {
+        let queue = OutboundQueue::new(3);
+        assert!(queue.push(ephemeral("presence-1")));
+        assert!(queue.push(reliable("message-1")));
+        assert!(queue.push(ephemeral("presence-2")));
+
+        assert!(queue.push(reliable("message-2")));
+        assert!(queue.push(ephemeral("presence-3")));
+
+        assert_eq!(
+            drain(&queue).await,
+            ["message-1", "message-2", "presence-3"]
+        );
+        assert!(!queue.is_overflowed());
+    }
+
+    #[tokio::test]
+    async fn ephemeral_frames_are_dropped_when_only_reliable_ones_queue() {
+        let queue = OutboundQueue::new(2);
+        assert!(queue.push(reliable("message-1")));
+        assert!(queue.push(reliable("message-2")));
+
+        assert!(!queue.push(ephemeral("presence")));
+        assert!(!queue.is_overflowed());
+        assert_eq!(drain(&queue).await, ["message-1", "message-2"]);
+    }
+
+    #[tokio::test]
+    async fn reliable_frame_that_doesnt_fit_overflows_the_queue() {
+        let queue = OutboundQueue::new(2);
+        assert!(queue.push(reliable("message-1")));
+        assert!(queue.push(reliable("message-2")));
+
+        assert!(!queue.push(reliable("message-3")));
+        assert!(queue.is_overflowed());
+        assert!(matches!(queue.next().await, Next::Overflowed));
+        // Nothing more is accepted once the client has to resync
+        assert!(!queue.push(ephemeral("presence")));
+        assert!(matches!(queue.next().await, Next::Overflowed));
+    }
+
+    #[tokio::test]
+    async fn next_waits_for_a_push() {
+        let queue = OutboundQueue::new(4);
+        let waiting = tokio::spawn({
+            let queue = queue.clone();
+            async move {
+                match queue.next().await {
+                    Next::Frame(outbound) => outbound.frame,
+                    Next::Overflowed => panic!("not overflowed"),
+                }
+            }
+        });
+        tokio::task::yield_now().await;
+        queue.push(reliable("hello"));
+        assert_eq!(waiting.await.unwrap(), "hello");
+    }
+}
@@ -0,0 +1,343 @@
+//! Fallback transport for clients that can't keep a WebSocket open, e.g.
+//! behind proxies that strip `Upgrade`. Server-to-client frames arrive on a
+//! Server-Sent Events stream and sends go through `POST /messages`. Both
+//! share the socket's auth, routing and frames, so a client can switch
+//! transports without changing how it reads or writes them.
+
+use super::queue::Outbound;
+use super::{
+    authenticate, dispatch, load_user, reconnect_after_secs, register, resume_session, unregister,
+    CloseReason, Connection, Next, RestartingFrame, WsState,
+};
+use crate::audit::{AuditEvent, ClientIp, WS_AUTH_FAILED};
+use crate::auth::firebase_auth::extract_token;
+use crate::auth::MessageSender;
+use crate::entity::users;
+use crate::error::AppError;
+use crate::sync::types::{EventFrame, ResyncFrame};
+use axum::{
+    extract::ws::Message,
+    extract::{Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::sse::{Event, KeepAlive, Sse},
+    response::{IntoResponse, Response},
+};
+use futures::stream::{self, StreamExt};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::time::Duration;
+use tracing::{error, field, info, info_span, warn, Instrument, Span};
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct EventStreamParams {
+    /// `EventSource` can't set headers, so the token may come in the query
+    token: Option<String>,
+    /// Resume from this event sequence number; `0` (the default) for a
+    /// fresh session. A `Last-Event-ID` header takes precedence.
+    since: Option<i64>,
+}
+
+/// `GET /events`: the socket's server-to-client half as an SSE stream.
+/// Always in resume mode; each event's `data` is the frame a resuming
+/// socket would get, and logged events carry their sequence number as the
+/// SSE `id` so a reconnecting `EventSource` picks up where it left off.
+pub async fn event_stream_handler(
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    Query(EventStreamParams { token, since }): Query<EventStreamParams>,
+    State(ws_state): State<WsState>,
+) -> Response {
+    // Draining for a restart; the client should try another instance
+    if ws_state.shutdown.is_draining() {
+        return AppError::ShuttingDown.into_response();
+    }
+    let Some(token) = token.or_else(|| extract_token(&headers)) else {
+        return AppError::unauthorized("Missing token").into_response();
+    };
+    let uid = match authenticate(&ws_state, &token, "sse").await {
+        Ok((uid, _)) => uid,
+        Err(e) => {
+            if matches!(e, AppError::Unauthorized(_)) {
+                AuditEvent::new(WS_AUTH_FAILED)
+                    .ip(ip)
+                    .log(&ws_state.db)
+                    .await;
+            }
+            return e.into_response();
+        }
+    };
+
+    let connection_id = Uuid::new_v4();
+    let span = info_span!(
+        "sse_connection",
+        %connection_id,
+        username = %uid,
+        user_id = field::Empty,
+    );
+    let user = match load_user(&ws_state.db, &uid).instrument(span.clone()).await {
+        Ok(user) => user,
+        Err(e) => return e.into_response(),
+    };
+    span.record("user_id", user.id);
+    let since = resume_from(&headers, since);
+
+    async move {
+        info!("SSE connection");
+        let connection = register(&ws_state, &user, ip.clone(), connection_id, "sse").await;
+
+        // Same catch-up as a resuming socket. Registered first, so anything
+        // sent meanwhile waits in the queue and is skipped if replayed.
+        let mut catch_up: Vec<Message> = Vec::new();
+        let resumed =
+            resume_session(&mut catch_up, &user, &ws_state.db, &ws_state.online, since).await;
+        let resumed_through = match &resumed {
+            Ok(cursor) => *cursor,
+            Err(e) => {
+                error!("❌ Failed to resume session for {}: {}", user.username, e);
+                catch_up.push(Message::Text(e.to_frame()));
+                0
+            }
+        };
+        // The closing sync frame moves the client's cursor
+        let sync_at = resumed.is_ok().then(|| catch_up.len().saturating_sub(1));
+        let catch_up: Vec<Event> = catch_up
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, msg)| match msg {
+                Message::Text(frame) if Some(i) == sync_at => {
+                    Some(frame_event(&frame).id(resumed_through.to_string()))
+                }
+                Message::Text(frame) => Some(frame_event(&frame)),
+                _ => None,
+            })
+            .collect();
+
+        let keep_alive = KeepAlive::new().interval(ws_state.config.ping_interval);
+        let session = EventStream {
+            ws_state,
+            user,
+            ip,
+            connection,
+            resumed_through,
+            reason: CloseReason::Client,
+            done: false,
+            span: Span::current(),
+        };
+        let live = stream::unfold(session, |mut session| async move {
+            let span = session.span.clone();
+            let event = session.next_event().instrument(span).await?;
+            Some((event, session))
+        });
+        let events = stream::iter(catch_up).chain(live).map(Ok::<_, Infallible>);
+
+        let mut response = Sse::new(events).keep_alive(keep_alive).into_response();
+        // Stop nginx-style proxies from buffering the stream
+        response
+            .headers_mut()
+            .insert("x-accel-buffering", HeaderValue::from_static("no"));
+        response
+    }
+    .instrument(span)
+    .await
+}
+
+/// `POST /messages`: send a frame exactly as it would go over a socket, a
+/// plain `recipient: text` message or a JSON client frame. Replies, like
+/// "does not exist" notices, arrive on the caller's event stream; failures
+/// are returned as the response instead.
+pub async fn post_message(
+    State(ws_state): State<WsState>,
+    MessageSender(user): MessageSender,
+    body: String,
+) -> Result<StatusCode, AppError> {
+    // Like the stream, leave new work to an instance that isn't restarting
+    if ws_state.shutdown.is_draining() {
+        return Err(AppError::ShuttingDown);
+    }
+    let WsState {
+        db,
+        online,
+        retention,
+        scheduler,
+        ..
+    } = &ws_state;
+    dispatch(
+        db,
+        online,
+        *retention,
+        scheduler.as_ref(),
+        &user.username,
+        &body,
+    )
+    .await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// One open event stream; taken offline when the response body is dropped.
+struct EventStream {
+    ws_state: WsState,
+    user: users::Model,
+    ip: Option<String>,
+    connection: Connection,
+    resumed_through: i64,
+    reason: CloseReason,
+    done: bool,
+    span: Span,
+}
+
+impl EventStream {
+    /// The next event to write, or `None` once the stream should end.
+    async fn next_event(&mut self) -> Option<Event> {
+        if self.done {
+            return None;
+        }
+        let queue = self.connection.queue.clone();
+        let cancel = self.connection.cancel.clone();
+        let shutdown = self.ws_state.shutdown.clone();
+        loop {
+            tokio::select! {
+                // Flush queued frames (e.g. the disconnect reason) before ending
+                biased;
+                next = queue.next() => match next {
+                    Next::Frame(msg) => {
+                        info!(
+                            correlation_id = msg.correlation_id.map(field::display),
+                            "🔔 Delivering message to {}: {}",
+                            self.user.username,
+                            msg.frame
+                        );
+                        let Some((frame, id)) = live_frame(&msg, self.resumed_through) else {
+                            continue;
+                        };
+                        let event = frame_event(&frame);
+                        return Some(match id {
+                            Some(seq) => event.id(seq.to_string()),
+                            None => event,
+                        });
+                    }
+                    // Too far behind to catch up live; the client reconnects
+                    // from its last event id
+                    Next::Overflowed => {
+                        warn!("⚠️ {} fell too far behind, ending stream for resync", self.user.username);
+                        self.done = true;
+                        self.reason = CloseReason::QueueOverflow;
+                        return Some(frame_event(
+                            &ResyncFrame::new("outbound_queue_overflow").to_text(),
+                        ));
+                    }
+                },
+                _ = shutdown.cancelled() => {
+                    self.done = true;
+                    self.reason = CloseReason::Shutdown;
+                    let retry_after_secs = reconnect_after_secs(self.connection.id);
+                    let frame = RestartingFrame {
+                        kind: "server_restarting",
+                        retry_after_secs,
+                    };
+                    return Some(
+                        frame_event(&serde_json::to_string(&frame).expect("restart frame serializes"))
+                            .retry(Duration::from_secs(retry_after_secs)),
+                    );
+                }
+                _ = cancel.cancelled() => {
+                    self.reason = CloseReason::Kicked;
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.connection.cancel.cancel();
+        let ws_state = self.ws_state.clone();
+        let user = self.user.clone();
+        let ip = self.ip.take();
+        let connection = self.connection.clone();
+        let reason = self.reason;
+        // Tracked so shutdown waits for the cleanup writes
+        self.ws_state.shutdown.spawn(
+            async move { unregister(&ws_state, &user, ip, &connection, reason).await }
+                .instrument(self.span.clone()),
+        );
+    }
+}
+
+/// SSE can't carry carriage returns; line breaks become extra `data:` lines
+/// that `EventSource` joins back with `\n`.
+fn frame_event(frame: &str) -> Event {
+    Event::default().data(frame.replace("\r\n", "\n").replace('\r', "\n"))
+}
+
+/// A live frame's event data and SSE id, or `None` if the catch-up already
+/// replayed it. Logged frames are wrapped like replayed ones.
+fn live_frame(msg: &Outbound, resumed_through: i64) -> Option<(String, Option<i64>)> {
+    match msg.seq {
+        Some(seq) if seq <= resumed_through => None,
+        Some(seq) => Some((EventFrame::new(seq, &msg.frame).to_text(), Some(seq))),
+        None => Some((msg.frame.clone(), None)),
+    }
+}
+
+/// Where a stream resumes: a reconnecting `EventSource`'s `Last-Event-ID`,
+/// else `?since=`, else a fresh session.
+fn resume_from(headers: &HeaderMap, since: Option<i64>) -> i64 {
+    last_event_id(headers).or(since).unwrap_or(0)
+}
+
+fn last_event_id(headers: &HeaderMap) -> Option<i64> {
+    headers
+        .get("last-event-id")?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_event_id_header(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("last-event-id", HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn last_event_id_wins_over_since() {
+        let none = HeaderMap::new();
+        assert_eq!(resume_from(&none, None), 0);
+        assert_eq!(resume_from(&none, Some(7)), 7);
+        assert_eq!(resume_from(&last_event_id_header(" 12 "), None), 12);
+        assert_eq!(resume_from(&last_event_id_header("12"), Some(7)), 12);
+        // A header that isn't a sequence number doesn't hide `since`
+        assert_eq!(resume_from(&last_event_id_header("abc"), Some(7)), 7);
+    }
+
+    #[test]
+    fn frames_replayed_in_the_catch_up_are_skipped() {
+        let logged = |seq| Outbound {
+            seq: Some(seq),
+            ..Outbound::from(format!("alice: message {}", seq))
+        };
+        assert_eq!(live_frame(&logged(4), 5), None);
+        assert_eq!(live_frame(&logged(5), 5), None);
+
+        let (frame, id) = live_frame(&logged(6), 5).unwrap();
+        assert_eq!(id, Some(6));
+        assert_eq!(frame, EventFrame::new(6, "alice: message 6").to_text());
+    }
+
+    #[test]
+    fn unlogged_frames_pass_through_without_an_id() {
+        let presence = Outbound::ephemeral("System: User 'bob' is online".to_string());
+        assert_eq!(
+            live_frame(&presence, 5),
+            Some(("System: User 'bob' is online".to_string(), None))
+        );
+    }
+}